@@ -0,0 +1,36 @@
+//! Times a loop that creates a closure on every iteration, which is
+//! dominated by function declarations. Run it with
+//! `cargo bench --bench closures`.
+
+mod common;
+
+use rlox::RunOptions;
+
+const CLOSURES: &str = "
+fun counter(start) {
+  var count = start;
+  fun next(step) {
+    if (step < 0) {
+      print \"negative step\";
+      return count;
+    }
+    var i = 0;
+    while (i < step) {
+      count = count + 1;
+      i = i + 1;
+    }
+    return count;
+  }
+  return next;
+}
+var total = 0;
+for (var i = 0; i < 200000; i = i + 1) {
+  var next = counter(i);
+  total = total + next(1);
+}
+print total;
+";
+
+fn main() {
+  common::report("closures", CLOSURES, RunOptions::default());
+}
@@ -0,0 +1,36 @@
+//! Helpers shared by the benchmarks, each of which only uses some.
+#![allow(dead_code)]
+
+use std::time::{Duration, Instant};
+
+use rlox::{Limits, RunOptions};
+
+/// How many times `report` runs a program.
+const RUNS: u32 = 5;
+
+/// Runs the program, discarding what it prints, and returns how long
+/// it took.
+pub fn run_once(source_code: &str, options: RunOptions) -> Duration {
+  let start = Instant::now();
+  let out = Some(Box::new(std::io::sink()) as Box<dyn std::io::Write>);
+  rlox::run_source_code_with_options(
+    "bench.lox",
+    source_code,
+    Vec::new(),
+    Limits::default(),
+    options,
+    out,
+    None,
+  )
+  .expect("expected the benchmark to run");
+  start.elapsed()
+}
+
+/// Runs the program a few times and prints its best and median time.
+pub fn report(label: &str, source_code: &str, options: RunOptions) {
+  let mut times: Vec<Duration> = (0..RUNS).map(|_| run_once(source_code, options)).collect();
+  times.sort();
+  let best = times[0];
+  let median = times[times.len() / 2];
+  println!("{label}: best {best:.2?}, median {median:.2?} over {RUNS} runs");
+}
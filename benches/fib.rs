@@ -0,0 +1,21 @@
+//! Times a recursive fib(30) on both backends, which is dominated by
+//! variable lookups and calls. Run it with `cargo bench --bench fib`.
+
+mod common;
+
+use rlox::{Backend, RunOptions};
+
+const FIB: &str = "
+fun fib(n) {
+  if (n < 2) { return n; }
+  return fib(n - 1) + fib(n - 2);
+}
+print fib(30);
+";
+
+fn main() {
+  for backend in [Backend::TreeWalker, Backend::Vm] {
+    let options = RunOptions::default().with_backend(backend);
+    common::report(&format!("fib(30) on {backend:?}"), FIB, options);
+  }
+}
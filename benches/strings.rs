@@ -0,0 +1,51 @@
+//! Counts the allocations of a loop that passes strings around, and
+//! times it. Run it with `cargo bench --bench strings`.
+
+mod common;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use rlox::RunOptions;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+  unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    unsafe { System.alloc(layout) }
+  }
+
+  unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe { System.dealloc(ptr, layout) }
+  }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const STRINGS: &str = "
+fun pick(first, second, useFirst) {
+  if (useFirst) { return first; }
+  return second;
+}
+var matches = 0;
+var longest = \"\";
+for (var i = 0; i < 100000; i = i + 1) {
+  var name = pick(\"Grace Hopper\", \"Ada Lovelace\", i < 50000);
+  var copy = name;
+  if (copy == \"Ada Lovelace\") { matches = matches + 1; }
+  if (copy > longest) { longest = copy; }
+}
+print matches;
+print longest;
+";
+
+fn main() {
+  let before = ALLOCATIONS.load(Ordering::Relaxed);
+  let elapsed = common::run_once(STRINGS, RunOptions::default());
+  let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+  println!("strings: {allocations} allocations in {elapsed:.2?}");
+}
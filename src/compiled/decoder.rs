@@ -0,0 +1,347 @@
+use std::rc::Rc;
+
+use super::{IDENTIFIER, KINDS, Link, LoadError, NUMBER, STRING};
+use crate::lexer::{Symbol, Token, TokenKind};
+use crate::parser::{Expr, FunctionDecl, LoxString, Stmt, Value};
+use crate::resolver::Binding;
+use crate::source::{SourceId, SourceMap};
+
+const TRUNCATED: LoadError = LoadError::Corrupt("the file is truncated");
+const UNKNOWN_TAG: LoadError = LoadError::Corrupt("a node has an unknown tag");
+const OUT_OF_SCOPE: LoadError = LoadError::Corrupt("a variable is bound outside of its scopes");
+
+const BINARY: [TokenKind; 10] = [
+  TokenKind::Star,
+  TokenKind::Slash,
+  TokenKind::Plus,
+  TokenKind::Minus,
+  TokenKind::Greater,
+  TokenKind::GreaterEqual,
+  TokenKind::Less,
+  TokenKind::LessEqual,
+  TokenKind::EqualEqual,
+  TokenKind::BangEqual,
+];
+
+/// Reads the payload of a `.loxc` file, giving every variable a fresh
+/// id and collecting their bindings.
+///
+/// The decoder follows the scopes the resolver opened, so that it can
+/// reject a binding to a scope that does not enclose the variable or to
+/// a slot that scope has not declared yet.
+pub struct Decoder<'b> {
+  bytes: &'b [u8],
+  pos: usize,
+  source: SourceId,
+  last_id: usize,
+  bindings: Vec<(usize, Link)>,
+  /// How many variables each scope declared so far, innermost last.
+  scopes: Vec<usize>,
+}
+
+impl<'b> Decoder<'b> {
+  pub fn new(bytes: &'b [u8]) -> Decoder<'b> {
+    Decoder {
+      bytes,
+      pos: 0,
+      source: SourceId::detached("<unknown>"),
+      last_id: 0,
+      bindings: Vec::new(),
+      scopes: Vec::new(),
+    }
+  }
+
+  /// Returns the bindings of the variables read, once every byte is.
+  pub fn finish(self) -> Result<Vec<(usize, Link)>, LoadError> {
+    if self.pos != self.bytes.len() {
+      return Err(LoadError::Corrupt("bytes follow the program"));
+    }
+    Ok(self.bindings)
+  }
+
+  fn byte(&mut self) -> Result<u8, LoadError> {
+    let byte = *self.bytes.get(self.pos).ok_or(TRUNCATED)?;
+    self.pos += 1;
+    Ok(byte)
+  }
+
+  fn take(&mut self, len: usize) -> Result<&'b [u8], LoadError> {
+    let end = self.pos.checked_add(len).ok_or(TRUNCATED)?;
+    let bytes = self.bytes.get(self.pos..end).ok_or(TRUNCATED)?;
+    self.pos = end;
+    Ok(bytes)
+  }
+
+  fn varint(&mut self) -> Result<usize, LoadError> {
+    let mut value: usize = 0;
+    let mut shift = 0;
+    loop {
+      let byte = self.byte()?;
+      let bits = usize::from(byte & 0x7f);
+      if shift >= usize::BITS || (bits << shift) >> shift != bits {
+        return Err(LoadError::Corrupt("an integer is too large"));
+      }
+      value |= bits << shift;
+      if byte & 0x80 == 0 {
+        return Ok(value);
+      }
+      shift += 7;
+    }
+  }
+
+  fn number(&mut self) -> Result<f64, LoadError> {
+    let bytes = self.take(8)?;
+    Ok(f64::from_le_bytes(
+      bytes.try_into().expect("expected 8 bytes"),
+    ))
+  }
+
+  pub fn string(&mut self) -> Result<String, LoadError> {
+    let len = self.varint()?;
+    let bytes = self.take(len)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| LoadError::Corrupt("a string is not UTF-8"))
+  }
+
+  fn flag(&mut self) -> Result<bool, LoadError> {
+    match self.byte()? {
+      0 => Ok(false),
+      1 => Ok(true),
+      _ => Err(UNKNOWN_TAG),
+    }
+  }
+
+  fn token(&mut self) -> Result<Token, LoadError> {
+    let line = self.varint()?;
+    let col = self.varint()?;
+    let len = self.varint()?;
+    let kind = match self.byte()? {
+      IDENTIFIER => TokenKind::Identifier(Symbol::intern(&self.string()?)),
+      STRING => TokenKind::String(self.string()?),
+      NUMBER => TokenKind::Number(self.number()?),
+      tag => KINDS.get(usize::from(tag)).cloned().ok_or(UNKNOWN_TAG)?,
+    };
+    Ok(Token::new(self.source.clone(), line, col, len, kind))
+  }
+
+  fn identifier(&mut self) -> Result<Token, LoadError> {
+    let token = self.token()?;
+    match token.kind() {
+      TokenKind::Identifier(_) => Ok(token),
+      _ => Err(LoadError::Corrupt(
+        "a variable is not named by an identifier",
+      )),
+    }
+  }
+
+  /// Reads the token of an operator, which must be one of `kinds`.
+  fn operator(&mut self, kinds: &[TokenKind]) -> Result<Token, LoadError> {
+    let token = self.token()?;
+    match kinds.contains(token.kind()) {
+      true => Ok(token),
+      false => Err(LoadError::Corrupt("an operator has the wrong token")),
+    }
+  }
+
+  /// Reads a variable and its binding, giving it a new id.
+  fn link(&mut self) -> Result<(usize, Token, Link), LoadError> {
+    let token = self.identifier()?;
+    self.last_id += 1;
+    let id = self.last_id;
+    let link = match self.byte()? {
+      0 => {
+        let depth = self.varint()?;
+        let slot = self.varint()?;
+        Link::Resolved(Binding::Local { depth, slot })
+      }
+      1 => Link::Global(token.clone()),
+      _ => return Err(UNKNOWN_TAG),
+    };
+    Ok((id, token, link))
+  }
+
+  /// Reads a use of a variable, which must be bound to a slot one of
+  /// the enclosing scopes declared already, and returns its new id.
+  fn variable(&mut self) -> Result<(usize, Token), LoadError> {
+    let (id, token, link) = self.link()?;
+    if let Link::Resolved(Binding::Local { depth, slot }) = link {
+      let declared = depth
+        .checked_add(1)
+        .and_then(|depth| self.scopes.len().checked_sub(depth))
+        .map(|scope| self.scopes[scope]);
+      if declared.is_none_or(|declared| slot >= declared) {
+        return Err(OUT_OF_SCOPE);
+      }
+    }
+    self.bindings.push((id, link));
+    Ok((id, token))
+  }
+
+  /// Reads the declaration of a variable, which must be bound to the
+  /// next slot of the innermost scope. The slot is only taken by
+  /// `declare`, so that an initializer cannot read it.
+  fn declaration(&mut self) -> Result<(usize, Token), LoadError> {
+    let (id, token, link) = self.link()?;
+    match link {
+      Link::Resolved(Binding::Local { depth: 0, slot }) if self.scopes.last() == Some(&slot) => {
+        self.bindings.push((id, link));
+        Ok((id, token))
+      }
+      _ => Err(OUT_OF_SCOPE),
+    }
+  }
+
+  fn declare(&mut self) {
+    *self
+      .scopes
+      .last_mut()
+      .expect("expected that we are inside of at least one scope") += 1;
+  }
+
+  /// Reads `body` inside a new scope whose first `declared` slots are
+  /// taken already, like the parameters of a function.
+  fn scope<T>(
+    &mut self,
+    declared: usize,
+    body: impl FnOnce(&mut Self) -> Result<T, LoadError>,
+  ) -> Result<T, LoadError> {
+    self.scopes.push(declared);
+    let result = body(self);
+    self.scopes.pop();
+    result
+  }
+
+  fn value(&mut self) -> Result<Value, LoadError> {
+    Ok(match self.byte()? {
+      0 => Value::Number(self.number()?),
+      1 => Value::Str(LoxString::from(self.string()?.as_str())),
+      2 => Value::Bool(self.flag()?),
+      3 => Value::Nil,
+      _ => return Err(UNKNOWN_TAG),
+    })
+  }
+
+  /// Reads the statements of the program, whose tokens point into the
+  /// source code named `name`.
+  pub fn stmts(&mut self, name: &str, source_code: &str) -> Result<Vec<Stmt>, LoadError> {
+    self.source = SourceMap::new().add(name, source_code);
+    self.block()
+  }
+
+  fn block(&mut self) -> Result<Vec<Stmt>, LoadError> {
+    self.scope(0, |decoder| decoder.list(Decoder::stmt))
+  }
+
+  /// Reads a length, then that many items.
+  fn list<T>(
+    &mut self,
+    mut item: impl FnMut(&mut Self) -> Result<T, LoadError>,
+  ) -> Result<Vec<T>, LoadError> {
+    let len = self.varint()?;
+    // Every item takes at least one byte, which bounds a corrupt length.
+    let mut items = Vec::with_capacity(len.min(self.bytes.len() - self.pos));
+    for _ in 0..len {
+      items.push(item(self)?);
+    }
+    Ok(items)
+  }
+
+  fn stmt(&mut self) -> Result<Stmt, LoadError> {
+    Ok(match self.byte()? {
+      0 => Stmt::ExprStmt {
+        expr: Box::new(self.expr()?),
+      },
+      1 => Stmt::PrintStmt {
+        keyword: self.token()?,
+        expr: Box::new(self.expr()?),
+      },
+      2 => {
+        let (id, variable) = self.declaration()?;
+        let expr = match self.flag()? {
+          true => Some(Box::new(self.expr()?)),
+          false => None,
+        };
+        self.declare();
+        Stmt::VarDecl { id, variable, expr }
+      }
+      3 => Stmt::Block {
+        stmts: self.block()?,
+      },
+      4 => {
+        let condition = Box::new(self.expr()?);
+        let then_stmt = Box::new(self.stmt()?);
+        let else_stmt = match self.flag()? {
+          true => Some(Box::new(self.stmt()?)),
+          false => None,
+        };
+        Stmt::If {
+          condition,
+          then_stmt,
+          else_stmt,
+        }
+      }
+      5 => Stmt::While {
+        condition: Box::new(self.expr()?),
+        body: Box::new(self.stmt()?),
+      },
+      6 => {
+        let (id, name) = self.declaration()?;
+        self.declare();
+        let params = self.list(Decoder::identifier)?;
+        let body = self.scope(params.len(), Decoder::stmt)?;
+        Stmt::FunDecl {
+          id,
+          decl: Rc::new(FunctionDecl { name, params, body }),
+        }
+      }
+      7 => Stmt::Return {
+        keyword: self.token()?,
+        expr: Box::new(self.expr()?),
+      },
+      _ => return Err(UNKNOWN_TAG),
+    })
+  }
+
+  fn expr(&mut self) -> Result<Expr, LoadError> {
+    Ok(match self.byte()? {
+      0 => Expr::Unary {
+        op: self.operator(&[TokenKind::Minus, TokenKind::Bang])?,
+        right: Box::new(self.expr()?),
+      },
+      1 => Expr::Binary {
+        left: Box::new(self.expr()?),
+        op: self.operator(&BINARY)?,
+        right: Box::new(self.expr()?),
+      },
+      2 => Expr::Grouping(Box::new(self.expr()?)),
+      3 => Expr::Literal(self.value()?),
+      4 => {
+        let (id, variable) = self.variable()?;
+        Expr::Variable { id, variable }
+      }
+      5 => {
+        let (id, variable) = self.variable()?;
+        Expr::Assignment {
+          id,
+          variable,
+          expr: Box::new(self.expr()?),
+        }
+      }
+      6 => Expr::Logical {
+        left: Box::new(self.expr()?),
+        op: self.operator(&[TokenKind::And, TokenKind::Or])?,
+        right: Box::new(self.expr()?),
+      },
+      7 => {
+        let callee = Box::new(self.expr()?);
+        let paren = self.token()?;
+        let args = self.list(|decoder| decoder.expr().map(Box::new))?;
+        Expr::FunCall {
+          callee,
+          paren,
+          args,
+        }
+      }
+      _ => return Err(UNKNOWN_TAG),
+    })
+  }
+}
@@ -0,0 +1,227 @@
+use super::{IDENTIFIER, KINDS, NUMBER, STRING};
+use crate::lexer::{Token, TokenKind};
+use crate::parser::{Expr, Stmt, Value};
+use crate::resolver::{Binding, Resolver};
+
+/// Writes the payload of a `.loxc` file.
+pub struct Encoder<'r> {
+  resolver: &'r Resolver,
+  bytes: Vec<u8>,
+}
+
+impl<'r> Encoder<'r> {
+  pub fn new(resolver: &'r Resolver) -> Encoder<'r> {
+    Encoder {
+      resolver,
+      bytes: Vec::new(),
+    }
+  }
+
+  pub fn finish(self) -> Vec<u8> {
+    self.bytes
+  }
+
+  fn byte(&mut self, byte: u8) {
+    self.bytes.push(byte);
+  }
+
+  fn varint(&mut self, mut value: usize) {
+    while value >= 0x80 {
+      self.byte((value as u8) | 0x80);
+      value >>= 7;
+    }
+    self.byte(value as u8);
+  }
+
+  fn number(&mut self, n: f64) {
+    self.bytes.extend_from_slice(&n.to_le_bytes());
+  }
+
+  pub fn str(&mut self, s: &str) {
+    self.varint(s.len());
+    self.bytes.extend_from_slice(s.as_bytes());
+  }
+
+  fn token(&mut self, token: &Token) {
+    self.varint(token.line());
+    self.varint(token.col());
+    self.varint(token.len());
+    match token.kind() {
+      TokenKind::Identifier(name) => {
+        self.byte(IDENTIFIER);
+        self.str(name.as_str());
+      }
+      TokenKind::String(s) => {
+        self.byte(STRING);
+        self.str(s);
+      }
+      TokenKind::Number(n) => {
+        self.byte(NUMBER);
+        self.number(*n);
+      }
+      kind => {
+        let tag = KINDS
+          .iter()
+          .position(|known| known == kind)
+          .expect("expected every token kind without a payload to be numbered");
+        self.byte(tag as u8);
+      }
+    }
+  }
+
+  /// Writes the variable of `id` named by `token`, then its binding.
+  fn variable(&mut self, id: usize, token: &Token) {
+    self.token(token);
+    match self.resolver.binding(id) {
+      Binding::Local { depth, slot } => {
+        self.byte(0);
+        self.varint(depth);
+        self.varint(slot);
+      }
+      // Bound again by name when the program is loaded.
+      Binding::Global(_) => self.byte(1),
+    }
+  }
+
+  fn value(&mut self, value: &Value) {
+    match value {
+      Value::Number(n) => {
+        self.byte(0);
+        self.number(*n);
+      }
+      Value::Str(s) => {
+        self.byte(1);
+        self.str(s.as_str());
+      }
+      Value::Bool(b) => {
+        self.byte(2);
+        self.byte(u8::from(*b));
+      }
+      Value::Nil => self.byte(3),
+      value => panic!("expected a literal, got a {}", value.type_name()),
+    }
+  }
+
+  pub fn stmts(&mut self, stmts: &[Stmt]) {
+    self.varint(stmts.len());
+    for stmt in stmts {
+      self.stmt(stmt);
+    }
+  }
+
+  fn stmt(&mut self, stmt: &Stmt) {
+    match stmt {
+      Stmt::ExprStmt { expr } => {
+        self.byte(0);
+        self.expr(expr);
+      }
+      Stmt::PrintStmt { keyword, expr } => {
+        self.byte(1);
+        self.token(keyword);
+        self.expr(expr);
+      }
+      Stmt::VarDecl { id, variable, expr } => {
+        self.byte(2);
+        self.variable(*id, variable);
+        match expr {
+          Some(expr) => {
+            self.byte(1);
+            self.expr(expr);
+          }
+          None => self.byte(0),
+        }
+      }
+      Stmt::Block { stmts } => {
+        self.byte(3);
+        self.stmts(stmts);
+      }
+      Stmt::If {
+        condition,
+        then_stmt,
+        else_stmt,
+      } => {
+        self.byte(4);
+        self.expr(condition);
+        self.stmt(then_stmt);
+        match else_stmt {
+          Some(else_stmt) => {
+            self.byte(1);
+            self.stmt(else_stmt);
+          }
+          None => self.byte(0),
+        }
+      }
+      Stmt::While { condition, body } => {
+        self.byte(5);
+        self.expr(condition);
+        self.stmt(body);
+      }
+      Stmt::FunDecl { id, decl } => {
+        self.byte(6);
+        self.variable(*id, &decl.name);
+        self.varint(decl.params.len());
+        for param in &decl.params {
+          self.token(param);
+        }
+        self.stmt(&decl.body);
+      }
+      Stmt::Return { keyword, expr } => {
+        self.byte(7);
+        self.token(keyword);
+        self.expr(expr);
+      }
+    }
+  }
+
+  fn expr(&mut self, expr: &Expr) {
+    match expr {
+      Expr::Unary { op, right } => {
+        self.byte(0);
+        self.token(op);
+        self.expr(right);
+      }
+      Expr::Binary { left, op, right } => {
+        self.byte(1);
+        self.expr(left);
+        self.token(op);
+        self.expr(right);
+      }
+      Expr::Grouping(expr) => {
+        self.byte(2);
+        self.expr(expr);
+      }
+      Expr::Literal(value) => {
+        self.byte(3);
+        self.value(value);
+      }
+      Expr::Variable { id, variable } => {
+        self.byte(4);
+        self.variable(*id, variable);
+      }
+      Expr::Assignment { id, variable, expr } => {
+        self.byte(5);
+        self.variable(*id, variable);
+        self.expr(expr);
+      }
+      Expr::Logical { left, op, right } => {
+        self.byte(6);
+        self.expr(left);
+        self.token(op);
+        self.expr(right);
+      }
+      Expr::FunCall {
+        callee,
+        paren,
+        args,
+      } => {
+        self.byte(7);
+        self.expr(callee);
+        self.token(paren);
+        self.varint(args.len());
+        for arg in args {
+          self.expr(arg);
+        }
+      }
+    }
+  }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+
+/// An error found while reading a compiled program, before any of it
+/// runs.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum LoadError {
+  /// The file does not start with the magic bytes of `.loxc` files.
+  NotCompiled,
+  /// The file was written in another version of the format.
+  UnsupportedVersion(u16),
+  /// The file is truncated or its bytes were altered.
+  Corrupt(&'static str),
+}
+
+impl LoadError {
+  pub fn code(&self) -> &'static str {
+    match self {
+      LoadError::NotCompiled => "E0501",
+      LoadError::UnsupportedVersion(_) => "E0502",
+      LoadError::Corrupt(_) => "E0503",
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    let diagnostic = Diagnostic::new(self.code(), self.to_string());
+    match self {
+      LoadError::NotCompiled => diagnostic.with_help("compile the script with `rlox compile`"),
+      LoadError::UnsupportedVersion(_) => diagnostic
+        .with_note(format!("this rlox reads version {}", super::VERSION))
+        .with_help("compile the script again with this rlox"),
+      LoadError::Corrupt(_) => diagnostic.with_help("compile the script again"),
+    }
+  }
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      LoadError::NotCompiled => write!(f, "not a compiled Lox program"),
+      LoadError::UnsupportedVersion(version) => {
+        write!(f, "compiled program has unsupported version {version}")
+      }
+      LoadError::Corrupt(reason) => write!(f, "compiled program is corrupt: {reason}"),
+    }
+  }
+}
+
+impl std::error::Error for LoadError {}
@@ -0,0 +1,313 @@
+use crate::lexer::{Token, TokenKind};
+use crate::parser::Stmt;
+use crate::resolver::{Binding, ResolveError, Resolver};
+
+mod decoder;
+mod encoder;
+mod errors;
+
+pub use errors::*;
+
+use decoder::Decoder;
+use encoder::Encoder;
+
+/// The bytes every `.loxc` file starts with.
+const MAGIC: &[u8; 4] = b"LOXC";
+
+/// The version of the format, bumped whenever the layout of the file or
+/// the meaning of its bindings changes.
+const VERSION: u16 = 2;
+
+/// The token kinds without a payload, numbered by their position.
+/// Identifiers, strings and numbers come after them.
+const KINDS: [TokenKind; 36] = [
+  TokenKind::LeftParen,
+  TokenKind::RightParen,
+  TokenKind::LeftBrace,
+  TokenKind::RightBrace,
+  TokenKind::Comma,
+  TokenKind::Dot,
+  TokenKind::Minus,
+  TokenKind::Plus,
+  TokenKind::Semicolon,
+  TokenKind::Slash,
+  TokenKind::Star,
+  TokenKind::Bang,
+  TokenKind::BangEqual,
+  TokenKind::Equal,
+  TokenKind::EqualEqual,
+  TokenKind::Greater,
+  TokenKind::GreaterEqual,
+  TokenKind::Less,
+  TokenKind::LessEqual,
+  TokenKind::And,
+  TokenKind::Class,
+  TokenKind::Else,
+  TokenKind::False,
+  TokenKind::Fun,
+  TokenKind::For,
+  TokenKind::If,
+  TokenKind::Nil,
+  TokenKind::Or,
+  TokenKind::Print,
+  TokenKind::Return,
+  TokenKind::Super,
+  TokenKind::This,
+  TokenKind::True,
+  TokenKind::Var,
+  TokenKind::While,
+  TokenKind::Eof,
+];
+const IDENTIFIER: u8 = KINDS.len() as u8;
+const STRING: u8 = IDENTIFIER + 1;
+const NUMBER: u8 = IDENTIFIER + 2;
+
+/// A program that was lexed, parsed and resolved ahead of time, as
+/// stored in a `.loxc` file.
+///
+/// The file holds the syntax tree with the binding of every variable
+/// and the position of every token, along with the source code, so that
+/// errors point at the same lines as when running the script itself.
+/// Globals, which are the natives of the interpreter, are bound by name
+/// when the program is loaded. Every other binding is kept as it was
+/// compiled, once loading checked that it points into a scope around
+/// the variable and at a slot that scope declared before it.
+///
+/// The layout is the magic bytes `LOXC`, the version of the format as a
+/// little-endian `u16`, a 64-bit FNV-1a checksum of the rest of the
+/// file, then the name of the source, the source code and the
+/// statements. Integers are LEB128 varints, strings are their length
+/// followed by their UTF-8 bytes, and every node starts with a tag.
+pub struct CompiledProgram {
+  name: String,
+  source_code: String,
+  stmts: Vec<Stmt>,
+  /// The bindings of the variables by their id, with the globals left
+  /// to bind by the name of their token.
+  bindings: Vec<(usize, Link)>,
+}
+
+enum Link {
+  Resolved(Binding),
+  Global(Token),
+}
+
+impl CompiledProgram {
+  /// Encodes statements resolved by `resolver`, along with the source
+  /// code they were parsed from.
+  pub fn encode(name: &str, source_code: &str, stmts: &[Stmt], resolver: &Resolver) -> Vec<u8> {
+    let mut payload = Encoder::new(resolver);
+    payload.str(name);
+    payload.str(source_code);
+    payload.stmts(stmts);
+    let payload = payload.finish();
+
+    let mut bytes = Vec::with_capacity(MAGIC.len() + 10 + payload.len());
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&checksum(&payload).to_le_bytes());
+    bytes.extend_from_slice(&payload);
+    bytes
+  }
+
+  /// Reads a program written by `encode`.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error unless the bytes are a `.loxc` file of this
+  /// version of the format, intact.
+  pub fn decode(bytes: &[u8]) -> Result<CompiledProgram, LoadError> {
+    let rest = bytes.strip_prefix(MAGIC).ok_or(LoadError::NotCompiled)?;
+    let (version, rest) = rest
+      .split_first_chunk::<2>()
+      .ok_or(LoadError::Corrupt("the header is truncated"))?;
+    let version = u16::from_le_bytes(*version);
+    if version != VERSION {
+      return Err(LoadError::UnsupportedVersion(version));
+    }
+    let (expected, payload) = rest
+      .split_first_chunk::<8>()
+      .ok_or(LoadError::Corrupt("the header is truncated"))?;
+    if checksum(payload) != u64::from_le_bytes(*expected) {
+      return Err(LoadError::Corrupt("the checksum does not match"));
+    }
+
+    let mut decoder = Decoder::new(payload);
+    let name = decoder.string()?;
+    let source_code = decoder.string()?;
+    let stmts = decoder.stmts(&name, &source_code)?;
+    let bindings = decoder.finish()?;
+    Ok(CompiledProgram {
+      name,
+      source_code,
+      stmts,
+      bindings,
+    })
+  }
+
+  /// The name of the script the program was compiled from.
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn source_code(&self) -> &str {
+    &self.source_code
+  }
+
+  /// Gives the bindings of the program to `resolver`, binding its
+  /// globals to the ones the resolver declared, and returns the
+  /// statements ready to run. The program is not resolved again.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if the program uses a global the resolver does
+  /// not declare or grant.
+  pub(crate) fn link(self, resolver: &mut Resolver) -> Result<Vec<Stmt>, ResolveError> {
+    for (id, link) in self.bindings {
+      match link {
+        Link::Resolved(binding) => resolver.bind(id, binding),
+        Link::Global(token) => resolver.bind_global(id, &token)?,
+      }
+    }
+    Ok(self.stmts)
+  }
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+    (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::source::SourceId;
+
+  fn compile(source_code: &str) -> Vec<u8> {
+    compile_altered(source_code, |_| {})
+  }
+
+  /// Compiles the source code with the bindings `alter` gives it.
+  fn compile_altered(source_code: &str, alter: impl FnOnce(&mut Resolver)) -> Vec<u8> {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    let mut resolver = Resolver::new();
+    resolver.declare_global("clock", None);
+    resolver.resolve(&stmts);
+    assert!(resolver.errors().is_empty());
+    alter(&mut resolver);
+    CompiledProgram::encode("main.lox", source_code, &stmts, &resolver)
+  }
+
+  #[test]
+  fn decodes_what_it_encodes() {
+    let source_code = "
+      fun f(a, b) { var c = a * -b; if (c > 1 or !true) return c; return clock(); }
+      var s = \"str\";
+      while (s == nil) { s = nil; }
+      print f(1, 2.5) + s;
+    ";
+    let bytes = compile(source_code);
+    let program = CompiledProgram::decode(&bytes).unwrap();
+    assert_eq!(program.name(), "main.lox");
+    assert_eq!(program.source_code(), source_code);
+
+    let mut resolver = Resolver::new();
+    resolver.declare_global("unused", None);
+    resolver.declare_global("clock", None);
+    let stmts = program.link(&mut resolver).unwrap();
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let parsed = Parser::new(tokens).parse();
+    let printed: Vec<String> = stmts.iter().map(ToString::to_string).collect();
+    let expected: Vec<String> = parsed.iter().map(ToString::to_string).collect();
+    assert_eq!(printed, expected);
+
+    let program = CompiledProgram::decode(&bytes).unwrap();
+    let err = program.link(&mut Resolver::new()).err();
+    assert!(matches!(err, Some(ResolveError::UndeclaredVariable(_))));
+  }
+
+  #[test]
+  fn links_the_bindings_it_was_compiled_with() {
+    // Ids are handed out as the parser finishes each use or declaration,
+    // so 1 and 2 declare `a` and `b` and 3 reads `a`.
+    let source_code = "var a = 1; var b = 2; print a + b;";
+    let swapped = Binding::Local { depth: 0, slot: 1 };
+    let bytes = compile_altered(source_code, |resolver| resolver.bind(3, swapped));
+    let program = CompiledProgram::decode(&bytes).unwrap();
+    let mut resolver = Resolver::new();
+    program.link(&mut resolver).unwrap();
+    // Resolving the program again would have bound `a` to its own slot.
+    assert_eq!(resolver.binding(3), swapped);
+    assert!(resolver.errors().is_empty());
+  }
+
+  #[test]
+  fn rejects_bindings_outside_of_the_scopes() {
+    let out_of_scope = Some(LoadError::Corrupt(
+      "a variable is bound outside of its scopes",
+    ));
+    // 1 declares `a`, 2 reads `x`, 3 reads `a` from inside `f`, 4
+    // declares `f`, 5 reads `f` and 6 reads `a`.
+    let source_code = "var a = 1; fun f(x) { return x + a; } print f(a);";
+    let altered = [
+      (1, Binding::Local { depth: 0, slot: 1 }),
+      (2, Binding::Local { depth: 1, slot: 1 }),
+      (3, Binding::Local { depth: 3, slot: 0 }),
+      (
+        3,
+        Binding::Local {
+          depth: usize::MAX,
+          slot: 0,
+        },
+      ),
+      (4, Binding::Local { depth: 1, slot: 1 }),
+      (6, Binding::Local { depth: 0, slot: 2 }),
+    ];
+    for (id, binding) in altered {
+      let bytes = compile_altered(source_code, |resolver| resolver.bind(id, binding));
+      assert_eq!(CompiledProgram::decode(&bytes).err(), out_of_scope);
+    }
+
+    // An initializer cannot read the variable it declares: 2 reads `a`
+    // before 3 declares `b`.
+    let bytes = compile_altered("var a = 1; { var b = a; print b; }", |resolver| {
+      resolver.bind(2, Binding::Local { depth: 0, slot: 0 });
+    });
+    assert_eq!(CompiledProgram::decode(&bytes).err(), out_of_scope);
+  }
+
+  #[test]
+  fn rejects_altered_files() {
+    let bytes = compile("print 1;");
+    assert_eq!(
+      CompiledProgram::decode(b"print 1;").err(),
+      Some(LoadError::NotCompiled)
+    );
+
+    let mut newer = bytes.clone();
+    newer[4] = 9;
+    assert_eq!(
+      CompiledProgram::decode(&newer).err(),
+      Some(LoadError::UnsupportedVersion(9))
+    );
+
+    let mut flipped = bytes.clone();
+    *flipped.last_mut().unwrap() ^= 1;
+    assert_eq!(
+      CompiledProgram::decode(&flipped).err(),
+      Some(LoadError::Corrupt("the checksum does not match"))
+    );
+
+    for len in 0..bytes.len() {
+      assert!(CompiledProgram::decode(&bytes[..len]).is_err());
+    }
+  }
+}
@@ -0,0 +1,235 @@
+use std::fmt::Write;
+
+use crate::source::SourceId;
+
+mod json;
+
+/// A range of chars on one line of the source code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+  source: SourceId,
+  line: usize,
+  col: usize,
+  len: usize,
+}
+
+impl Span {
+  pub fn new(source: SourceId, line: usize, col: usize, len: usize) -> Span {
+    Span {
+      source,
+      line,
+      col,
+      len,
+    }
+  }
+
+  pub fn source_id(&self) -> &SourceId {
+    &self.source
+  }
+
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn col(&self) -> usize {
+    self.col
+  }
+
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// The column right after the last char of the span.
+  pub fn end_col(&self) -> usize {
+    self.col + self.len
+  }
+}
+
+/// A human readable report of an error, with a stable error code,
+/// the location it points at and optional notes and help.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+  code: &'static str,
+  message: String,
+  span: Option<Span>,
+  label: Option<String>,
+  related: Vec<(Span, String)>,
+  notes: Vec<String>,
+  help: Option<String>,
+}
+
+impl Diagnostic {
+  pub fn new(code: &'static str, message: impl Into<String>) -> Diagnostic {
+    Diagnostic {
+      code,
+      message: message.into(),
+      span: None,
+      label: None,
+      related: Vec::new(),
+      notes: Vec::new(),
+      help: None,
+    }
+  }
+
+  pub fn with_span(mut self, span: Span, label: impl Into<String>) -> Diagnostic {
+    self.span = Some(span);
+    self.label = Some(label.into());
+    self
+  }
+
+  /// Points at another location that helps explaining the error,
+  /// such as a previous declaration.
+  pub fn with_related(mut self, span: Span, label: impl Into<String>) -> Diagnostic {
+    self.related.push((span, label.into()));
+    self
+  }
+
+  pub fn with_note(mut self, note: impl Into<String>) -> Diagnostic {
+    self.notes.push(note.into());
+    self
+  }
+
+  pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+    self.help = Some(help.into());
+    self
+  }
+
+  pub fn code(&self) -> &'static str {
+    self.code
+  }
+
+  pub fn message(&self) -> &str {
+    &self.message
+  }
+
+  pub fn span(&self) -> Option<&Span> {
+    self.span.as_ref()
+  }
+
+  pub fn label(&self) -> Option<&str> {
+    self.label.as_deref()
+  }
+
+  pub fn related(&self) -> &[(Span, String)] {
+    &self.related
+  }
+
+  pub fn notes(&self) -> &[String] {
+    &self.notes
+  }
+
+  pub fn help(&self) -> Option<&str> {
+    self.help.as_deref()
+  }
+
+  /// Renders the diagnostic with the offending line of `source_code`
+  /// underlined by carets:
+  ///
+  /// ```text
+  /// error[E0402]: operand must be a number
+  ///  --> main.lox:1:7
+  ///   |
+  /// 1 | print -"a";
+  ///   |       ^ expected a number
+  /// ```
+  pub fn render(&self, source_code: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "error[{}]: {}", self.code, self.message).unwrap();
+
+    let snippet = self
+      .span
+      .as_ref()
+      .and_then(|span| Some((span, source_code.lines().nth(span.line.checked_sub(1)?)?)));
+    let gutter = match snippet {
+      Some((span, _)) => span.line.to_string().len(),
+      None => 0,
+    };
+    let pad = " ".repeat(gutter);
+
+    if let Some((span, line)) = snippet {
+      writeln!(out, "{pad}--> {}:{}:{}", span.source, span.line, span.col).unwrap();
+      writeln!(out, "{pad} |").unwrap();
+      writeln!(out, "{} | {}", span.line, line).unwrap();
+
+      // Keep tabs so that the carets line up with the source line.
+      let indent: String = line
+        .chars()
+        .take(span.col.saturating_sub(1))
+        .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+        .collect();
+      let available = line.chars().count().saturating_sub(indent.chars().count());
+      let carets = "^".repeat(span.len.min(available).max(1));
+      write!(out, "{pad} | {indent}{carets}").unwrap();
+      match &self.label {
+        Some(label) => writeln!(out, " {label}").unwrap(),
+        None => writeln!(out).unwrap(),
+      }
+    }
+
+    if !self.related.is_empty() || !self.notes.is_empty() || self.help.is_some() {
+      writeln!(out, "{pad} |").unwrap();
+    }
+    for (span, label) in &self.related {
+      writeln!(
+        out,
+        "{pad} = note: {label} at {}:{}:{}",
+        span.source, span.line, span.col
+      )
+      .unwrap();
+    }
+    for note in &self.notes {
+      writeln!(out, "{pad} = note: {note}").unwrap();
+    }
+    if let Some(help) = &self.help {
+      writeln!(out, "{pad} = help: {help}").unwrap();
+    }
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn span(line: usize, col: usize, len: usize) -> Span {
+    Span::new(SourceId::detached("main.lox"), line, col, len)
+  }
+
+  #[test]
+  fn renders_carets_under_span() {
+    let diagnostic = Diagnostic::new("E0402", "operand must be a number")
+      .with_span(span(2, 7, 1), "expected a number")
+      .with_help("negate a number instead");
+    let source_code = "var a = 1;\nprint -\"a\";\n";
+    assert_eq!(
+      diagnostic.render(source_code),
+      "error[E0402]: operand must be a number\n \
+       --> main.lox:2:7\n  \
+       |\n\
+       2 | print -\"a\";\n  \
+       |       ^ expected a number\n  \
+       |\n  \
+       = help: negate a number instead\n"
+    );
+  }
+
+  #[test]
+  fn keeps_tabs_and_clamps_to_line() {
+    let diagnostic = Diagnostic::new("E0101", "oops").with_span(span(1, 3, 10), "here");
+    let rendered = diagnostic.render("\tx yz");
+    assert!(rendered.ends_with("1 | \tx yz\n  | \t ^^^ here\n"));
+  }
+
+  #[test]
+  fn renders_without_span() {
+    let diagnostic = Diagnostic::new("E0405", "expected a number").with_note("in a native");
+    assert_eq!(
+      diagnostic.render(""),
+      "error[E0405]: expected a number\n |\n = note: in a native\n"
+    );
+  }
+}
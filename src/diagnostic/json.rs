@@ -0,0 +1,144 @@
+use std::fmt::Write;
+
+use super::{Diagnostic, Span};
+
+impl Diagnostic {
+  /// Serialises the diagnostic as a single line of JSON:
+  ///
+  /// ```text
+  /// {"code":"E0402","severity":"error","message":"operand must be a number",
+  ///  "file":"main.lox","start_line":1,"start_col":7,"end_line":1,"end_col":8,
+  ///  "label":"expected a number","related":[],"notes":[],"help":null}
+  /// ```
+  ///
+  /// Lines and columns start at 1 and `end_col` is exclusive. Location
+  /// fields are `null` for diagnostics that point nowhere.
+  pub fn to_json(&self) -> String {
+    let mut out = String::new();
+    write!(out, "{{\"code\":").unwrap();
+    write_string(&mut out, self.code);
+    write!(out, ",\"severity\":\"error\",\"message\":").unwrap();
+    write_string(&mut out, &self.message);
+    out.push(',');
+    write_location(&mut out, self.span.as_ref());
+    write!(out, ",\"label\":").unwrap();
+    write_optional_string(&mut out, self.label.as_deref());
+
+    write!(out, ",\"related\":[").unwrap();
+    for (i, (span, label)) in self.related.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      out.push('{');
+      write_location(&mut out, Some(span));
+      write!(out, ",\"message\":").unwrap();
+      write_string(&mut out, label);
+      out.push('}');
+    }
+
+    write!(out, "],\"notes\":[").unwrap();
+    for (i, note) in self.notes.iter().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      write_string(&mut out, note);
+    }
+    write!(out, "],\"help\":").unwrap();
+    write_optional_string(&mut out, self.help.as_deref());
+    out.push('}');
+    out
+  }
+}
+
+fn write_location(out: &mut String, span: Option<&Span>) {
+  match span {
+    Some(span) => {
+      write!(out, "\"file\":").unwrap();
+      write_string(out, span.source.name());
+      write!(
+        out,
+        ",\"start_line\":{},\"start_col\":{},\"end_line\":{},\"end_col\":{}",
+        span.line,
+        span.col,
+        span.line,
+        span.end_col()
+      )
+      .unwrap();
+    }
+    None => write!(
+      out,
+      "\"file\":null,\"start_line\":null,\"start_col\":null,\"end_line\":null,\"end_col\":null"
+    )
+    .unwrap(),
+  }
+}
+
+fn write_optional_string(out: &mut String, string: Option<&str>) {
+  match string {
+    Some(string) => write_string(out, string),
+    None => out.push_str("null"),
+  }
+}
+
+fn write_string(out: &mut String, string: &str) {
+  out.push('"');
+  for ch in string.chars() {
+    match ch {
+      '"' => out.push_str("\\\""),
+      '\\' => out.push_str("\\\\"),
+      '\n' => out.push_str("\\n"),
+      '\r' => out.push_str("\\r"),
+      '\t' => out.push_str("\\t"),
+      ch if ch.is_control() => write!(out, "\\u{:04x}", ch as u32).unwrap(),
+      ch => out.push(ch),
+    }
+  }
+  out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::source::SourceId;
+
+  fn span(line: usize, col: usize, len: usize) -> Span {
+    Span::new(SourceId::detached("dir/main.lox"), line, col, len)
+  }
+
+  #[test]
+  fn serialises_every_field() {
+    let diagnostic = Diagnostic::new("E0304", "variable 'a' is already declared")
+      .with_span(span(2, 5, 1), "declared again here")
+      .with_related(span(1, 5, 1), "previously declared here")
+      .with_note("a note")
+      .with_help("rename it");
+    assert_eq!(
+      diagnostic.to_json(),
+      "{\"code\":\"E0304\",\"severity\":\"error\",\
+       \"message\":\"variable 'a' is already declared\",\
+       \"file\":\"dir/main.lox\",\"start_line\":2,\"start_col\":5,\"end_line\":2,\"end_col\":6,\
+       \"label\":\"declared again here\",\
+       \"related\":[{\"file\":\"dir/main.lox\",\"start_line\":1,\"start_col\":5,\
+       \"end_line\":1,\"end_col\":6,\"message\":\"previously declared here\"}],\
+       \"notes\":[\"a note\"],\"help\":\"rename it\"}"
+    );
+  }
+
+  #[test]
+  fn serialises_missing_location_as_null() {
+    let diagnostic = Diagnostic::new("E0001", "no such file");
+    assert_eq!(
+      diagnostic.to_json(),
+      "{\"code\":\"E0001\",\"severity\":\"error\",\"message\":\"no such file\",\
+       \"file\":null,\"start_line\":null,\"start_col\":null,\"end_line\":null,\"end_col\":null,\
+       \"label\":null,\"related\":[],\"notes\":[],\"help\":null}"
+    );
+  }
+
+  #[test]
+  fn escapes_strings() {
+    let mut out = String::new();
+    write_string(&mut out, "a \"b\"\\\n\u{1}é");
+    assert_eq!(out, "\"a \\\"b\\\"\\\\\\n\\u0001é\"");
+  }
+}
@@ -2,26 +2,88 @@ use std::error::Error;
 use std::fmt;
 
 use crate::{
-  interpreter::RuntimeError, lexer::LexerError, parser::ParseError, resolver::ResolveError,
+  compiled::LoadError, diagnostic::Diagnostic, interpreter::RuntimeError, lexer::LexerError,
+  parser::ParseError, resolver::ResolveError,
 };
 
+/// Any error raised while reading, checking or running a program.
+/// Displays which step failed; the wrapped error, with the details, is
+/// available through `Error::source`.
 #[derive(Debug)]
 pub enum LoxError {
   IoError(Box<dyn Error>),
+  LoadError(LoadError),
   LexerError(LexerError),
   ParseError(ParseError),
   ResolveError(ResolveError),
   RuntimeError(RuntimeError),
 }
 
+impl LoxError {
+  /// The stable code identifying the kind of error, e.g. `E0303`.
+  pub fn code(&self) -> &'static str {
+    match self {
+      Self::IoError(_) => "E0001",
+      Self::LoadError(err) => err.code(),
+      Self::LexerError(err) => err.code(),
+      Self::ParseError(err) => err.code(),
+      Self::ResolveError(err) => err.code(),
+      Self::RuntimeError(err) => err.code(),
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    match self {
+      Self::IoError(err) => Diagnostic::new(self.code(), err.to_string()),
+      Self::LoadError(err) => err.diagnostic(),
+      Self::LexerError(err) => err.diagnostic(),
+      Self::ParseError(err) => err.diagnostic(),
+      Self::ResolveError(err) => err.diagnostic(),
+      Self::RuntimeError(err) => err.diagnostic(),
+    }
+  }
+
+  /// Renders the error together with the line of `source_code` it points at.
+  pub fn render(&self, source_code: &str) -> String {
+    self.diagnostic().render(source_code)
+  }
+
+  /// Serialises the error as a single line of JSON, see `Diagnostic::to_json`.
+  pub fn to_json(&self) -> String {
+    self.diagnostic().to_json()
+  }
+
+  /// Whether the error was found before the program started running.
+  pub fn is_static(&self) -> bool {
+    matches!(
+      self,
+      Self::LoadError(_) | Self::LexerError(_) | Self::ParseError(_) | Self::ResolveError(_)
+    )
+  }
+}
+
 impl fmt::Display for LoxError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      Self::IoError(err) => write!(f, "{}", err),
-      Self::LexerError(err) => write!(f, "LexerError: {}", err),
-      Self::ParseError(err) => write!(f, "ParseError: {}", err),
-      Self::ResolveError(err) => write!(f, "ResolveError: {}", err),
-      Self::RuntimeError(err) => write!(f, "RuntimeError: {}", err),
+      Self::IoError(_) => write!(f, "could not read the program"),
+      Self::LoadError(_) => write!(f, "could not load the compiled program"),
+      Self::LexerError(_) => write!(f, "could not tokenize the program"),
+      Self::ParseError(_) => write!(f, "could not parse the program"),
+      Self::ResolveError(_) => write!(f, "could not resolve the program"),
+      Self::RuntimeError(_) => write!(f, "the program failed while running"),
+    }
+  }
+}
+
+impl Error for LoxError {
+  fn source(&self) -> Option<&(dyn Error + 'static)> {
+    match self {
+      Self::IoError(err) => Some(err.as_ref()),
+      Self::LoadError(err) => Some(err),
+      Self::LexerError(err) => Some(err),
+      Self::ParseError(err) => Some(err),
+      Self::ResolveError(err) => Some(err),
+      Self::RuntimeError(err) => Some(err),
     }
   }
 }
@@ -1,3 +1,5 @@
 mod fun;
+mod native;
 
 pub use fun::*;
+pub use native::*;
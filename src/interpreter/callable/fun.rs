@@ -1,67 +1,64 @@
 use crate::interpreter::Interpreter;
 use crate::interpreter::environment::Environment;
 use crate::interpreter::errors::RuntimeError;
-use crate::lexer::{Token, TokenKind};
-use crate::parser::{ControlSignal, LoxCallable, Stmt, Value};
+use crate::lexer::TokenKind;
+use crate::parser::{ControlSignal, FunctionDecl, LoxCallable, Value};
 use std::cell::RefCell;
 use std::rc::Rc;
 
 #[derive(Debug)]
 pub struct LoxFunction {
-  name: Token,
-  params: Vec<Token>,
-  body: Box<Stmt>,
+  decl: Rc<FunctionDecl>,
   environment: Rc<RefCell<Environment>>,
 }
 
 impl LoxFunction {
-  pub fn new(
-    name: Token,
-    params: Vec<Token>,
-    body: Box<Stmt>,
-    environment: Rc<RefCell<Environment>>,
-  ) -> LoxFunction {
-    LoxFunction {
-      name,
-      params,
-      body,
-      environment,
-    }
+  pub fn new(decl: Rc<FunctionDecl>, environment: Rc<RefCell<Environment>>) -> LoxFunction {
+    LoxFunction { decl, environment }
   }
 
-  fn declare_params(&self, interpreter: &mut Interpreter, args: Vec<Value>) {
-    for (i, arg) in args.iter().enumerate() {
-      let param_name = self.params.get(i).unwrap().extract_identifier();
-      interpreter.declare(param_name.clone(), arg.clone());
-    }
+  /// Runs the body of the function, and returns how it ended. A call
+  /// it returned is left to the caller to make.
+  pub(crate) fn run(
+    &self,
+    interpreter: &mut Interpreter,
+    args: Vec<Value>,
+  ) -> Result<ControlSignal, RuntimeError> {
+    assert_eq!(self.decl.params.len(), args.len());
+    let params = interpreter
+      .allocate_environment(Environment::with_values(args, Rc::clone(&self.environment)));
+    let mut scope = interpreter.enter(params);
+    scope.eval_stmt(&self.decl.body)
+  }
+
+  /// The environment the function was declared in.
+  pub fn closure(&self) -> &Rc<RefCell<Environment>> {
+    &self.environment
   }
 }
 
 impl LoxCallable for LoxFunction {
   fn name(&self) -> &str {
-    match self.name.kind() {
-      TokenKind::Identifier(iden) => iden,
+    match self.decl.name.kind() {
+      TokenKind::Identifier(iden) => iden.as_str(),
       _ => panic!("expected identifier for function name"),
     }
   }
 
   fn arity(&self) -> usize {
-    self.params.len()
+    self.decl.params.len()
   }
 
   fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
-    assert_eq!(self.params.len(), args.len());
-    let old = interpreter.swap_environment(Rc::clone(&self.environment));
-    interpreter.begin_scope();
-    self.declare_params(interpreter, args);
-
-    let value = match interpreter.eval_stmt(&self.body)? {
-      ControlSignal::Return(value) => value,
-      _ => Value::Nil,
-    };
-
-    interpreter.end_scope();
-    interpreter.swap_environment(old);
-    Ok(value)
+    let mut signal = self.run(interpreter, args)?;
+    // The calls the function returned run here, one after the other,
+    // instead of nesting in the functions that returned them.
+    loop {
+      signal = match signal {
+        ControlSignal::TailCall(call) => interpreter.tail_call(call)?,
+        ControlSignal::Return(value) => return Ok(value),
+        ControlSignal::None => return Ok(Value::Nil),
+      };
+    }
   }
 }
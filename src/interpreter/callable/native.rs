@@ -0,0 +1,145 @@
+use crate::interpreter::errors::RuntimeError;
+use crate::interpreter::{Capability, Interpreter};
+use crate::parser::{FromLox, IntoLoxResult, LoxCallable, Value};
+use std::fmt;
+
+type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError>;
+
+/// A function implemented in Rust and callable from Lox.
+pub struct NativeFunction {
+  name: String,
+  arity: usize,
+  fun: Box<NativeFn>,
+  capability: Option<Capability>,
+}
+
+impl NativeFunction {
+  /// Wraps a typed Rust closure, e.g. `|a: f64, b: String| -> bool`.
+  /// The arity is inferred from the closure's parameters, which are
+  /// converted with `FromLox` before each call.
+  pub fn new<Args, F>(name: &str, fun: F) -> NativeFunction
+  where
+    F: IntoNative<Args> + 'static,
+  {
+    NativeFunction {
+      name: name.to_string(),
+      arity: F::ARITY,
+      fun: Box::new(move |_, args| fun.call_native(args)),
+      capability: None,
+    }
+  }
+
+  /// Wraps a closure that takes the interpreter and the unconverted arguments.
+  pub(crate) fn with_interpreter<F>(name: &str, arity: usize, fun: F) -> NativeFunction
+  where
+    F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+  {
+    NativeFunction {
+      name: name.to_string(),
+      arity,
+      fun: Box::new(fun),
+      capability: None,
+    }
+  }
+
+  /// Only lets programs granted `capability` call the function.
+  pub fn with_capability(mut self, capability: Capability) -> NativeFunction {
+    self.capability = Some(capability);
+    self
+  }
+}
+
+impl fmt::Debug for NativeFunction {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "<native {}/{}>", self.name, self.arity)
+  }
+}
+
+impl LoxCallable for NativeFunction {
+  fn name(&self) -> &str {
+    &self.name
+  }
+
+  fn arity(&self) -> usize {
+    self.arity
+  }
+
+  fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    assert_eq!(self.arity, args.len());
+    let mut value = (self.fun)(interpreter, args)?;
+    interpreter.count_memory(&mut value);
+    Ok(value)
+  }
+
+  fn capability(&self) -> Option<Capability> {
+    self.capability
+  }
+}
+
+/// Rust closures that can be turned into a `NativeFunction`.
+/// `Args` is the tuple of the closure's parameter types.
+pub trait IntoNative<Args> {
+  const ARITY: usize;
+  fn call_native(&self, args: Vec<Value>) -> Result<Value, RuntimeError>;
+}
+
+macro_rules! impl_into_native {
+  ($arity:expr; $($arg:ident),*) => {
+    impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+    where
+      F: Fn($($arg),*) -> R,
+      R: IntoLoxResult,
+      $($arg: FromLox,)*
+    {
+      const ARITY: usize = $arity;
+
+      #[allow(non_snake_case, unused_mut, unused_variables)]
+      fn call_native(&self, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        let mut args = args.into_iter();
+        $(
+          let $arg = $arg::from_lox(args.next().expect("expected that arity is checked by caller"))?;
+        )*
+        self($($arg),*).into_lox_result()
+      }
+    }
+  };
+}
+
+impl_into_native!(0;);
+impl_into_native!(1; A);
+impl_into_native!(2; A, B);
+impl_into_native!(3; A, B, C);
+impl_into_native!(4; A, B, C, D);
+impl_into_native!(5; A, B, C, D, E);
+impl_into_native!(6; A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::interpreter::RuntimeErrorKind;
+  use crate::parser::IntoLox;
+  use crate::resolver::Resolver;
+
+  fn call(native: &NativeFunction, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let mut interpreter = Interpreter::new(Resolver::new());
+    native.call(&mut interpreter, args)
+  }
+
+  #[test]
+  fn infers_arity_from_closure() {
+    let native = NativeFunction::new("longer", |a: f64, b: String| -> bool { b.len() as f64 > a });
+    assert_eq!(native.arity(), 2);
+    let value = call(&native, vec![2.into_lox(), "lox".into_lox()]).unwrap();
+    assert!(matches!(value, Value::Bool(true)));
+  }
+
+  #[test]
+  fn reports_bad_argument_types() {
+    let native = NativeFunction::new("half", |a: f64| a / 2.0);
+    let err = call(&native, vec![Value::Nil]).unwrap_err();
+    assert!(matches!(
+      err.kind(),
+      RuntimeErrorKind::ConversionFailed { .. }
+    ));
+  }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// A group of host functionality that natives, and the `print`
+/// statement, need to be granted before a program may use them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Capability {
+  /// The `print` statement and `write(value)`.
+  IoStdout,
+  /// `read_line()`.
+  IoStdin,
+  /// `read_file(path)`.
+  FsRead,
+  /// `write_file(path, contents)`.
+  FsWrite,
+  /// `env(name)`.
+  Env,
+  /// `clock()`.
+  Time,
+}
+
+impl Capability {
+  pub const ALL: [Capability; 6] = [
+    Capability::IoStdout,
+    Capability::IoStdin,
+    Capability::FsRead,
+    Capability::FsWrite,
+    Capability::Env,
+    Capability::Time,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Capability::IoStdout => "io.stdout",
+      Capability::IoStdin => "io.stdin",
+      Capability::FsRead => "fs.read",
+      Capability::FsWrite => "fs.write",
+      Capability::Env => "env",
+      Capability::Time => "time",
+    }
+  }
+
+  fn bit(&self) -> u8 {
+    1 << Capability::ALL
+      .iter()
+      .position(|capability| capability == self)
+      .expect("expected that every capability is listed in ALL")
+  }
+}
+
+impl fmt::Display for Capability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for Capability {
+  type Err = String;
+
+  fn from_str(name: &str) -> Result<Capability, String> {
+    Capability::ALL
+      .into_iter()
+      .find(|capability| capability.name() == name)
+      .ok_or_else(|| format!("unknown capability '{name}'"))
+  }
+}
+
+/// The set of capabilities granted to a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Capabilities(u8);
+
+impl Capabilities {
+  pub fn none() -> Capabilities {
+    Capabilities(0)
+  }
+
+  pub fn all() -> Capabilities {
+    Capability::ALL
+      .into_iter()
+      .fold(Capabilities::none(), Capabilities::with)
+  }
+
+  pub fn with(self, capability: Capability) -> Capabilities {
+    Capabilities(self.0 | capability.bit())
+  }
+
+  pub fn without(self, capability: Capability) -> Capabilities {
+    Capabilities(self.0 & !capability.bit())
+  }
+
+  pub fn contains(&self, capability: Capability) -> bool {
+    self.0 & capability.bit() != 0
+  }
+}
+
+impl Default for Capabilities {
+  fn default() -> Capabilities {
+    Capabilities::all()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parses_capability_names() {
+    for capability in Capability::ALL {
+      assert_eq!(capability.name().parse(), Ok(capability));
+    }
+    assert!("net".parse::<Capability>().is_err());
+  }
+
+  #[test]
+  fn grants_and_revokes_capabilities() {
+    let capabilities = Capabilities::none().with(Capability::Env);
+    assert!(capabilities.contains(Capability::Env));
+    assert!(!capabilities.contains(Capability::Time));
+    assert!(
+      !capabilities
+        .without(Capability::Env)
+        .contains(Capability::Env)
+    );
+    assert!(
+      Capability::ALL
+        .iter()
+        .all(|c| Capabilities::all().contains(*c))
+    );
+  }
+}
@@ -1,79 +1,103 @@
 use std::cell::RefCell;
-use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::parser::Value;
 
+/// The variables of one scope, in the slots the resolver assigned them.
 #[derive(Debug)]
 pub struct Environment {
-  variables: RefCell<HashMap<String, Value>>,
+  values: Vec<Value>,
   enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
   pub fn new() -> Environment {
     Environment {
-      variables: RefCell::new(HashMap::new()),
+      values: Vec::new(),
       enclosing: None,
     }
   }
 
   pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Environment {
+    Environment::with_values(Vec::new(), enclosing)
+  }
+
+  /// An environment whose first slots hold `values`, such as the
+  /// arguments of a call.
+  pub fn with_values(values: Vec<Value>, enclosing: Rc<RefCell<Environment>>) -> Environment {
     Environment {
-      variables: RefCell::new(HashMap::new()),
+      values,
       enclosing: Some(enclosing),
     }
   }
 
-  pub fn enclosing(&self) -> Rc<RefCell<Environment>> {
-    if let Some(enclosing) = &self.enclosing {
-      Rc::clone(enclosing)
+  /// Sets the variable in `slot`, filling the slots before it with nil
+  /// if they were never declared.
+  pub fn declare(&mut self, slot: usize, value: Value) {
+    if slot < self.values.len() {
+      self.values[slot] = value;
     } else {
-      panic!("expected enclosing to exist");
+      self.values.resize(slot, Value::Nil);
+      self.values.push(value);
+    }
+  }
+
+  /// Declares every slot below `len` that was not declared yet as nil.
+  pub fn reserve(&mut self, len: usize) {
+    if self.values.len() < len {
+      self.values.resize(len, Value::Nil);
     }
   }
 
-  pub fn declare(&mut self, variable: String, value: Value) {
-    self.variables.borrow_mut().insert(variable, value);
+  pub fn len(&self) -> usize {
+    self.values.len()
   }
 
-  pub fn get_at_depth(&self, depth: usize, variable: &String) -> Value {
+  pub fn capacity(&self) -> usize {
+    self.values.capacity()
+  }
+
+  pub fn values(&self) -> &[Value] {
+    &self.values
+  }
+
+  pub fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+    self.enclosing.as_ref()
+  }
+
+  /// Takes every variable and the enclosing environment out, so that
+  /// the environment no longer keeps them alive.
+  pub fn clear(&mut self) -> (Vec<Value>, Option<Rc<RefCell<Environment>>>) {
+    (std::mem::take(&mut self.values), self.enclosing.take())
+  }
+
+  pub fn get(&self, slot: usize) -> Option<&Value> {
+    self.values.get(slot)
+  }
+
+  pub fn get_at_depth(&self, depth: usize, slot: usize) -> Value {
     if depth == 0 {
-      self
-        .variables
-        .borrow()
-        .get(variable)
-        .map(|v| v.clone())
-        .expect("expected env at depth to contain reference")
+      self.values[slot].clone()
     } else {
       self
         .enclosing
         .as_ref()
         .expect("expected env at depth to exist")
         .borrow()
-        .get_at_depth(depth - 1, variable)
+        .get_at_depth(depth - 1, slot)
     }
   }
 
-  pub fn assign_at_depth(&mut self, depth: usize, variable: &String, value: &Value) -> Option<()> {
+  pub fn assign_at_depth(&mut self, depth: usize, slot: usize, value: Value) {
     if depth == 0 {
-      self
-        .variables
-        .borrow_mut()
-        .get_mut(variable)
-        .and_then(|v| {
-          *v = value.clone();
-          Some(())
-        })
-        .expect("expected env at depth to contain reference");
-      Some(())
+      self.values[slot] = value;
     } else {
       self
         .enclosing
         .as_ref()
         .expect("expected env at depth to exist")
         .borrow_mut()
-        .assign_at_depth(depth - 1, variable, value)
+        .assign_at_depth(depth - 1, slot, value);
     }
   }
 }
@@ -1,20 +1,253 @@
 use std::fmt;
+use std::rc::Rc;
 
+use super::Capability;
+use crate::diagnostic::{Diagnostic, Span};
 use crate::lexer::Token;
-use crate::parser::Value;
+use crate::parser::{LoxCallable, Value};
+
+/// Deep recursion would otherwise print thousands of frames.
+const MAX_RENDERED_FRAMES: usize = 16;
+
+/// An error raised while running the program, together with the
+/// calls that were running when it was raised.
+#[derive(Debug)]
+pub struct RuntimeError {
+  kind: Box<RuntimeErrorKind>,
+  backtrace: Vec<Frame>,
+}
 
 #[derive(Debug)]
-pub enum RuntimeError {
+#[non_exhaustive]
+pub enum RuntimeErrorKind {
   UndefinedOpBetween(Value, Token, Value),
   ExpectedNumber(Token),
   CallableBadArgsCount(Token),
   ExpectedCallable(Token),
+  /// A call would nest deeper than `Limits` allow.
+  StackOverflow(Token),
+  /// Building a string, or calling a native that builds values, would
+  /// take the program beyond the memory allowed by `Limits`.
+  MemoryLimit(Token),
+  /// A native was called without its capability being granted.
+  PermissionDenied(Token, Capability),
+  /// The program used up the fuel given by `Limits`.
+  BudgetExhausted,
+  /// The interrupt handle was set while the program was running.
+  Interrupted,
+  /// The program called `exit(code)`. The error unwinds the calls
+  /// without recording them, and running the program then completes
+  /// with `Completion::Exit` instead of failing.
+  Exit(i32),
+  /// A native was called with an argument of the wrong type. The call
+  /// site is filled in once the error leaves the native.
+  ConversionFailed {
+    expected: &'static str,
+    found: &'static str,
+    call_site: Option<Token>,
+  },
+}
+
+/// A call of a function, identified by the function's name and the
+/// closing paren of the call.
+#[derive(Debug, Clone)]
+pub struct Frame {
+  callable: Rc<dyn LoxCallable>,
+  call_site: Token,
+}
+
+impl Frame {
+  pub fn new(callable: Rc<dyn LoxCallable>, call_site: Token) -> Frame {
+    Frame {
+      callable,
+      call_site,
+    }
+  }
+
+  pub fn function(&self) -> &str {
+    self.callable.name()
+  }
+
+  pub fn call_site(&self) -> &Token {
+    &self.call_site
+  }
+}
+
+impl fmt::Display for Frame {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let call_site = &self.call_site;
+    write!(
+      f,
+      "in {}(), called at {}:{}:{}",
+      self.function(),
+      call_site.source_id(),
+      call_site.line(),
+      call_site.col()
+    )
+  }
+}
+
+impl RuntimeError {
+  pub fn kind(&self) -> &RuntimeErrorKind {
+    &self.kind
+  }
+
+  /// The calls that were running when the error was raised, innermost first.
+  pub fn backtrace(&self) -> &[Frame] {
+    &self.backtrace
+  }
+
+  /// Records a call the error unwound through. Calls are pushed
+  /// innermost first.
+  pub fn push_frame(&mut self, frame: Frame) {
+    if let RuntimeErrorKind::ConversionFailed {
+      call_site: call_site @ None,
+      ..
+    } = &mut *self.kind
+    {
+      *call_site = Some(frame.call_site.clone());
+    }
+    self.backtrace.push(frame);
+  }
+
+  /// The code the program called `exit` with, if the error is unwinding
+  /// from that call rather than failing.
+  pub fn exit_code(&self) -> Option<i32> {
+    match *self.kind {
+      RuntimeErrorKind::Exit(code) => Some(code),
+      _ => None,
+    }
+  }
+
+  /// The token the error points at, if any.
+  pub fn token(&self) -> Option<&Token> {
+    match &*self.kind {
+      RuntimeErrorKind::UndefinedOpBetween(_, tok, _)
+      | RuntimeErrorKind::ExpectedNumber(tok)
+      | RuntimeErrorKind::CallableBadArgsCount(tok)
+      | RuntimeErrorKind::ExpectedCallable(tok)
+      | RuntimeErrorKind::StackOverflow(tok)
+      | RuntimeErrorKind::MemoryLimit(tok)
+      | RuntimeErrorKind::PermissionDenied(tok, _) => Some(tok),
+      RuntimeErrorKind::ConversionFailed { call_site, .. } => call_site.as_ref(),
+      RuntimeErrorKind::BudgetExhausted
+      | RuntimeErrorKind::Interrupted
+      | RuntimeErrorKind::Exit(_) => None,
+    }
+  }
+
+  pub fn span(&self) -> Option<Span> {
+    self.token().map(Token::span)
+  }
+
+  pub fn code(&self) -> &'static str {
+    match *self.kind {
+      RuntimeErrorKind::UndefinedOpBetween(..) => "E0401",
+      RuntimeErrorKind::ExpectedNumber(_) => "E0402",
+      RuntimeErrorKind::CallableBadArgsCount(_) => "E0403",
+      RuntimeErrorKind::ExpectedCallable(_) => "E0404",
+      RuntimeErrorKind::ConversionFailed { .. } => "E0405",
+      RuntimeErrorKind::StackOverflow(_) => "E0407",
+      RuntimeErrorKind::BudgetExhausted => "E0408",
+      RuntimeErrorKind::Interrupted => "E0409",
+      RuntimeErrorKind::MemoryLimit(_) => "E0410",
+      RuntimeErrorKind::PermissionDenied(..) => "E0411",
+      RuntimeErrorKind::Exit(_) => "E0412",
+    }
+  }
+
+  /// The diagnostic of the error, with one note per frame of the
+  /// backtrace, innermost first. Only the innermost frames of a deep
+  /// backtrace are listed.
+  pub fn diagnostic(&self) -> Diagnostic {
+    let mut diagnostic = match &*self.kind {
+      RuntimeErrorKind::UndefinedOpBetween(left, op, right) => Diagnostic::new(
+        self.code(),
+        format!(
+          "operator '{}' is not defined between a {} and a {}",
+          op.kind().name(),
+          left.type_name(),
+          right.type_name()
+        ),
+      )
+      .with_span(op.span(), "cannot be applied to these operands"),
+      RuntimeErrorKind::ExpectedNumber(tok) => {
+        Diagnostic::new(self.code(), "operand must be a number")
+          .with_span(tok.span(), "expected a number")
+      }
+      RuntimeErrorKind::CallableBadArgsCount(tok) => {
+        Diagnostic::new(self.code(), "called with too few or too many arguments")
+          .with_span(tok.span(), "in this call")
+      }
+      RuntimeErrorKind::ExpectedCallable(tok) => {
+        Diagnostic::new(self.code(), "only functions can be called")
+          .with_span(tok.span(), "not a function")
+      }
+      RuntimeErrorKind::StackOverflow(tok) => {
+        Diagnostic::new(self.code(), "maximum call depth exceeded")
+          .with_span(tok.span(), "this call nests too deep")
+          .with_help("check that the recursion ends")
+      }
+      RuntimeErrorKind::MemoryLimit(tok) => Diagnostic::new(self.code(), "memory limit exceeded")
+        .with_span(tok.span(), "this goes beyond the limit"),
+      RuntimeErrorKind::PermissionDenied(tok, capability) => Diagnostic::new(
+        self.code(),
+        format!("capability '{capability}' is not granted"),
+      )
+      .with_span(tok.span(), format!("needs '{capability}'")),
+      RuntimeErrorKind::BudgetExhausted => {
+        Diagnostic::new(self.code(), "execution budget exhausted")
+          .with_note("every statement and expression uses one unit of fuel")
+      }
+      RuntimeErrorKind::Interrupted => Diagnostic::new(self.code(), "interrupted"),
+      RuntimeErrorKind::Exit(code) => {
+        Diagnostic::new(self.code(), format!("exited with code {code}"))
+      }
+      RuntimeErrorKind::ConversionFailed {
+        expected,
+        found,
+        call_site,
+      } => {
+        let diagnostic = Diagnostic::new(
+          self.code(),
+          format!("expected a {expected} but found a {found}"),
+        );
+        match call_site {
+          Some(tok) => diagnostic.with_span(tok.span(), "in the arguments of this call"),
+          None => diagnostic.with_note("raised while converting arguments of a native function"),
+        }
+      }
+    };
+    for frame in self.backtrace.iter().take(MAX_RENDERED_FRAMES) {
+      diagnostic = diagnostic.with_note(frame.to_string());
+    }
+    if self.backtrace.len() > MAX_RENDERED_FRAMES {
+      let hidden = self.backtrace.len() - MAX_RENDERED_FRAMES;
+      diagnostic = diagnostic.with_note(format!("... and {hidden} more calls"));
+    }
+    diagnostic
+  }
+}
+
+impl From<RuntimeErrorKind> for RuntimeError {
+  fn from(kind: RuntimeErrorKind) -> RuntimeError {
+    RuntimeError {
+      kind: Box::new(kind),
+      backtrace: Vec::new(),
+    }
+  }
 }
 
 impl fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.kind)
+  }
+}
+
+impl fmt::Display for RuntimeErrorKind {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
-      Self::UndefinedOpBetween(right, op, left) => {
+      Self::UndefinedOpBetween(left, op, right) => {
         write!(
           f,
           "{} is not defined between {:?} and {:?}",
@@ -28,6 +261,26 @@ impl fmt::Display for RuntimeError {
       Self::ExpectedCallable(tok) => {
         write!(f, "{} expected callable", tok)
       }
+      Self::StackOverflow(tok) => write!(f, "{} maximum call depth exceeded", tok),
+      Self::MemoryLimit(tok) => write!(f, "{} memory limit exceeded", tok),
+      Self::PermissionDenied(tok, capability) => {
+        write!(f, "{} capability '{}' is not granted", tok, capability)
+      }
+      Self::BudgetExhausted => write!(f, "execution budget exhausted"),
+      Self::Interrupted => write!(f, "interrupted"),
+      Self::Exit(code) => write!(f, "exited with code {}", code),
+      Self::ConversionFailed {
+        expected,
+        found,
+        call_site: Some(tok),
+      } => write!(f, "{} expected a {} but found a {}", tok, expected, found),
+      Self::ConversionFailed {
+        expected,
+        found,
+        call_site: None,
+      } => write!(f, "expected a {} but found a {}", expected, found),
     }
   }
 }
+
+impl std::error::Error for RuntimeError {}
@@ -0,0 +1,324 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::mem::size_of;
+use std::rc::{Rc, Weak};
+
+use super::LoxFunction;
+use super::environment::Environment;
+use crate::parser::Value;
+use crate::vm::{Closure, Upvalue};
+
+/// Collections run once this many functions were declared since the
+/// last one, or twice as many as survived it if that is more.
+const MIN_THRESHOLD: usize = 1024;
+
+/// Statistics of an interpreter's garbage collector.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GcStats {
+  bytes_allocated: usize,
+  collections: usize,
+  objects_freed: usize,
+}
+
+impl GcStats {
+  /// Bytes of environments, functions and closures allocated so far,
+  /// estimated from their size when they were created.
+  pub fn bytes_allocated(&self) -> usize {
+    self.bytes_allocated
+  }
+
+  pub fn collections(&self) -> usize {
+    self.collections
+  }
+
+  /// Environments, functions, closures, upvalues, lists and maps freed
+  /// by the collections, which reference counting alone would have leaked.
+  pub fn objects_freed(&self) -> usize {
+    self.objects_freed
+  }
+}
+
+/// Frees the reference cycles between functions and the environments
+/// they close over, such as the one of every recursive function.
+///
+/// Every function the program declares is registered, and so is every
+/// closure the VM creates, whose upvalues play the part of environments.
+/// A collection looks at everything reachable from those and counts how
+/// many references each object gets from the others. An object with
+/// more strong references than that is also held from outside, by the
+/// interpreter or the host, so it is kept with everything it reaches.
+/// The environments, upvalues, lists and maps left over are only
+/// referenced by each other, and emptying them breaks the cycles.
+pub struct Heap {
+  functions: Vec<Weak<LoxFunction>>,
+  closures: Vec<Weak<Closure>>,
+  threshold: usize,
+  stats: GcStats,
+}
+
+impl Heap {
+  pub fn new() -> Heap {
+    Heap {
+      functions: Vec::new(),
+      closures: Vec::new(),
+      threshold: MIN_THRESHOLD,
+      stats: GcStats::default(),
+    }
+  }
+
+  pub fn stats(&self) -> GcStats {
+    self.stats
+  }
+
+  pub fn allocate_environment(&mut self, environment: Environment) -> Rc<RefCell<Environment>> {
+    self.stats.bytes_allocated +=
+      rc_size::<RefCell<Environment>>() + environment.capacity() * size_of::<Value>();
+    Rc::new(RefCell::new(environment))
+  }
+
+  pub fn allocate_function(&mut self, function: LoxFunction) -> Rc<LoxFunction> {
+    self.stats.bytes_allocated += rc_size::<LoxFunction>();
+    let function = Rc::new(function);
+    self.functions.push(Rc::downgrade(&function));
+    function
+  }
+
+  pub fn allocate_closure(&mut self, closure: Closure) -> Rc<Closure> {
+    self.stats.bytes_allocated += rc_size::<Closure>() + std::mem::size_of_val(closure.upvalues());
+    let closure = Rc::new(closure);
+    self.closures.push(Rc::downgrade(&closure));
+    closure
+  }
+
+  /// Whether enough functions were declared to make a collection worth it.
+  pub fn should_collect(&self) -> bool {
+    self.functions.len() + self.closures.len() >= self.threshold
+  }
+
+  /// Frees the objects that are only referenced by each other, and
+  /// returns how many there were.
+  pub fn collect(&mut self) -> usize {
+    self.forget_freed();
+    let index: HashMap<usize, Callable> = self
+      .functions
+      .iter()
+      .map(|function| {
+        (
+          function.as_ptr() as *const () as usize,
+          Callable::Function(Weak::clone(function)),
+        )
+      })
+      .chain(self.closures.iter().map(|closure| {
+        (
+          closure.as_ptr() as *const () as usize,
+          Callable::Closure(Weak::clone(closure)),
+        )
+      }))
+      .collect();
+
+    // Each object is held exactly once by `objects` while counting.
+    let mut objects: HashMap<usize, Object> = HashMap::new();
+    let mut references: HashMap<usize, Vec<usize>> = HashMap::new();
+    let mut pinned: HashSet<usize> = HashSet::new();
+    let mut pending: Vec<Object> = self.registered().collect();
+    while let Some(object) = pending.pop() {
+      let address = object.address();
+      if objects.contains_key(&address) {
+        continue;
+      }
+      match object.references(&index) {
+        Some(targets) => {
+          references.insert(address, targets.iter().map(Object::address).collect());
+          pending.extend(targets);
+        }
+        // An object that is borrowed right now is in use.
+        None => {
+          pinned.insert(address);
+          references.insert(address, Vec::new());
+        }
+      }
+      objects.insert(address, object);
+    }
+
+    let mut internal: HashMap<usize, usize> = HashMap::new();
+    for targets in references.values() {
+      for target in targets {
+        *internal.entry(*target).or_default() += 1;
+      }
+    }
+    let mut live: HashSet<usize> = objects
+      .iter()
+      .filter(|(address, object)| {
+        let held_by_others = internal.get(*address).copied().unwrap_or(0);
+        pinned.contains(*address) || object.strong_count() > held_by_others + 1
+      })
+      .map(|(address, _)| *address)
+      .collect();
+    let mut pending: Vec<usize> = live.iter().copied().collect();
+    while let Some(address) = pending.pop() {
+      for target in &references[&address] {
+        if live.insert(*target) {
+          pending.push(*target);
+        }
+      }
+    }
+
+    let mut freed = 0;
+    for (address, object) in &objects {
+      if !live.contains(address) {
+        object.clear();
+        freed += 1;
+      }
+    }
+    drop(objects);
+
+    self.forget_freed();
+    self.threshold = MIN_THRESHOLD.max(2 * (self.functions.len() + self.closures.len()));
+    self.stats.collections += 1;
+    self.stats.objects_freed += freed;
+    freed
+  }
+
+  /// The functions and closures that are still alive.
+  fn registered(&self) -> impl Iterator<Item = Object> + '_ {
+    let functions = self
+      .functions
+      .iter()
+      .filter_map(Weak::upgrade)
+      .map(Object::Function);
+    let closures = self
+      .closures
+      .iter()
+      .filter_map(Weak::upgrade)
+      .map(Object::Closure);
+    functions.chain(closures)
+  }
+
+  fn forget_freed(&mut self) {
+    self
+      .functions
+      .retain(|function| function.strong_count() > 0);
+    self.closures.retain(|closure| closure.strong_count() > 0);
+  }
+}
+
+/// The size of an `Rc<T>` allocation, including the reference counts.
+fn rc_size<T>() -> usize {
+  size_of::<T>() + 2 * size_of::<usize>()
+}
+
+/// A registered function or closure, which values refer to as callables.
+enum Callable {
+  Function(Weak<LoxFunction>),
+  Closure(Weak<Closure>),
+}
+
+impl Callable {
+  fn upgrade(&self) -> Option<Object> {
+    match self {
+      Callable::Function(function) => function.upgrade().map(Object::Function),
+      Callable::Closure(closure) => closure.upgrade().map(Object::Closure),
+    }
+  }
+}
+
+/// An object the collector can trace through.
+enum Object {
+  Environment(Rc<RefCell<Environment>>),
+  Function(Rc<LoxFunction>),
+  Closure(Rc<Closure>),
+  Upvalue(Rc<RefCell<Upvalue>>),
+  List(Rc<RefCell<Vec<Value>>>),
+  Map(Rc<RefCell<HashMap<String, Value>>>),
+}
+
+impl Object {
+  fn address(&self) -> usize {
+    match self {
+      Object::Environment(environment) => Rc::as_ptr(environment) as *const () as usize,
+      Object::Function(function) => Rc::as_ptr(function) as *const () as usize,
+      Object::Closure(closure) => Rc::as_ptr(closure) as *const () as usize,
+      Object::Upvalue(upvalue) => Rc::as_ptr(upvalue) as *const () as usize,
+      Object::List(items) => Rc::as_ptr(items) as *const () as usize,
+      Object::Map(entries) => Rc::as_ptr(entries) as *const () as usize,
+    }
+  }
+
+  fn strong_count(&self) -> usize {
+    match self {
+      Object::Environment(environment) => Rc::strong_count(environment),
+      Object::Function(function) => Rc::strong_count(function),
+      Object::Closure(closure) => Rc::strong_count(closure),
+      Object::Upvalue(upvalue) => Rc::strong_count(upvalue),
+      Object::List(items) => Rc::strong_count(items),
+      Object::Map(entries) => Rc::strong_count(entries),
+    }
+  }
+
+  /// The objects this one holds, once per strong reference, or `None`
+  /// if it is in use, such as when it cannot be borrowed.
+  fn references(&self, callables: &HashMap<usize, Callable>) -> Option<Vec<Object>> {
+    let objects = |values: &mut dyn Iterator<Item = &Value>| -> Vec<Object> {
+      values
+        .filter_map(|value| Object::of(value, callables))
+        .collect()
+    };
+    match self {
+      Object::Environment(environment) => {
+        let environment = environment.try_borrow().ok()?;
+        let mut targets = objects(&mut environment.values().iter());
+        if let Some(enclosing) = environment.enclosing() {
+          targets.push(Object::Environment(Rc::clone(enclosing)));
+        }
+        Some(targets)
+      }
+      Object::Function(function) => Some(vec![Object::Environment(Rc::clone(function.closure()))]),
+      Object::Closure(closure) => Some(
+        closure
+          .upvalues()
+          .iter()
+          .map(|upvalue| Object::Upvalue(Rc::clone(upvalue)))
+          .collect(),
+      ),
+      // An open upvalue points into the stack of the VM, which is in use.
+      Object::Upvalue(upvalue) => match &*upvalue.try_borrow().ok()? {
+        Upvalue::Open(_) => None,
+        Upvalue::Closed(value) => Some(objects(&mut std::iter::once(value))),
+      },
+      Object::List(items) => Some(objects(&mut items.try_borrow().ok()?.iter())),
+      Object::Map(entries) => Some(objects(&mut entries.try_borrow().ok()?.values())),
+    }
+  }
+
+  /// The object a value references, if the collector traces it.
+  fn of(value: &Value, callables: &HashMap<usize, Callable>) -> Option<Object> {
+    match value {
+      Value::Callable(callable) => callables
+        .get(&(Rc::as_ptr(callable) as *const () as usize))
+        .and_then(Callable::upgrade),
+      Value::List(items) => Some(Object::List(Rc::clone(items))),
+      Value::Map(entries) => Some(Object::Map(Rc::clone(entries))),
+      _ => None,
+    }
+  }
+
+  /// Drops the references the object holds. The dropped values are
+  /// released after the borrow ends, as they may free other objects.
+  fn clear(&self) {
+    match self {
+      Object::Environment(environment) => {
+        let _released = environment.borrow_mut().clear();
+      }
+      Object::Function(_) | Object::Closure(_) => {}
+      Object::Upvalue(upvalue) => {
+        let _released = std::mem::replace(&mut *upvalue.borrow_mut(), Upvalue::Closed(Value::Nil));
+      }
+      Object::List(items) => {
+        let _released = std::mem::take(&mut *items.borrow_mut());
+      }
+      Object::Map(entries) => {
+        let _released = std::mem::take(&mut *entries.borrow_mut());
+      }
+    }
+  }
+}
@@ -0,0 +1,97 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use super::Interpreter;
+use super::environment::Environment;
+
+/// Gives access to an interpreter whose current environment was replaced,
+/// and puts the previous environment back when dropped. Scopes are entered
+/// through a guard so that an error propagated with `?` cannot leave the
+/// interpreter inside of a scope that already ended.
+pub struct EnvironmentGuard<'i, 'a> {
+  interpreter: &'i mut Interpreter<'a>,
+  previous: Rc<RefCell<Environment>>,
+}
+
+impl<'i, 'a> EnvironmentGuard<'i, 'a> {
+  pub fn new(
+    interpreter: &'i mut Interpreter<'a>,
+    environment: Rc<RefCell<Environment>>,
+  ) -> EnvironmentGuard<'i, 'a> {
+    let previous = interpreter.swap_environment(environment);
+    EnvironmentGuard {
+      interpreter,
+      previous,
+    }
+  }
+}
+
+impl<'a> Deref for EnvironmentGuard<'_, 'a> {
+  type Target = Interpreter<'a>;
+
+  fn deref(&self) -> &Interpreter<'a> {
+    self.interpreter
+  }
+}
+
+impl<'a> DerefMut for EnvironmentGuard<'_, 'a> {
+  fn deref_mut(&mut self) -> &mut Interpreter<'a> {
+    self.interpreter
+  }
+}
+
+impl Drop for EnvironmentGuard<'_, '_> {
+  fn drop(&mut self) {
+    self.interpreter.swap_environment(Rc::clone(&self.previous));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::source::SourceId;
+
+  use super::*;
+
+  fn run(interpreter: &mut Interpreter, source_code: &str) {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    interpreter.resolver_mut().resolve(&stmts);
+    assert!(interpreter.resolver_mut().errors().is_empty());
+    interpreter.interpret(stmts).unwrap_err();
+  }
+
+  #[test]
+  fn restores_environment_after_errors() {
+    let mut interpreter = Interpreter::new(Resolver::new());
+    interpreter.set_out_writer(Box::new(std::io::sink()));
+    let environment = interpreter.environment();
+    run(&mut interpreter, "{ var a = 1; { print a - nil; } }");
+    assert!(Rc::ptr_eq(&interpreter.environment(), &environment));
+
+    run(
+      &mut interpreter,
+      "fun f(n) { { var b = n; return b - nil; } } f(1);",
+    );
+    assert!(Rc::ptr_eq(&interpreter.environment(), &environment));
+  }
+
+  #[test]
+  fn restores_environment_when_dropped() {
+    let mut interpreter = Interpreter::new(Resolver::new());
+    let environment = interpreter.environment();
+    {
+      let scope = interpreter.enter_block();
+      let block = scope.environment();
+      block.borrow_mut().declare(0, crate::parser::Value::Nil);
+      assert_eq!(block.borrow().len(), 1);
+    }
+    assert!(Rc::ptr_eq(&interpreter.environment(), &environment));
+    assert_eq!(environment.borrow().len(), 0);
+  }
+}
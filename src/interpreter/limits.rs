@@ -0,0 +1,99 @@
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use super::Capabilities;
+
+/// Bounds on the resources a program may use while running. Exceeding
+/// one of them raises a runtime error instead of crashing the host.
+#[derive(Debug, Clone)]
+pub struct Limits {
+  max_call_depth: usize,
+  max_stack_bytes: usize,
+  fuel: Option<u64>,
+  max_memory: Option<usize>,
+  interrupt: Option<Arc<AtomicBool>>,
+  capabilities: Capabilities,
+}
+
+impl Limits {
+  /// The maximum number of nested calls, 1000 by default.
+  pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Limits {
+    self.max_call_depth = max_call_depth;
+    self
+  }
+
+  /// The maximum number of bytes of the native stack used by nested
+  /// calls, 1 MiB by default. Keep it well below the stack size of the
+  /// thread running the interpreter, which is 2 MiB for spawned threads
+  /// and usually 8 MiB for the main thread. The VM backend bounds the
+  /// bytes of its own stack instead, as its calls do not nest natively.
+  pub fn with_max_stack_bytes(mut self, max_stack_bytes: usize) -> Limits {
+    self.max_stack_bytes = max_stack_bytes;
+    self
+  }
+
+  /// How many statements and expressions each run may evaluate,
+  /// unlimited by default.
+  pub fn with_fuel(mut self, fuel: u64) -> Limits {
+    self.fuel = Some(fuel);
+    self
+  }
+
+  /// How many bytes the strings, lists and maps built by the program,
+  /// or returned to it by natives, may hold at once, unlimited by
+  /// default.
+  pub fn with_max_memory(mut self, max_memory: usize) -> Limits {
+    self.max_memory = Some(max_memory);
+    self
+  }
+
+  /// A flag that another thread sets to cancel the running program.
+  /// The interpreter clears it once the program is cancelled.
+  pub fn with_interrupt(mut self, interrupt: Arc<AtomicBool>) -> Limits {
+    self.interrupt = Some(interrupt);
+    self
+  }
+
+  /// The host functionality the program may use, everything by default.
+  pub fn with_capabilities(mut self, capabilities: Capabilities) -> Limits {
+    self.capabilities = capabilities;
+    self
+  }
+
+  pub fn max_call_depth(&self) -> usize {
+    self.max_call_depth
+  }
+
+  pub fn max_stack_bytes(&self) -> usize {
+    self.max_stack_bytes
+  }
+
+  pub fn fuel(&self) -> Option<u64> {
+    self.fuel
+  }
+
+  pub fn max_memory(&self) -> Option<usize> {
+    self.max_memory
+  }
+
+  pub fn interrupt(&self) -> Option<&Arc<AtomicBool>> {
+    self.interrupt.as_ref()
+  }
+
+  pub fn capabilities(&self) -> Capabilities {
+    self.capabilities
+  }
+}
+
+impl Default for Limits {
+  fn default() -> Limits {
+    Limits {
+      max_call_depth: 1000,
+      max_stack_bytes: 1024 * 1024,
+      fuel: None,
+      max_memory: None,
+      interrupt: None,
+      capabilities: Capabilities::all(),
+    }
+  }
+}
@@ -1,55 +1,298 @@
+use std::any::Any;
 use std::cell::RefCell;
 use std::io::{Write, stdout};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use crate::lexer::{Token, TokenKind};
-use crate::parser::{ControlSignal, Expr, Stmt, Value};
-use crate::resolver::Resolver;
+use crate::parser::{
+  ControlSignal, Expr, FunctionDecl, LoxCallable, LoxString, MemoryCounter, Stmt, TailCall, Value,
+};
+use crate::resolver::{Binding, Resolver};
+use crate::vm::{self, Closure};
 
 mod callable;
+mod capabilities;
 mod environment;
 mod errors;
+mod gc;
+mod guard;
+mod limits;
+mod natives;
 
-use callable::*;
+pub use callable::*;
+pub use capabilities::*;
 use environment::*;
 pub use errors::*;
+pub use gc::GcStats;
+use gc::Heap;
+pub use guard::*;
+pub use limits::*;
+pub use natives::*;
 
 pub struct Interpreter<'a> {
+  /// Natives and other globals, in the slots the resolver assigned them.
+  globals: Vec<Value>,
   environment: Rc<RefCell<Environment>>,
   resolver: Resolver,
+  frames: Vec<Frame>,
+  limits: Limits,
+  /// Address of the native stack when the outermost call started.
+  stack_base: usize,
+  /// Fuel left for the current run, if it is limited.
+  fuel: Option<u64>,
+  interrupt: Arc<AtomicBool>,
+  memory: MemoryCounter,
+  /// Where the VM prints its stack and each instruction it runs, if it
+  /// traces them.
+  trace: Option<Box<dyn Write + 'a>>,
+  heap: Heap,
+  vm_stack: vm::Stack,
   out: Box<dyn Write + 'a>,
 }
 
+/// How a program stopped running without an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Completion {
+  /// Every statement ran.
+  Finished,
+  /// The program called `exit(code)`.
+  Exit(i32),
+}
+
 impl<'a> Interpreter<'a> {
   pub fn new(resolver: Resolver) -> Self {
     Interpreter {
+      globals: Vec::new(),
       environment: Rc::new(RefCell::new(Environment::new())),
       resolver,
+      frames: Vec::new(),
+      limits: Limits::default(),
+      stack_base: 0,
+      fuel: None,
+      interrupt: Arc::new(AtomicBool::new(false)),
+      memory: MemoryCounter::new(),
+      trace: None,
+      heap: Heap::new(),
+      vm_stack: vm::Stack::default(),
       out: Box::new(stdout()),
     }
   }
 
-  pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<(), RuntimeError> {
-    for stmt in &stmts {
-      self.eval_stmt(stmt)?;
+  /// Runs the statements until they finish or the program calls `exit`.
+  /// Every run starts with the fuel given by the limits.
+  ///
+  /// # Errors
+  ///
+  /// Returns the first runtime error raised by the statements.
+  pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<Completion, RuntimeError> {
+    let (completion, _) = self.interpret_with_value(stmts)?;
+    Ok(completion)
+  }
+
+  /// Same as `interpret`, but also returns the value of the last
+  /// statement when it is an expression statement that finished, as a
+  /// REPL prints it.
+  ///
+  /// # Errors
+  ///
+  /// Returns the first runtime error raised by the statements.
+  pub fn interpret_with_value(
+    &mut self,
+    stmts: Vec<Stmt>,
+  ) -> Result<(Completion, Option<Value>), RuntimeError> {
+    self.refuel();
+    let (body, tail) = match stmts.split_last() {
+      Some((Stmt::ExprStmt { expr }, body)) => (body, Some(expr)),
+      _ => (&stmts[..], None),
+    };
+    let result = body
+      .iter()
+      .try_for_each(|stmt| self.eval_stmt(stmt).map(drop))
+      .and_then(|()| {
+        tail
+          .map(|expr| {
+            self.tick()?;
+            self.eval_expr(expr)
+          })
+          .transpose()
+      });
+    match result {
+      Ok(value) => Ok((Completion::Finished, value)),
+      Err(err) => match err.exit_code() {
+        Some(code) => Ok((Completion::Exit(code), None)),
+        None => Err(err),
+      },
     }
-    Ok(())
+  }
+
+  /// Defines a global that every program can refer to, such as a native
+  /// function. Globals live outside of the program's top-level scope.
+  pub fn define_global(&mut self, name: &str, value: Value) {
+    let slot = self.resolver.declare_global(name, None);
+    self.set_global(slot, value);
+  }
+
+  /// Defines a native function as a global. Programs that are not granted
+  /// the native's capability fail to resolve when they refer to it.
+  pub fn define_native(&mut self, native: NativeFunction) {
+    let slot = self
+      .resolver
+      .declare_global(native.name(), native.capability());
+    self.set_global(slot, Value::Callable(Rc::new(native)));
+  }
+
+  pub(crate) fn global(&self, slot: usize) -> &Value {
+    &self.globals[slot]
+  }
+
+  pub(crate) fn set_global(&mut self, slot: usize, value: Value) {
+    if slot < self.globals.len() {
+      self.globals[slot] = value;
+    } else {
+      self.globals.push(value);
+    }
+  }
+
+  /// Forgets every declaration except for the globals, keeping the output writer.
+  pub fn reset(&mut self) {
+    self.environment = Rc::new(RefCell::new(Environment::new()));
+    self.resolver.reset();
+    self.collect_garbage();
+  }
+
+  pub fn out(&mut self) -> &mut (dyn Write + 'a) {
+    &mut *self.out
   }
 
   pub fn set_out_writer(&mut self, out: Box<dyn Write + 'a>) {
     self.out = out;
   }
 
-  pub fn begin_scope(&mut self) {
-    let block = Rc::new(RefCell::new(Environment::with_enclosing(Rc::clone(
-      &self.environment,
-    ))));
-    self.environment = block;
+  pub(crate) fn resolver(&self) -> &Resolver {
+    &self.resolver
+  }
+
+  pub fn resolver_mut(&mut self) -> &mut Resolver {
+    &mut self.resolver
+  }
+
+  pub fn limits(&self) -> &Limits {
+    &self.limits
+  }
+
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.resolver.set_capabilities(limits.capabilities());
+    if let Some(interrupt) = limits.interrupt() {
+      self.interrupt = Arc::clone(interrupt);
+    }
+    self.fuel = limits.fuel();
+    self.limits = limits;
+  }
+
+  pub(crate) fn is_tracing(&self) -> bool {
+    self.trace.is_some()
+  }
+
+  pub(crate) fn trace_writer(&mut self) -> Option<&mut (dyn Write + 'a)> {
+    self.trace.as_deref_mut()
+  }
+
+  pub(crate) fn set_trace_writer(&mut self, trace: Option<Box<dyn Write + 'a>>) {
+    self.trace = trace;
+  }
+
+  /// Gives the current run the fuel of the limits.
+  pub(crate) fn refuel(&mut self) {
+    self.fuel = self.limits.fuel();
+  }
+
+  /// Fuel left for the current run, or `None` if fuel is unlimited.
+  pub fn fuel(&self) -> Option<u64> {
+    self.fuel
+  }
+
+  /// Bytes held by the strings, lists and maps the program built that
+  /// are still alive.
+  pub fn memory_used(&self) -> usize {
+    self.memory.used()
+  }
+
+  /// Frees the functions and environments that are only kept alive by
+  /// reference cycles, and returns how many objects were freed. This
+  /// also happens on its own as the program declares functions.
+  pub fn collect_garbage(&mut self) -> usize {
+    self.heap.collect()
+  }
+
+  pub fn gc_stats(&self) -> GcStats {
+    self.heap.stats()
+  }
+
+  pub(crate) fn allocate_closure(&mut self, closure: Closure) -> Rc<Closure> {
+    self.heap.allocate_closure(closure)
+  }
+
+  /// Whether enough functions were created to make a collection worth it.
+  pub(crate) fn should_collect(&self) -> bool {
+    self.heap.should_collect()
+  }
+
+  pub(crate) fn vm_stack(&mut self) -> &mut vm::Stack {
+    &mut self.vm_stack
   }
 
-  pub fn end_scope(&mut self) {
-    let block = self.environment.borrow().enclosing();
-    self.environment = block;
+  /// A flag that cancels the running program once set, from any thread.
+  pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+    Arc::clone(&self.interrupt)
+  }
+
+  /// The calls that are running, outermost first.
+  pub fn call_stack(&self) -> &[Frame] {
+    &self.frames
+  }
+
+  pub fn environment(&self) -> Rc<RefCell<Environment>> {
+    Rc::clone(&self.environment)
+  }
+
+  /// The variables of the current environment, when it is the top-level
+  /// environment kept between runs.
+  pub fn variables(&self) -> Vec<(String, Value)> {
+    let environment = self.environment.borrow();
+    self
+      .resolver
+      .top_level()
+      .into_iter()
+      .filter_map(|(name, slot)| Some((name, environment.get(slot)?.clone())))
+      .collect()
+  }
+
+  /// Declares the top-level variables that were resolved but never ran,
+  /// such as the ones after a runtime error, as nil.
+  pub fn declare_skipped(&mut self) {
+    let len = self.resolver.top_level().len();
+    self.environment.borrow_mut().reserve(len);
+  }
+
+  /// Enters a new block scope, which ends when the guard is dropped.
+  pub fn enter_block(&mut self) -> EnvironmentGuard<'_, 'a> {
+    let block =
+      self.allocate_environment(Environment::with_enclosing(Rc::clone(&self.environment)));
+    EnvironmentGuard::new(self, block)
+  }
+
+  pub(crate) fn allocate_environment(
+    &mut self,
+    environment: Environment,
+  ) -> Rc<RefCell<Environment>> {
+    self.heap.allocate_environment(environment)
+  }
+
+  /// Makes `environment` the current environment until the guard is dropped.
+  pub fn enter(&mut self, environment: Rc<RefCell<Environment>>) -> EnvironmentGuard<'_, 'a> {
+    EnvironmentGuard::new(self, environment)
   }
 
   pub fn swap_environment(&mut self, other: Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
@@ -58,31 +301,40 @@ impl<'a> Interpreter<'a> {
     old
   }
 
-  pub fn declare(&mut self, name: String, value: Value) {
-    self.environment.borrow_mut().declare(name, value);
+  /// Declares the variable of the declaration `id` in the current environment.
+  pub fn declare(&mut self, id: usize, value: Value) {
+    match self.resolver.binding(id) {
+      Binding::Local { slot, .. } => self.environment.borrow_mut().declare(slot, value),
+      Binding::Global(_) => panic!("expected a declaration to bind a local"),
+    }
   }
 
-  pub fn get(&self, id: usize, name: &String) -> Value {
-    let depth = self.resolver.get_bound_depth(id);
-    self.environment.borrow().get_at_depth(depth, name)
+  pub fn get(&self, id: usize) -> Value {
+    match self.resolver.binding(id) {
+      Binding::Local { depth, slot } => self.environment.borrow().get_at_depth(depth, slot),
+      Binding::Global(slot) => self.globals[slot].clone(),
+    }
   }
 
-  pub fn assign(&mut self, id: usize, name: &String, value: &Value) {
-    let depth = self.resolver.get_bound_depth(id);
-    self
-      .environment
-      .borrow_mut()
-      .assign_at_depth(depth, name, value);
+  pub fn assign(&mut self, id: usize, value: Value) {
+    match self.resolver.binding(id) {
+      Binding::Local { depth, slot } => self
+        .environment
+        .borrow_mut()
+        .assign_at_depth(depth, slot, value),
+      Binding::Global(slot) => self.globals[slot] = value,
+    }
   }
 
-  pub fn eval_expr(&mut self, expr: &Box<Expr>) -> Result<Value, RuntimeError> {
-    let value = match &**expr {
+  pub fn eval_expr(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+    self.tick()?;
+    let value = match expr {
       Expr::Unary { op, right } => self.eval_unary_expr(op, right)?,
       Expr::Binary { left, op, right } => self.eval_binary_expr(left, op, right)?,
       Expr::Grouping(expr) => self.eval_expr(expr)?,
       Expr::Literal(value) => value.clone(),
-      Expr::Variable { id, variable } => self.eval_variable(id, variable)?,
-      Expr::Assignment { id, variable, expr } => self.eval_assignment(id, variable, expr)?,
+      Expr::Variable { id, .. } => self.get(*id),
+      Expr::Assignment { id, expr, .. } => self.eval_assignment(*id, expr)?,
       Expr::Logical { left, op, right } => self.eval_logical_expr(left, op, right)?,
       Expr::FunCall {
         callee,
@@ -94,12 +346,12 @@ impl<'a> Interpreter<'a> {
     Ok(value)
   }
 
-  fn eval_unary_expr(&mut self, op: &Token, right: &Box<Expr>) -> Result<Value, RuntimeError> {
+  fn eval_unary_expr(&mut self, op: &Token, right: &Expr) -> Result<Value, RuntimeError> {
     let value = self.eval_expr(right)?;
     let result = match op.kind() {
       TokenKind::Minus => match value {
         Value::Number(n) => Value::Number(-n),
-        _ => return Err(RuntimeError::ExpectedNumber(op.clone())),
+        _ => return Err(RuntimeErrorKind::ExpectedNumber(op.clone()).into()),
       },
       TokenKind::Bang => Value::Bool(value.is_falsy()),
       _ => panic!("eval unary node with non-unary token"),
@@ -110,53 +362,47 @@ impl<'a> Interpreter<'a> {
 
   fn eval_binary_expr(
     &mut self,
-    left: &Box<Expr>,
+    left: &Expr,
     op: &Token,
-    right: &Box<Expr>,
+    right: &Expr,
   ) -> Result<Value, RuntimeError> {
     let left_val = self.eval_expr(left)?;
     let right_val = self.eval_expr(right)?;
+    self.binary(left_val, op, right_val)
+  }
+
+  /// Applies the binary operator `op` to two values.
+  pub(crate) fn binary(
+    &self,
+    left_val: Value,
+    op: &Token,
+    right_val: Value,
+  ) -> Result<Value, RuntimeError> {
     let result = match op.kind() {
       TokenKind::Star => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Number(a * b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
       TokenKind::Slash => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Number(a / b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
 
       TokenKind::Plus => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
-        (Value::Str(a), Value::Str(b)) => Value::Str(a.clone() + b),
+        (Value::Str(a), Value::Str(b)) => Value::Str(self.concat(a, b, op)?),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
       TokenKind::Minus => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Number(a - b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
 
@@ -164,61 +410,33 @@ impl<'a> Interpreter<'a> {
         (Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
         (Value::Str(a), Value::Str(b)) => Value::Bool(a > b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
       TokenKind::GreaterEqual => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Bool(a >= b),
         (Value::Str(a), Value::Str(b)) => Value::Bool(a >= b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
       TokenKind::Less => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
         (Value::Str(a), Value::Str(b)) => Value::Bool(a < b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
       TokenKind::LessEqual => match (&left_val, &right_val) {
         (Value::Number(a), Value::Number(b)) => Value::Bool(a <= b),
         (Value::Str(a), Value::Str(b)) => Value::Bool(a <= b),
         _ => {
-          return Err(RuntimeError::UndefinedOpBetween(
-            left_val,
-            op.clone(),
-            right_val,
-          ));
+          return Err(RuntimeErrorKind::UndefinedOpBetween(left_val, op.clone(), right_val).into());
         }
       },
 
-      TokenKind::EqualEqual => match (&left_val, &right_val) {
-        (Value::Number(a), Value::Number(b)) => Value::Bool(a == b),
-        (Value::Str(a), Value::Str(b)) => Value::Bool(a == b),
-        (Value::Bool(a), Value::Bool(b)) => Value::Bool(a == b),
-        (Value::Nil, Value::Nil) => Value::Bool(true),
-        _ => Value::Bool(false),
-      },
-      TokenKind::BangEqual => match (&left_val, &right_val) {
-        (Value::Number(a), Value::Number(b)) => Value::Bool(a != b),
-        (Value::Str(a), Value::Str(b)) => Value::Bool(a != b),
-        (Value::Bool(a), Value::Bool(b)) => Value::Bool(a != b),
-        (Value::Nil, Value::Nil) => Value::Bool(false),
-        _ => Value::Bool(true),
-      },
+      TokenKind::EqualEqual => Value::Bool(is_equal(&left_val, &right_val)),
+      TokenKind::BangEqual => Value::Bool(!is_equal(&left_val, &right_val)),
 
       _ => panic!("binary node received non-binary token"),
     };
@@ -226,30 +444,18 @@ impl<'a> Interpreter<'a> {
     Ok(result)
   }
 
-  fn eval_variable(&self, id: &usize, variable: &Token) -> Result<Value, RuntimeError> {
-    let name = variable.extract_identifier();
-    let value = self.get(*id, name);
-    Ok(value)
-  }
-
-  fn eval_assignment(
-    &mut self,
-    id: &usize,
-    variable: &Token,
-    expr: &Box<Expr>,
-  ) -> Result<Value, RuntimeError> {
+  fn eval_assignment(&mut self, id: usize, expr: &Expr) -> Result<Value, RuntimeError> {
     let value = self.eval_expr(expr)?;
-    let name = variable.extract_identifier();
-    self.assign(*id, name, &value);
+    self.assign(id, value.clone());
 
     Ok(value)
   }
 
   fn eval_logical_expr(
     &mut self,
-    left: &Box<Expr>,
+    left: &Expr,
     op: &Token,
-    right: &Box<Expr>,
+    right: &Expr,
   ) -> Result<Value, RuntimeError> {
     let value = match op.kind() {
       TokenKind::Or => {
@@ -278,30 +484,171 @@ impl<'a> Interpreter<'a> {
 
   fn eval_fun_call(
     &mut self,
-    callee: &Box<Expr>,
+    callee: &Expr,
+    paren: &Token,
+    args: &[Box<Expr>],
+  ) -> Result<Value, RuntimeError> {
+    let (callable, args) = self.eval_call_parts(callee, paren, args)?;
+    self.call(callable, args, paren)
+  }
+
+  /// Evaluates the callee and the arguments of a call, failing unless
+  /// the program may call the callee with them.
+  fn eval_call_parts(
+    &mut self,
+    callee: &Expr,
     paren: &Token,
-    args: &Vec<Box<Expr>>,
+    args: &[Box<Expr>],
+  ) -> Result<(Rc<dyn LoxCallable>, Vec<Value>), RuntimeError> {
+    let Value::Callable(callable) = self.eval_expr(callee)? else {
+      return Err(RuntimeErrorKind::ExpectedCallable(paren.clone()).into());
+    };
+    if callable.arity() != args.len() {
+      return Err(RuntimeErrorKind::CallableBadArgsCount(paren.clone()).into());
+    }
+    let args: Vec<Value> = args
+      .iter()
+      .map(|arg| self.eval_expr(arg))
+      .collect::<Result<_, _>>()?;
+    if let Some(capability) = callable.capability() {
+      self.check_capability(capability, paren)?;
+    }
+    Ok((callable, args))
+  }
+
+  fn call(
+    &mut self,
+    callable: Rc<dyn LoxCallable>,
+    args: Vec<Value>,
+    call_site: &Token,
   ) -> Result<Value, RuntimeError> {
-    let value = self.eval_expr(callee)?;
-    if let Value::Callable(callable) = value {
-      if callable.arity() != args.len() {
-        return Err(RuntimeError::CallableBadArgsCount(paren.clone()));
+    self.check_stack(call_site)?;
+    self
+      .frames
+      .push(Frame::new(Rc::clone(&callable), call_site.clone()));
+    let result = callable
+      .call(self, args)
+      .and_then(|value| self.check_memory(call_site).map(|()| value));
+    let frame = self.frames.pop().expect("expected the frame of this call");
+    result.map_err(|mut err| {
+      if err.exit_code().is_none() {
+        err.push_frame(frame);
       }
-      let args: Vec<Value> = args
-        .iter()
-        .map(|arg| self.eval_expr(arg))
-        .collect::<Result<_, _>>()?;
-      Ok(callable.call(self, args)?)
+      err
+    })
+  }
+
+  /// Makes a call in tail position in place of the function that
+  /// returned it. A function takes over the frame of that function,
+  /// called where it was, and runs without nesting, returning its signal
+  /// for the caller to go on with. Natives may call back into the
+  /// program, so they nest like any other call.
+  pub(crate) fn tail_call(&mut self, call: TailCall) -> Result<ControlSignal, RuntimeError> {
+    let TailCall {
+      callable,
+      args,
+      call_site,
+    } = call;
+    match (Rc::clone(&callable) as Rc<dyn Any>).downcast::<LoxFunction>() {
+      Ok(function) => {
+        if let Some(frame) = self.frames.last_mut() {
+          *frame = Frame::new(callable, frame.call_site().clone());
+        }
+        function.run(self, args)
+      }
+      Err(_) => self
+        .call(callable, args, &call_site)
+        .map(ControlSignal::Return),
+    }
+  }
+
+  pub(crate) fn check_capability(
+    &self,
+    capability: Capability,
+    token: &Token,
+  ) -> Result<(), RuntimeError> {
+    if self.limits.capabilities().contains(capability) {
+      Ok(())
     } else {
-      Err(RuntimeError::ExpectedCallable(paren.clone()))
+      Err(RuntimeErrorKind::PermissionDenied(token.clone(), capability).into())
     }
   }
 
+  /// Charges one unit of fuel, and fails if the fuel ran out or the
+  /// program was interrupted.
+  pub(crate) fn tick(&mut self) -> Result<(), RuntimeError> {
+    if let Some(fuel) = &mut self.fuel {
+      if *fuel == 0 {
+        return Err(RuntimeErrorKind::BudgetExhausted.into());
+      }
+      *fuel -= 1;
+    }
+    self.check_interrupt()
+  }
+
+  /// Fails if the program was interrupted.
+  pub(crate) fn check_interrupt(&self) -> Result<(), RuntimeError> {
+    // A plain load keeps the check cheap while no interrupt is pending.
+    if self.interrupt.load(Ordering::Relaxed) {
+      self.interrupt.store(false, Ordering::Relaxed);
+      return Err(RuntimeErrorKind::Interrupted.into());
+    }
+    Ok(())
+  }
+
+  /// Counts the memory held by a value a native created.
+  pub(crate) fn count_memory(&self, value: &mut Value) {
+    self.memory.count(value);
+  }
+
+  /// Fails if the call made the program go beyond its memory limit.
+  pub(crate) fn check_memory(&self, call_site: &Token) -> Result<(), RuntimeError> {
+    if let Some(max_memory) = self.limits.max_memory()
+      && self.memory.used() > max_memory
+    {
+      return Err(RuntimeErrorKind::MemoryLimit(call_site.clone()).into());
+    }
+    Ok(())
+  }
+
+  /// Concatenates two strings, failing if the result would take the
+  /// program beyond its memory limit.
+  fn concat(&self, a: &str, b: &str, op: &Token) -> Result<LoxString, RuntimeError> {
+    let len = a.len() + b.len();
+    if let Some(max_memory) = self.limits.max_memory()
+      && self.memory.used() + len > max_memory
+    {
+      return Err(RuntimeErrorKind::MemoryLimit(op.clone()).into());
+    }
+    let mut value = String::with_capacity(len);
+    value.push_str(a);
+    value.push_str(b);
+    Ok(LoxString::counted(value, &self.memory))
+  }
+
+  /// Fails before a call would nest deeper than the limits allow, so
+  /// that runaway recursion cannot overflow the native stack.
+  fn check_stack(&mut self, call_site: &Token) -> Result<(), RuntimeError> {
+    let marker = 0u8;
+    let address = std::ptr::addr_of!(marker) as usize;
+    if self.frames.is_empty() {
+      self.stack_base = address;
+    }
+    let stack_used = self.stack_base.abs_diff(address);
+    if self.frames.len() >= self.limits.max_call_depth()
+      || stack_used > self.limits.max_stack_bytes()
+    {
+      return Err(RuntimeErrorKind::StackOverflow(call_site.clone()).into());
+    }
+    Ok(())
+  }
+
   pub fn eval_stmt(&mut self, stmt: &Stmt) -> Result<ControlSignal, RuntimeError> {
+    self.tick()?;
     match &stmt {
-      Stmt::PrintStmt { expr } => self.eval_print_stmt(expr),
+      Stmt::PrintStmt { keyword, expr } => self.eval_print_stmt(keyword, expr),
       Stmt::ExprStmt { expr } => self.eval_expr_stmt(expr),
-      Stmt::VarDecl { variable, expr } => self.eval_var_decl(variable, expr),
+      Stmt::VarDecl { id, expr, .. } => self.eval_var_decl(*id, expr),
       Stmt::Block { stmts } => self.eval_block(stmts),
       Stmt::If {
         condition,
@@ -309,25 +656,30 @@ impl<'a> Interpreter<'a> {
         else_stmt,
       } => self.eval_if_stmt(condition, then_stmt, else_stmt),
       Stmt::While { condition, body } => self.eval_while_stmt(condition, body),
-      Stmt::FunDecl { name, params, body } => self.eval_fun_decl(name, params, body),
-      Stmt::Return { expr } => self.eval_return_stmt(expr),
+      Stmt::FunDecl { id, decl } => self.eval_fun_decl(*id, decl),
+      Stmt::Return { expr, .. } => self.eval_return_stmt(expr),
     }
   }
 
-  fn eval_print_stmt(&mut self, expr: &Box<Expr>) -> Result<ControlSignal, RuntimeError> {
+  fn eval_print_stmt(
+    &mut self,
+    keyword: &Token,
+    expr: &Expr,
+  ) -> Result<ControlSignal, RuntimeError> {
+    self.check_capability(Capability::IoStdout, keyword)?;
     let value = self.eval_expr(expr)?;
     writeln!(self.out, "{value}").expect("expected that writing to out buffer works");
     Ok(ControlSignal::None)
   }
 
-  fn eval_expr_stmt(&mut self, expr: &Box<Expr>) -> Result<ControlSignal, RuntimeError> {
+  fn eval_expr_stmt(&mut self, expr: &Expr) -> Result<ControlSignal, RuntimeError> {
     self.eval_expr(expr)?;
     Ok(ControlSignal::None)
   }
 
   fn eval_var_decl(
     &mut self,
-    variable: &Token,
+    id: usize,
     expr: &Option<Box<Expr>>,
   ) -> Result<ControlSignal, RuntimeError> {
     let value = if let Some(expr) = expr {
@@ -335,28 +687,25 @@ impl<'a> Interpreter<'a> {
     } else {
       Value::Nil
     };
-    let name = variable.extract_identifier().clone();
-    self.declare(name, value);
+    self.declare(id, value);
     Ok(ControlSignal::None)
   }
 
-  fn eval_block(&mut self, stmts: &Vec<Stmt>) -> Result<ControlSignal, RuntimeError> {
-    self.begin_scope();
+  fn eval_block(&mut self, stmts: &[Stmt]) -> Result<ControlSignal, RuntimeError> {
+    let mut scope = self.enter_block();
     for stmt in stmts {
-      let signal = self.eval_stmt(stmt)?;
+      let signal = scope.eval_stmt(stmt)?;
       let ControlSignal::None = signal else {
-        self.end_scope();
         return Ok(signal);
       };
     }
-    self.end_scope();
     Ok(ControlSignal::None)
   }
 
   fn eval_if_stmt(
     &mut self,
-    condition: &Box<Expr>,
-    then_stmt: &Box<Stmt>,
+    condition: &Expr,
+    then_stmt: &Stmt,
     else_stmt: &Option<Box<Stmt>>,
   ) -> Result<ControlSignal, RuntimeError> {
     let value = self.eval_expr(condition)?;
@@ -371,13 +720,16 @@ impl<'a> Interpreter<'a> {
 
   fn eval_while_stmt(
     &mut self,
-    condition: &Box<Expr>,
-    body: &Box<Stmt>,
+    condition: &Expr,
+    body: &Stmt,
   ) -> Result<ControlSignal, RuntimeError> {
     loop {
       let value = self.eval_expr(condition)?;
       if value.is_truthy() {
-        self.eval_stmt(body)?;
+        let signal = self.eval_stmt(body)?;
+        let ControlSignal::None = signal else {
+          return Ok(signal);
+        };
       } else {
         break;
       }
@@ -387,25 +739,62 @@ impl<'a> Interpreter<'a> {
 
   fn eval_fun_decl(
     &mut self,
-    name: &Token,
-    params: &Vec<Token>,
-    body: &Box<Stmt>,
+    id: usize,
+    decl: &Rc<FunctionDecl>,
   ) -> Result<ControlSignal, RuntimeError> {
-    let value = Value::Callable(Rc::new(LoxFunction::new(
-      name.clone(),
-      params.clone(),
-      body.clone(),
+    let function = self.heap.allocate_function(LoxFunction::new(
+      Rc::clone(decl),
       Rc::clone(&self.environment),
-    )));
-    self
-      .environment
-      .borrow_mut()
-      .declare(name.extract_identifier().clone(), value);
+    ));
+    self.declare(id, Value::Callable(function));
+    if self.heap.should_collect() {
+      self.collect_garbage();
+    }
     Ok(ControlSignal::None)
   }
 
-  fn eval_return_stmt(&mut self, expr: &Box<Expr>) -> Result<ControlSignal, RuntimeError> {
+  fn eval_return_stmt(&mut self, expr: &Expr) -> Result<ControlSignal, RuntimeError> {
+    // Inside a function, a returned call is left to the call of the
+    // function. A `return` at the top level only ends its statement.
+    if let Expr::FunCall {
+      callee,
+      paren,
+      args,
+    } = expr
+      && !self.frames.is_empty()
+    {
+      self.tick()?;
+      let (callable, args) = self.eval_call_parts(callee, paren, args)?;
+      return Ok(ControlSignal::TailCall(TailCall {
+        callable,
+        args,
+        call_site: paren.clone(),
+      }));
+    }
     let value = self.eval_expr(expr)?;
     Ok(ControlSignal::Return(value))
   }
 }
+
+/// Whether `==` holds between two values. Lists and maps are only equal
+/// to themselves, and functions to nothing.
+pub(crate) fn is_equal(left: &Value, right: &Value) -> bool {
+  match (left, right) {
+    (Value::Number(a), Value::Number(b)) => a == b,
+    (Value::Str(a), Value::Str(b)) => a == b,
+    (Value::Bool(a), Value::Bool(b)) => a == b,
+    (Value::Nil, Value::Nil) => true,
+    (Value::List(a), Value::List(b)) => Rc::ptr_eq(a, b),
+    (Value::Map(a), Value::Map(b)) => Rc::ptr_eq(a, b),
+    _ => false,
+  }
+}
+
+impl Drop for Interpreter<'_> {
+  /// Frees the cycles of the program, except for the values the host
+  /// still holds.
+  fn drop(&mut self) {
+    self.environment = Rc::new(RefCell::new(Environment::new()));
+    self.collect_garbage();
+  }
+}
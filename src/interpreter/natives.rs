@@ -0,0 +1,71 @@
+use std::io::BufRead;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Capability, Interpreter, NativeFunction, RuntimeErrorKind};
+use crate::parser::{FromLox, Value};
+
+/// Defines the natives every program can call, each behind the
+/// capability in brackets, if any:
+///
+/// - `args()` returns the script arguments as a list of strings
+/// - `exit(code)` stops the program with the given exit code
+/// - `gc()` frees the functions only kept alive by cycles and returns how many objects were freed
+/// - `write(value)` prints a value without a newline [io.stdout]
+/// - `read_line()` returns the next line of stdin, or nil at its end [io.stdin]
+/// - `read_file(path)` returns the contents of a file, or nil if it cannot be read [fs.read]
+/// - `write_file(path, contents)` writes a file and returns whether it worked [fs.write]
+/// - `env(name)` returns an environment variable, or nil if it is not set [env]
+/// - `clock()` returns the seconds since the Unix epoch [time]
+pub fn define_natives(interpreter: &mut Interpreter, script_args: Vec<String>) {
+  interpreter.define_native(NativeFunction::new("args", move || script_args.clone()));
+  interpreter.define_native(NativeFunction::with_interpreter("exit", 1, |_, args| {
+    let code = i32::from_lox(args[0].clone())?;
+    Err(RuntimeErrorKind::Exit(code).into())
+  }));
+  interpreter.define_native(NativeFunction::with_interpreter(
+    "gc",
+    0,
+    |interpreter, _| Ok(Value::Number(interpreter.collect_garbage() as f64)),
+  ));
+  interpreter.define_native(
+    NativeFunction::with_interpreter("write", 1, |interpreter, args| {
+      write!(interpreter.out(), "{}", args[0]).expect("expected that writing to out buffer works");
+      Ok(Value::Nil)
+    })
+    .with_capability(Capability::IoStdout),
+  );
+  interpreter.define_native(
+    NativeFunction::new("read_line", || {
+      let mut line = String::new();
+      match std::io::stdin().lock().read_line(&mut line) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(line.trim_end_matches(['\n', '\r']).to_string()),
+      }
+    })
+    .with_capability(Capability::IoStdin),
+  );
+  interpreter.define_native(
+    NativeFunction::new("read_file", |path: String| {
+      std::fs::read_to_string(path).ok()
+    })
+    .with_capability(Capability::FsRead),
+  );
+  interpreter.define_native(
+    NativeFunction::new("write_file", |path: String, contents: String| {
+      std::fs::write(path, contents).is_ok()
+    })
+    .with_capability(Capability::FsWrite),
+  );
+  interpreter.define_native(
+    NativeFunction::new("env", |name: String| std::env::var(name).ok())
+      .with_capability(Capability::Env),
+  );
+  interpreter.define_native(
+    NativeFunction::new("clock", || {
+      SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |elapsed| elapsed.as_secs_f64())
+    })
+    .with_capability(Capability::Time),
+  );
+}
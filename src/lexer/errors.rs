@@ -1,25 +1,93 @@
 use std::fmt;
 
-#[derive(Debug)]
+use crate::diagnostic::{Diagnostic, Span};
+use crate::source::SourceId;
+
+/// An error found while splitting the source code into tokens.
+#[derive(Debug, Clone)]
 pub struct LexerError {
+  source: SourceId,
   line: usize,
   col: usize,
+  len: usize,
   kind: LexerErrorKind,
 }
 
 impl LexerError {
-  pub fn new(line: usize, col: usize, kind: LexerErrorKind) -> LexerError {
-    LexerError { line, col, kind }
+  pub fn new(
+    source: SourceId,
+    line: usize,
+    col: usize,
+    len: usize,
+    kind: LexerErrorKind,
+  ) -> LexerError {
+    LexerError {
+      source,
+      line,
+      col,
+      len,
+      kind,
+    }
+  }
+
+  pub fn kind(&self) -> &LexerErrorKind {
+    &self.kind
+  }
+
+  pub fn source_id(&self) -> &SourceId {
+    &self.source
+  }
+
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn col(&self) -> usize {
+    self.col
+  }
+
+  /// The location of the offending chars.
+  pub fn span(&self) -> Span {
+    Span::new(self.source.clone(), self.line, self.col, self.len)
+  }
+
+  pub fn code(&self) -> &'static str {
+    match self.kind {
+      LexerErrorKind::UnexpectedChar(_) => "E0101",
+      LexerErrorKind::UnterminatedString => "E0102",
+      LexerErrorKind::InvalidNumber(_) => "E0103",
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    let span = self.span();
+    let diagnostic = Diagnostic::new(self.code(), self.kind.to_string());
+    match &self.kind {
+      LexerErrorKind::UnexpectedChar(_) => diagnostic.with_span(span, "not valid in Lox"),
+      LexerErrorKind::UnterminatedString => diagnostic
+        .with_span(span, "string starts here")
+        .with_help("add a closing '\"'"),
+      LexerErrorKind::InvalidNumber(_) => diagnostic
+        .with_span(span, "not a number")
+        .with_note("numbers are digits with at most one '.'"),
+    }
   }
 }
 
 impl fmt::Display for LexerError {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "main.lox:l{}:c{} {}", self.line, self.col, self.kind)
+    write!(
+      f,
+      "{}:{}:{} {}",
+      self.source, self.line, self.col, self.kind
+    )
   }
 }
 
-#[derive(Debug)]
+impl std::error::Error for LexerError {}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum LexerErrorKind {
   UnexpectedChar(char),
   UnterminatedString,
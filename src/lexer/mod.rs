@@ -1,24 +1,36 @@
 mod errors;
+mod symbol;
 mod tokens;
 
 pub use errors::*;
+pub use symbol::*;
 pub use tokens::*;
 
+use crate::source::SourceId;
+
 #[derive(Debug)]
 pub struct Lexer<'a> {
   source_code: &'a str,
+  source: SourceId,
   pos: usize,
   line: usize,
   col: usize,
+  start_pos: usize,
+  start_line: usize,
+  start_col: usize,
 }
 
 impl<'a> Lexer<'a> {
-  pub fn new(source_code: &'a str) -> Lexer<'a> {
+  pub fn new(source_code: &'a str, source: SourceId) -> Lexer<'a> {
     Lexer {
       source_code,
+      source,
       pos: 0,
       line: 1,
       col: 1,
+      start_pos: 0,
+      start_line: 1,
+      start_col: 1,
     }
   }
 
@@ -45,9 +57,31 @@ impl<'a> Lexer<'a> {
     None
   }
 
+  /// Remembers where the token whose first char was just consumed starts.
+  fn begin_token(&mut self) {
+    self.start_pos = self.pos - 1;
+    self.start_line = self.line;
+    self.start_col = self.col - 1;
+  }
+
+  fn token_len(&self) -> usize {
+    self.pos - self.start_pos
+  }
+
+  fn error_at_token(&self, kind: LexerErrorKind) -> LexerError {
+    LexerError::new(
+      self.source.clone(),
+      self.start_line,
+      self.start_col,
+      self.token_len(),
+      kind,
+    )
+  }
+
   pub fn tokenize(&mut self) -> Result<Vec<Token>, LexerError> {
     let mut tokens: Vec<Token> = Vec::new();
     while let Some(ch) = self.consume() {
+      self.begin_token();
       let token = match ch {
         // Single character tokens
         '(' => TokenKind::LeftParen,
@@ -82,18 +116,26 @@ impl<'a> Lexer<'a> {
 
         // Invalid
         other => {
-          return Err(LexerError::new(
-            self.line,
-            self.col,
-            LexerErrorKind::UnexpectedChar(other),
-          ));
+          return Err(self.error_at_token(LexerErrorKind::UnexpectedChar(other)));
         }
       };
 
-      tokens.push(Token::new(self.line, self.col, token));
+      tokens.push(Token::new(
+        self.source.clone(),
+        self.start_line,
+        self.start_col,
+        self.token_len(),
+        token,
+      ));
     }
 
-    tokens.push(Token::new(self.line, self.col, TokenKind::Eof));
+    tokens.push(Token::new(
+      self.source.clone(),
+      self.line,
+      self.col,
+      0,
+      TokenKind::Eof,
+    ));
 
     Ok(tokens)
   }
@@ -119,8 +161,10 @@ impl<'a> Lexer<'a> {
     }
     if self.peek().is_none() {
       return Err(LexerError::new(
-        self.line,
-        self.col,
+        self.source.clone(),
+        self.start_line,
+        self.start_col,
+        1,
         LexerErrorKind::UnterminatedString,
       ));
     }
@@ -138,9 +182,9 @@ impl<'a> Lexer<'a> {
       self.consume();
     }
 
-    let result: f64 = result.parse().map_err(|_err| {
-      LexerError::new(self.line, self.col, LexerErrorKind::InvalidNumber(result))
-    })?;
+    let result: f64 = result
+      .parse()
+      .map_err(|_err| self.error_at_token(LexerErrorKind::InvalidNumber(result)))?;
 
     Ok(TokenKind::Number(result))
   }
@@ -172,7 +216,7 @@ impl<'a> Lexer<'a> {
       "true" => TokenKind::True,
       "var" => TokenKind::Var,
       "while" => TokenKind::While,
-      _ => TokenKind::Identifier(value),
+      _ => TokenKind::Identifier(Symbol::intern(&value)),
     }
   }
 }
@@ -182,7 +226,7 @@ mod tests {
   use super::*;
 
   fn run_lexer(source_code: &str) -> Vec<Token> {
-    let mut lexer = Lexer::new(source_code);
+    let mut lexer = Lexer::new(source_code, SourceId::detached("main.lox"));
     lexer.tokenize().unwrap()
   }
 
@@ -213,7 +257,7 @@ mod tests {
     let tokens = run_lexer(source_code);
     assert_eq!(
       tokens[0].kind(),
-      &TokenKind::Identifier(String::from("my_identifier"))
+      &TokenKind::Identifier(Symbol::intern("my_identifier"))
     )
   }
 
@@ -228,6 +272,10 @@ mod tests {
   }
 
   #[test]
+  #[allow(
+    clippy::approx_constant,
+    reason = "the literal is lexed, not used as pi"
+  )]
   fn capture_numbers() {
     let source_code = "3.14";
     let tokens = run_lexer(source_code);
@@ -0,0 +1,90 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::{LazyLock, Mutex};
+
+/// An interned identifier. Every occurrence of a name maps to the same
+/// symbol, so symbols are compared and hashed like integers. The symbol
+/// keeps its name, so displaying it takes no lock.
+///
+/// Interned names are leaked and shared by the whole process: it keeps
+/// one copy of every distinct identifier any program used, for as long
+/// as it runs. That is little for programs written by people, but a
+/// host that runs generated programs with ever new names grows with them.
+#[derive(Clone, Copy)]
+pub struct Symbol {
+  id: u32,
+  name: &'static str,
+}
+
+#[derive(Default)]
+struct Interner {
+  symbols: HashMap<&'static str, Symbol>,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(Mutex::default);
+
+fn interner() -> std::sync::MutexGuard<'static, Interner> {
+  INTERNER
+    .lock()
+    .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl Symbol {
+  pub fn intern(name: &str) -> Symbol {
+    let mut interner = interner();
+    if let Some(symbol) = interner.symbols.get(name) {
+      return *symbol;
+    }
+    let id =
+      u32::try_from(interner.symbols.len()).expect("expected fewer than 2^32 distinct identifiers");
+    let name: &'static str = Box::leak(name.into());
+    let symbol = Symbol { id, name };
+    interner.symbols.insert(name, symbol);
+    symbol
+  }
+
+  pub fn as_str(&self) -> &'static str {
+    self.name
+  }
+}
+
+impl PartialEq for Symbol {
+  fn eq(&self, other: &Symbol) -> bool {
+    self.id == other.id
+  }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.id.hash(state);
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Symbol({:?})", self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn interns_equal_names_once() {
+    let a = Symbol::intern("interned");
+    assert_eq!(Symbol::intern("interned"), a);
+    assert_ne!(Symbol::intern("other"), a);
+    assert_eq!(a.as_str(), "interned");
+    assert_eq!(format!("{a} {a:?}"), "interned Symbol(\"interned\")");
+  }
+}
@@ -1,24 +1,61 @@
 use std::fmt;
 
+use super::Symbol;
+use crate::diagnostic::Span;
+use crate::source::SourceId;
+
 #[derive(Debug, Clone)]
 pub struct Token {
+  source: SourceId,
   line: usize,
   col: usize,
+  len: usize,
   kind: TokenKind,
 }
 
 impl Token {
-  pub fn new(line: usize, col: usize, kind: TokenKind) -> Token {
-    Token { line, col, kind }
+  pub fn new(source: SourceId, line: usize, col: usize, len: usize, kind: TokenKind) -> Token {
+    Token {
+      source,
+      line,
+      col,
+      len,
+      kind,
+    }
+  }
+
+  pub fn source_id(&self) -> &SourceId {
+    &self.source
+  }
+
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn col(&self) -> usize {
+    self.col
+  }
+
+  /// Length of the token in chars, as written in the source code.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn span(&self) -> Span {
+    Span::new(self.source.clone(), self.line, self.col, self.len)
   }
 
   pub fn kind(&self) -> &TokenKind {
     &self.kind
   }
 
-  pub fn extract_identifier(&self) -> &String {
+  pub fn extract_identifier(&self) -> Symbol {
     match self.kind() {
-      TokenKind::Identifier(name) => name,
+      TokenKind::Identifier(name) => *name,
       _ => panic!("expected identifier token"),
     }
   }
@@ -26,7 +63,11 @@ impl Token {
 
 impl fmt::Display for Token {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "line {} col {} token {}", self.line, self.col, self.kind)
+    write!(
+      f,
+      "{}:{}:{} token {}",
+      self.source, self.line, self.col, self.kind
+    )
   }
 }
 
@@ -53,7 +94,7 @@ pub enum TokenKind {
   Less,
   LessEqual,
 
-  Identifier(String),
+  Identifier(Symbol),
   String(String),
   Number(f64),
 
@@ -1,14 +1,46 @@
-use std::io::Write;
+use std::io::{Write, stderr};
 
+mod diagnostic;
 mod errors;
-use errors::LoxError;
+mod options;
+mod source;
+pub use diagnostic::{Diagnostic, Span};
+pub use errors::LoxError;
+pub use options::RunOptions;
+pub use source::{SourceId, SourceMap};
 
-use crate::{interpreter::Interpreter, lexer::Lexer, parser::Parser, resolver::Resolver};
+use crate::{
+  interpreter::{Interpreter, define_natives},
+  lexer::Lexer,
+  optimizer::Optimizer,
+  parser::{Parser, Stmt},
+  resolver::Resolver,
+};
 
+mod compiled;
 mod interpreter;
 mod lexer;
+mod optimizer;
 mod parser;
+mod repl;
 mod resolver;
+mod session;
+mod vm;
+
+pub use compiled::{CompiledProgram, LoadError};
+pub use interpreter::{
+  Capabilities, Capability, Completion, Frame, GcStats, IntoNative, Limits, NativeFunction,
+  RuntimeError, RuntimeErrorKind,
+};
+pub use lexer::{LexerError, LexerErrorKind, Symbol, Token, TokenKind};
+pub use parser::{
+  FromLox, IntoLox, IntoLoxResult, LoxCallable, LoxString, MemoryCounter, ParseError,
+  ParseErrorKind, Value,
+};
+pub use repl::Repl;
+pub use resolver::ResolveError;
+pub use session::Session;
+pub use vm::Backend;
 
 /// Starts interpreting the given file.
 ///
@@ -19,51 +51,310 @@ mod resolver;
 pub fn run_file<'a>(
   path: String,
   out_writer: Option<Box<dyn Write + 'a>>,
-) -> Result<(), Vec<LoxError>> {
-  match std::fs::read_to_string(path) {
+) -> Result<Completion, Vec<LoxError>> {
+  match std::fs::read_to_string(&path) {
     Err(err) => Err(vec![LoxError::IoError(Box::new(err))]),
-    Ok(source_code) => run_source_code(&source_code, out_writer),
+    Ok(source_code) => run_source_code_with_args(&path, &source_code, Vec::new(), out_writer),
   }
 }
 
 pub fn run_source_code<'a>(
   source_code: &str,
   out_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  run_source_code_with_args("<eval>", source_code, Vec::new(), out_writer)
+}
+
+/// Runs the source code, exposing `script_args` through the `args()` native.
+/// Errors refer to the source code by `name`, usually its path.
+///
+/// # Errors
+///
+/// Returns an error if the source code cannot be interpreted.
+pub fn run_source_code_with_args<'a>(
+  name: &str,
+  source_code: &str,
+  script_args: Vec<String>,
+  out_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  run_source_code_with_limits(
+    name,
+    source_code,
+    script_args,
+    Limits::default(),
+    out_writer,
+  )
+}
+
+/// Same as `run_source_code_with_args`, but the program fails with a
+/// runtime error instead of going beyond `limits`.
+///
+/// # Errors
+///
+/// Returns an error if the source code cannot be interpreted.
+pub fn run_source_code_with_limits<'a>(
+  name: &str,
+  source_code: &str,
+  script_args: Vec<String>,
+  limits: Limits,
+  out_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  run_source_code_with_options(
+    name,
+    source_code,
+    script_args,
+    limits,
+    RunOptions::default(),
+    out_writer,
+    None,
+  )
+}
+
+/// Same as `run_source_code_with_limits`, but runs the program as
+/// `options` say, such as on the VM backend. The bytecode listing and the
+/// trace of the VM go to `trace_writer`, or to stderr if there is none.
+///
+/// # Errors
+///
+/// Returns an error if the source code cannot be interpreted.
+pub fn run_source_code_with_options<'a>(
+  name: &str,
+  source_code: &str,
+  script_args: Vec<String>,
+  limits: Limits,
+  options: RunOptions,
+  out_writer: Option<Box<dyn Write + 'a>>,
+  trace_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  let stmts = parse(tokenize(name, source_code)?)?;
+  let mut interpreter = new_interpreter(script_args);
+  interpreter.set_limits(limits);
+  resolve(&mut interpreter, &stmts)?;
+  run_resolved(interpreter, stmts, options, out_writer, trace_writer)
+}
+
+/// Runs a program compiled by `compile_source_code`, without lexing,
+/// parsing or resolving it again.
+///
+/// # Errors
+///
+/// Returns an error if the program uses a native this interpreter does
+/// not define or grant, or fails at runtime.
+pub fn run_compiled_with_limits<'a>(
+  program: CompiledProgram,
+  script_args: Vec<String>,
+  limits: Limits,
+  out_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  run_compiled_with_options(
+    program,
+    script_args,
+    limits,
+    RunOptions::default(),
+    out_writer,
+    None,
+  )
+}
+
+/// Same as `run_compiled_with_limits`, but runs the program as `options`
+/// say, tracing to `trace_writer` or to stderr.
+///
+/// # Errors
+///
+/// Returns an error if the program uses a native this interpreter does
+/// not define or grant, or fails at runtime.
+pub fn run_compiled_with_options<'a>(
+  program: CompiledProgram,
+  script_args: Vec<String>,
+  limits: Limits,
+  options: RunOptions,
+  out_writer: Option<Box<dyn Write + 'a>>,
+  trace_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  let mut interpreter = new_interpreter(script_args);
+  interpreter.set_limits(limits);
+  let stmts = program
+    .link(interpreter.resolver_mut())
+    .map_err(|err| vec![LoxError::ResolveError(err)])?;
+  run_resolved(interpreter, stmts, options, out_writer, trace_writer)
+}
+
+/// Lexes, parses and resolves the source code without running it.
+///
+/// # Errors
+///
+/// Returns every static error found in the source code.
+pub fn check_source_code(name: &str, source_code: &str) -> Result<(), Vec<LoxError>> {
+  check_source_code_with_limits(name, source_code, Limits::default())
+}
+
+/// Same as `check_source_code`, but also reports the uses of the
+/// capabilities `limits` does not grant.
+///
+/// # Errors
+///
+/// Returns every static error found in the source code.
+pub fn check_source_code_with_limits(
+  name: &str,
+  source_code: &str,
+  limits: Limits,
 ) -> Result<(), Vec<LoxError>> {
-  let mut lexer = Lexer::new(source_code);
-  match lexer.tokenize() {
-    Err(err) => Err(vec![LoxError::LexerError(err)]),
-    Ok(tokens) => {
-      let mut parser = Parser::new(tokens);
-      let stmts = parser.parse();
-      if parser.errors().len() > 0 {
-        return Err(
-          parser
-            .errors()
-            .iter()
-            .map(|t| LoxError::ParseError(t.clone()))
-            .collect(),
-        );
-      }
-      let mut resolver = Resolver::new();
-      resolver.resolve(&stmts);
-      if resolver.errors().len() > 0 {
-        return Err(
-          resolver
-            .errors()
-            .iter()
-            .map(|t| LoxError::ResolveError(t.clone()))
-            .collect(),
-        );
-      }
-      let mut interpreter = Interpreter::new(resolver);
-      if let Some(out_writer) = out_writer {
-        interpreter.set_out_writer(out_writer);
-      }
-      match interpreter.interpret(stmts) {
-        Err(err) => Err(vec![LoxError::RuntimeError(err)]),
-        Ok(_) => Ok(()),
-      }
-    }
+  let stmts = parse(tokenize(name, source_code)?)?;
+  let mut interpreter = new_interpreter(Vec::new());
+  interpreter.set_limits(limits);
+  resolve(&mut interpreter, &stmts)
+}
+
+/// Lists the tokens of the source code, one per line.
+///
+/// # Errors
+///
+/// Returns an error if the source code cannot be lexed.
+pub fn dump_tokens(name: &str, source_code: &str) -> Result<String, Vec<LoxError>> {
+  let tokens = tokenize(name, source_code)?;
+  Ok(
+    tokens
+      .iter()
+      .map(|token| format!("{}:{} {}\n", token.line(), token.col(), token.kind()))
+      .collect(),
+  )
+}
+
+/// Prints the syntax tree of the source code, one top-level statement per line.
+///
+/// # Errors
+///
+/// Returns an error if the source code cannot be lexed or parsed.
+pub fn dump_ast(name: &str, source_code: &str) -> Result<String, Vec<LoxError>> {
+  let stmts = parse(tokenize(name, source_code)?)?;
+  Ok(stmts.iter().map(|stmt| format!("{stmt}\n")).collect())
+}
+
+/// Compiles the source code to bytecode and lists the instructions of
+/// every function, as the VM backend would run them.
+///
+/// # Errors
+///
+/// Returns every static error found in the source code.
+pub fn dump_bytecode(name: &str, source_code: &str) -> Result<String, Vec<LoxError>> {
+  dump_bytecode_with_options(name, source_code, RunOptions::default())
+}
+
+/// Same as `dump_bytecode`, for the program optimised as `options` say.
+///
+/// # Errors
+///
+/// Returns every static error found in the source code.
+pub fn dump_bytecode_with_options(
+  name: &str,
+  source_code: &str,
+  options: RunOptions,
+) -> Result<String, Vec<LoxError>> {
+  let stmts = parse(tokenize(name, source_code)?)?;
+  let mut interpreter = new_interpreter(Vec::new());
+  resolve(&mut interpreter, &stmts)?;
+  let stmts = optimize(&interpreter, stmts, options);
+  let script = vm::Compiler::new(interpreter.resolver(), false).compile(&stmts);
+  Ok(vm::disassemble(&script))
+}
+
+/// Lexes, parses and resolves the source code, and encodes the result
+/// as the bytes of a `.loxc` file. See `CompiledProgram` for the format.
+///
+/// # Errors
+///
+/// Returns every static error found in the source code.
+pub fn compile_source_code(name: &str, source_code: &str) -> Result<Vec<u8>, Vec<LoxError>> {
+  let stmts = parse(tokenize(name, source_code)?)?;
+  let mut interpreter = new_interpreter(Vec::new());
+  resolve(&mut interpreter, &stmts)?;
+  Ok(CompiledProgram::encode(
+    name,
+    source_code,
+    &stmts,
+    interpreter.resolver(),
+  ))
+}
+
+/// Serialises every error as one JSON object per line, for tools that
+/// annotate the source code. See `Diagnostic::to_json` for the fields.
+pub fn errors_to_json(errs: &[LoxError]) -> String {
+  errs.iter().map(|err| err.to_json() + "\n").collect()
+}
+
+fn tokenize(name: &str, source_code: &str) -> Result<Vec<Token>, Vec<LoxError>> {
+  Lexer::new(source_code, SourceId::detached(name))
+    .tokenize()
+    .map_err(|err| vec![LoxError::LexerError(err)])
+}
+
+fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>, Vec<LoxError>> {
+  let mut parser = Parser::new(tokens);
+  let stmts = parser.parse();
+  if !parser.errors().is_empty() {
+    return Err(
+      parser
+        .errors()
+        .iter()
+        .map(|t| LoxError::ParseError(t.clone()))
+        .collect(),
+    );
+  }
+  Ok(stmts)
+}
+
+fn new_interpreter<'a>(script_args: Vec<String>) -> Interpreter<'a> {
+  let mut interpreter = Interpreter::new(Resolver::new());
+  define_natives(&mut interpreter, script_args);
+  interpreter
+}
+
+fn run_resolved<'a>(
+  mut interpreter: Interpreter<'a>,
+  stmts: Vec<Stmt>,
+  options: RunOptions,
+  out_writer: Option<Box<dyn Write + 'a>>,
+  trace_writer: Option<Box<dyn Write + 'a>>,
+) -> Result<Completion, Vec<LoxError>> {
+  if let Some(out_writer) = out_writer {
+    interpreter.set_out_writer(out_writer);
+  }
+  let mut trace_writer = trace_writer.unwrap_or_else(|| Box::new(stderr()));
+  let stmts = optimize(&interpreter, stmts, options);
+  if options.dump_bytecode() {
+    let metered = interpreter.limits().fuel().is_some();
+    let script = vm::Compiler::new(interpreter.resolver(), metered).compile(&stmts);
+    write!(trace_writer, "{}", vm::disassemble(&script))
+      .map_err(|err| vec![LoxError::IoError(Box::new(err))])?;
+  }
+  if options.trace() {
+    interpreter.set_trace_writer(Some(trace_writer));
+  }
+  let completion = match options.backend() {
+    Backend::TreeWalker => interpreter.interpret(stmts),
+    Backend::Vm => vm::run(&mut interpreter, &stmts),
+  };
+  completion.map_err(|err| vec![LoxError::RuntimeError(err)])
+}
+
+fn optimize(interpreter: &Interpreter, stmts: Vec<Stmt>, options: RunOptions) -> Vec<Stmt> {
+  match options.opt_level() {
+    0 => stmts,
+    _ => Optimizer::new(interpreter).optimize(stmts),
+  }
+}
+
+fn resolve(interpreter: &mut Interpreter, stmts: &[Stmt]) -> Result<(), Vec<LoxError>> {
+  let resolver = interpreter.resolver_mut();
+  resolver.resolve(stmts);
+  if !resolver.errors().is_empty() {
+    return Err(
+      resolver
+        .errors()
+        .iter()
+        .map(|t| LoxError::ResolveError(t.clone()))
+        .collect(),
+    );
   }
+  Ok(())
 }
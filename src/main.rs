@@ -1,18 +1,571 @@
-fn main() {
-  let args: Vec<String> = std::env::args().collect();
-
-  if args.len() == 2 {
-    let path = args[1].clone();
-    match rlox::run_file(path, None) {
-      Err(errs) => {
-        println!("One or more errors encountered: ");
-        for err in errs {
-          println!("{err}");
-        }
+use std::io::{BufRead, Read, Write, stderr, stdin, stdout};
+use std::path::Path;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use rlox::{
+  Backend, Capabilities, Capability, CompiledProgram, Completion, Diagnostic, Limits, LoxError,
+  RunOptions, RuntimeErrorKind,
+};
+
+const USAGE: &str = "\
+Usage: rlox [COMMAND] [FILE | -e CODE | -] [--] [ARGS...]
+
+Commands:
+  run       run a script or a compiled .loxc program (the default)
+  check     lex, parse and resolve a script without running it
+  tokens    print the tokens of a script
+  ast       print the syntax tree of a script
+  bytecode  print the bytecode the VM runs for a script
+  compile   resolve a script ahead of time into a .loxc program
+  repl      start the interactive REPL (the default without a script)
+
+Options:
+  -e CODE                      run CODE instead of a file
+  -                            read the script from stdin
+  --                           pass every following argument to the script
+  -o FILE                      where compile writes the program (default: the
+                               script's path with a .loxc extension)
+  --error-format=human|json    print errors for humans, or one JSON object per line
+  --backend=tree|vm            run scripts on the tree-walking interpreter (the
+                               default) or compile them to bytecode for the VM
+  --trace                      print the stack and each instruction the VM runs
+                               to stderr
+  --dump-bytecode              print the bytecode of a script to stderr before
+                               running it
+  --opt-level=0|1              run scripts as written (the default), or fold
+                               constant expressions and drop dead branches
+  --max-call-depth=N           fail when calls nest deeper than N (default 1000)
+  --fuel=N                     fail after evaluating N statements and expressions
+  --max-memory=BYTES           fail when values would hold more than BYTES
+  --allow=CAP,...              only grant the listed capabilities (default all):
+                               io.stdout, io.stdin, fs.read, fs.write, env, time
+  -h, --help                   print this message
+";
+
+// Exit codes from sysexits.h.
+const EX_USAGE: u8 = 64;
+const EX_DATAERR: u8 = 65;
+const EX_NOINPUT: u8 = 66;
+const EX_SOFTWARE: u8 = 70;
+const EX_CANTCREAT: u8 = 73;
+const EX_IOERR: u8 = 74;
+// Shells report a process stopped by Ctrl-C with 128 + SIGINT.
+const EX_INTERRUPTED: u8 = 130;
+
+// Programs run on a thread with a large stack so that deep recursion
+// hits the call-depth limit long before the stack runs out.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+const MAX_STACK_BYTES: usize = 56 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+  Run,
+  Check,
+  Tokens,
+  Ast,
+  Bytecode,
+  Compile,
+  Repl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ErrorFormat {
+  Human,
+  Json,
+}
+
+enum Source {
+  File(String),
+  Stdin,
+  Inline(String),
+}
+
+struct Invocation {
+  command: Command,
+  source: Option<Source>,
+  script_args: Vec<String>,
+  /// Where `compile` writes the program.
+  output: Option<String>,
+  error_format: ErrorFormat,
+  limits: Limits,
+  options: RunOptions,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Invocation, String> {
+  let mut args = args.by_ref().peekable();
+  let command = match args.peek().map(String::as_str) {
+    Some("run") => Some(Command::Run),
+    Some("check") => Some(Command::Check),
+    Some("tokens") => Some(Command::Tokens),
+    Some("ast") => Some(Command::Ast),
+    Some("bytecode") => Some(Command::Bytecode),
+    Some("compile") => Some(Command::Compile),
+    Some("repl") => Some(Command::Repl),
+    _ => None,
+  };
+  if command.is_some() {
+    args.next();
+  }
+
+  let mut source: Option<Source> = None;
+  let mut script_args: Vec<String> = Vec::new();
+  let mut output: Option<String> = None;
+  let mut error_format = ErrorFormat::Human;
+  let mut limits = Limits::default().with_max_stack_bytes(MAX_STACK_BYTES);
+  let mut options = RunOptions::default();
+  while let Some(arg) = args.next() {
+    if source.is_some() {
+      if arg != "--" || !script_args.is_empty() {
+        script_args.push(arg);
+      }
+      continue;
+    }
+    match arg.as_str() {
+      "-h" | "--help" => return Err(String::new()),
+      "-e" => match args.next() {
+        Some(code) => source = Some(Source::Inline(code)),
+        None => return Err(String::from("-e expects an argument")),
+      },
+      "-" => source = Some(Source::Stdin),
+      "-o" => match args.next() {
+        Some(path) => output = Some(path),
+        None => return Err(String::from("-o expects an argument")),
+      },
+      "--error-format=human" => error_format = ErrorFormat::Human,
+      "--error-format=json" => error_format = ErrorFormat::Json,
+      "--backend=tree" => options = options.with_backend(Backend::TreeWalker),
+      "--backend=vm" => options = options.with_backend(Backend::Vm),
+      "--trace" => options = options.with_trace(true),
+      "--dump-bytecode" => options = options.with_dump_bytecode(true),
+      "--opt-level=0" => options = options.with_opt_level(0),
+      "--opt-level=1" => options = options.with_opt_level(1),
+      "--" => {
+        script_args.extend(args.by_ref());
+        break;
+      }
+      option if option.starts_with("--max-call-depth=") => {
+        limits = limits.with_max_call_depth(parse_number(option)?);
+      }
+      option if option.starts_with("--fuel=") => limits = limits.with_fuel(parse_number(option)?),
+      option if option.starts_with("--max-memory=") => {
+        limits = limits.with_max_memory(parse_number(option)?);
+      }
+      option if option.starts_with("--allow=") => {
+        limits = limits.with_capabilities(parse_capabilities(option)?);
+      }
+      option if option.starts_with('-') => return Err(format!("unknown option '{option}'")),
+      _ => source = Some(Source::File(arg)),
+    }
+  }
+
+  let command = match (command, &source) {
+    (Some(Command::Repl), Some(_)) => return Err(String::from("repl does not take a script")),
+    (Some(command), _) => command,
+    (None, Some(_)) => Command::Run,
+    (None, None) => Command::Repl,
+  };
+  if command == Command::Repl && options != RunOptions::default() {
+    return Err(String::from(
+      "repl always runs on the tree-walking interpreter, without \
+       --backend, --trace, --dump-bytecode or --opt-level",
+    ));
+  }
+  if command != Command::Repl && source.is_none() {
+    return Err(String::from("expected a script"));
+  }
+  if command != Command::Compile && output.is_some() {
+    return Err(String::from("only compile takes -o"));
+  }
+  if command == Command::Compile && output.is_none() {
+    match &source {
+      Some(Source::File(path)) => {
+        output = Some(Path::new(path).with_extension("loxc").display().to_string());
+      }
+      _ => {
+        return Err(String::from(
+          "compile expects -o FILE for a script that is not a file",
+        ));
       }
-      Ok(()) => println!("Lox done."),
     }
+  }
+
+  Ok(Invocation {
+    command,
+    source,
+    script_args,
+    output,
+    error_format,
+    limits,
+    options,
+  })
+}
+
+/// Parses the value of a `--name=N` option.
+fn parse_number<T: FromStr>(option: &str) -> Result<T, String> {
+  let (name, value) = option.split_once('=').unwrap_or((option, ""));
+  value
+    .parse()
+    .map_err(|_| format!("{name} expects a number, got '{value}'"))
+}
+
+/// Parses the comma-separated capabilities of `--allow=CAP,...`.
+fn parse_capabilities(option: &str) -> Result<Capabilities, String> {
+  let (_, value) = option.split_once('=').unwrap_or((option, ""));
+  value
+    .split(',')
+    .filter(|name| !name.is_empty())
+    .try_fold(Capabilities::none(), |capabilities, name| {
+      Ok(capabilities.with(name.parse::<Capability>()?))
+    })
+}
+
+impl Source {
+  fn name(&self) -> &str {
+    match self {
+      Source::File(path) => path,
+      Source::Stdin => "<stdin>",
+      Source::Inline(_) => "<eval>",
+    }
+  }
+}
+
+fn read_source(source: &Source) -> std::io::Result<String> {
+  match source {
+    Source::File(path) => std::fs::read_to_string(path),
+    Source::Inline(code) => Ok(code.clone()),
+    Source::Stdin => {
+      let mut source_code = String::new();
+      stdin().read_to_string(&mut source_code)?;
+      Ok(source_code)
+    }
+  }
+}
+
+fn report(errs: Vec<LoxError>, source_code: &str, error_format: ErrorFormat) -> ExitCode {
+  let interrupted = errs.iter().any(|err| {
+    matches!(err, LoxError::RuntimeError(err) if matches!(err.kind(), RuntimeErrorKind::Interrupted))
+  });
+  let code = if interrupted {
+    EX_INTERRUPTED
+  } else if errs.iter().all(LoxError::is_static) {
+    EX_DATAERR
   } else {
-    panic!("Usage: rlox <FILE_PATH>");
+    EX_SOFTWARE
+  };
+  match error_format {
+    ErrorFormat::Human => {
+      for err in errs {
+        eprintln!("{}", err.render(source_code));
+      }
+    }
+    ErrorFormat::Json => eprint!("{}", rlox::errors_to_json(&errs)),
+  }
+  ExitCode::from(code)
+}
+
+fn report_io_error(message: String, error_format: ErrorFormat, code: u8) -> ExitCode {
+  match error_format {
+    ErrorFormat::Human => eprintln!("rlox: {message}"),
+    ErrorFormat::Json => eprintln!("{}", Diagnostic::new("E0001", message).to_json()),
+  }
+  ExitCode::from(code)
+}
+
+/// The exit code of a script that ran, once its output is flushed.
+fn finish(
+  result: Result<Completion, Vec<LoxError>>,
+  source_code: &str,
+  error_format: ErrorFormat,
+) -> ExitCode {
+  stdout().flush().expect("expected that stdout is writable");
+  match result {
+    Ok(Completion::Finished) => ExitCode::SUCCESS,
+    Ok(Completion::Exit(code)) => exit_code(code),
+    Err(errs) => report(errs, source_code, error_format),
+  }
+}
+
+/// Runs a program written by `rlox compile`. Its errors point into the
+/// source code stored along with it.
+fn run_compiled(
+  path: &str,
+  script_args: Vec<String>,
+  limits: Limits,
+  options: RunOptions,
+  error_format: ErrorFormat,
+) -> ExitCode {
+  let bytes = match std::fs::read(path) {
+    Ok(bytes) => bytes,
+    Err(err) => return report_io_error(format!("{path}: {err}"), error_format, EX_NOINPUT),
+  };
+  let program = match CompiledProgram::decode(&bytes) {
+    Ok(program) => program,
+    Err(err) => return report(vec![LoxError::LoadError(err)], "", error_format),
+  };
+  let source_code = program.source_code().to_string();
+  let result = rlox::run_compiled_with_options(program, script_args, limits, options, None, None);
+  finish(result, &source_code, error_format)
+}
+
+/// Exit codes outside of 0..=255 wrap around like they do on unix.
+fn exit_code(code: i32) -> ExitCode {
+  ExitCode::from(code as u8)
+}
+
+fn print_output(
+  result: Result<String, Vec<LoxError>>,
+  source_code: &str,
+  error_format: ErrorFormat,
+) -> ExitCode {
+  match result {
+    Ok(output) => {
+      print!("{output}");
+      ExitCode::SUCCESS
+    }
+    Err(errs) => report(errs, source_code, error_format),
+  }
+}
+
+static INTERRUPT: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+/// Whether the REPL is waiting for input, in which case Ctrl-C ends the
+/// process as it would without a handler.
+static WAITING_FOR_INPUT: AtomicBool = AtomicBool::new(false);
+
+// The layouts of `struct sigaction` below were checked against the C
+// headers of these targets only; elsewhere Ctrl-C keeps its default
+// behaviour.
+#[cfg(all(
+  target_os = "linux",
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sys {
+  const SA_RESTART: i32 = 0x1000_0000;
+
+  #[repr(C)]
+  pub struct SigAction {
+    handler: extern "C" fn(i32),
+    mask: [u64; 16],
+    flags: i32,
+    restorer: usize,
+  }
+
+  /// Calls `handler` without blocking other signals, restarting the
+  /// reads and writes it interrupts.
+  pub fn action(handler: extern "C" fn(i32)) -> SigAction {
+    SigAction {
+      handler,
+      mask: [0; 16],
+      flags: SA_RESTART,
+      restorer: 0,
+    }
+  }
+}
+
+#[cfg(all(
+  target_os = "macos",
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod sys {
+  const SA_RESTART: i32 = 0x0002;
+
+  #[repr(C)]
+  pub struct SigAction {
+    handler: extern "C" fn(i32),
+    mask: u32,
+    flags: i32,
+  }
+
+  /// Calls `handler` without blocking other signals, restarting the
+  /// reads and writes it interrupts.
+  pub fn action(handler: extern "C" fn(i32)) -> SigAction {
+    SigAction {
+      handler,
+      mask: 0,
+      flags: SA_RESTART,
+    }
+  }
+}
+
+/// Makes Ctrl-C set `interrupt` instead of killing the process, so
+/// that the running program is cancelled with a runtime error. Only
+/// called right before a program runs, so that reading the program
+/// can still be stopped with Ctrl-C.
+#[cfg(all(
+  any(target_os = "linux", target_os = "macos"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn interrupt_on_ctrl_c(interrupt: Arc<AtomicBool>) {
+  const SIGINT: i32 = 2;
+  const SIG_DFL: usize = 0;
+
+  unsafe extern "C" {
+    fn sigaction(signum: i32, act: *const sys::SigAction, old: *mut sys::SigAction) -> i32;
+    fn signal(signum: i32, handler: usize) -> usize;
+    fn _exit(status: i32) -> !;
+  }
+
+  extern "C" fn on_sigint(_: i32) {
+    if WAITING_FOR_INPUT.load(Ordering::Relaxed) {
+      // SAFETY: `_exit` is async-signal-safe.
+      unsafe { _exit(i32::from(EX_INTERRUPTED)) }
+    }
+    if let Some(interrupt) = INTERRUPT.get() {
+      interrupt.store(true, Ordering::Relaxed);
+    }
+  }
+
+  if INTERRUPT.set(interrupt).is_ok() {
+    // SAFETY: the handler only performs atomic operations and `_exit`.
+    let failed = unsafe { sigaction(SIGINT, &sys::action(on_sigint), std::ptr::null_mut()) } != 0;
+    if failed {
+      // SAFETY: restoring the default action installs no handler.
+      unsafe {
+        signal(SIGINT, SIG_DFL);
+      }
+    }
+  }
+}
+
+#[cfg(not(all(
+  any(target_os = "linux", target_os = "macos"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+fn interrupt_on_ctrl_c(_interrupt: Arc<AtomicBool>) {}
+
+/// The input of the REPL, which flags the time spent waiting for it.
+struct ReplInput<R>(R);
+
+impl<R: Read> Read for ReplInput<R> {
+  fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+    self.0.read(buf)
+  }
+}
+
+impl<R: BufRead> BufRead for ReplInput<R> {
+  fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+    WAITING_FOR_INPUT.store(true, Ordering::Relaxed);
+    let buf = self.0.fill_buf();
+    WAITING_FOR_INPUT.store(false, Ordering::Relaxed);
+    buf
+  }
+
+  fn consume(&mut self, amount: usize) {
+    self.0.consume(amount);
+  }
+}
+
+fn main() -> ExitCode {
+  std::thread::Builder::new()
+    .stack_size(STACK_SIZE)
+    .spawn(run)
+    .expect("expected that the interpreter thread can be spawned")
+    .join()
+    .unwrap_or(ExitCode::from(EX_SOFTWARE))
+}
+
+fn run() -> ExitCode {
+  let mut invocation = match parse_args(std::env::args().skip(1)) {
+    Ok(invocation) => invocation,
+    Err(message) if message.is_empty() => {
+      print!("{USAGE}");
+      return ExitCode::SUCCESS;
+    }
+    Err(message) => {
+      eprint!("rlox: {message}\n\n{USAGE}");
+      return ExitCode::from(EX_USAGE);
+    }
+  };
+
+  let interrupt = Arc::new(AtomicBool::new(false));
+  invocation.limits = invocation.limits.with_interrupt(Arc::clone(&interrupt));
+
+  let Some(source) = invocation.source else {
+    let mut repl = rlox::Repl::new(Box::new(stdout()), Box::new(stderr()));
+    repl.set_limits(invocation.limits);
+    interrupt_on_ctrl_c(interrupt);
+    return match repl.run(ReplInput(stdin().lock())) {
+      Ok(()) => repl.exit_code().map_or(ExitCode::SUCCESS, exit_code),
+      Err(err) => {
+        eprintln!("rlox: {err}");
+        ExitCode::from(EX_IOERR)
+      }
+    };
+  };
+
+  if invocation.command == Command::Run
+    && let Source::File(path) = &source
+    && path.ends_with(".loxc")
+  {
+    interrupt_on_ctrl_c(interrupt);
+    return run_compiled(
+      path,
+      invocation.script_args,
+      invocation.limits,
+      invocation.options,
+      invocation.error_format,
+    );
+  }
+
+  let source_code = match read_source(&source) {
+    Ok(source_code) => source_code,
+    Err(err) => {
+      let message = format!("{}: {err}", source.name());
+      return report_io_error(message, invocation.error_format, EX_NOINPUT);
+    }
+  };
+
+  let name = source.name();
+  let error_format = invocation.error_format;
+  match invocation.command {
+    Command::Run => {
+      interrupt_on_ctrl_c(interrupt);
+      let result = rlox::run_source_code_with_options(
+        name,
+        &source_code,
+        invocation.script_args,
+        invocation.limits,
+        invocation.options,
+        None,
+        None,
+      );
+      finish(result, &source_code, error_format)
+    }
+    Command::Check => {
+      match rlox::check_source_code_with_limits(name, &source_code, invocation.limits) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(errs) => report(errs, &source_code, error_format),
+      }
+    }
+    Command::Tokens => print_output(
+      rlox::dump_tokens(name, &source_code),
+      &source_code,
+      error_format,
+    ),
+    Command::Ast => print_output(
+      rlox::dump_ast(name, &source_code),
+      &source_code,
+      error_format,
+    ),
+    Command::Bytecode => print_output(
+      rlox::dump_bytecode_with_options(name, &source_code, invocation.options),
+      &source_code,
+      error_format,
+    ),
+    Command::Compile => match rlox::compile_source_code(name, &source_code) {
+      Ok(bytes) => {
+        let output = invocation
+          .output
+          .expect("expected that compile has an output");
+        match std::fs::write(&output, bytes) {
+          Ok(()) => ExitCode::SUCCESS,
+          Err(err) => report_io_error(format!("{output}: {err}"), error_format, EX_CANTCREAT),
+        }
+      }
+      Err(errs) => report(errs, &source_code, error_format),
+    },
+    Command::Repl => unreachable!("expected that repl never has a source"),
   }
 }
@@ -0,0 +1,270 @@
+use std::rc::Rc;
+
+use crate::interpreter::Interpreter;
+use crate::lexer::{Token, TokenKind};
+use crate::parser::{Expr, FunctionDecl, LoxString, Stmt, Value};
+
+/// Simplifies resolved statements before they run. Operators whose
+/// operands are literals are replaced by their result, and the branches
+/// of `if` and `while` statements whose condition is a literal that
+/// never lets them run are dropped.
+///
+/// Operators are applied by the interpreter, so a folded result is the
+/// value the program would have computed. An operator that would fail,
+/// as in `"a" - 1`, stays in the tree and fails when the program runs,
+/// pointing at the same token. Variables are never folded, so the
+/// bindings of the resolver still hold.
+pub struct Optimizer<'i, 'a> {
+  interpreter: &'i Interpreter<'a>,
+}
+
+impl<'i, 'a> Optimizer<'i, 'a> {
+  pub fn new(interpreter: &'i Interpreter<'a>) -> Optimizer<'i, 'a> {
+    Optimizer { interpreter }
+  }
+
+  pub fn optimize(&self, stmts: Vec<Stmt>) -> Vec<Stmt> {
+    stmts
+      .into_iter()
+      .filter_map(|stmt| self.stmt(stmt))
+      .collect()
+  }
+
+  /// Returns the simplified statement, or nothing if it can never run.
+  fn stmt(&self, stmt: Stmt) -> Option<Stmt> {
+    let stmt = match stmt {
+      Stmt::ExprStmt { expr } => Stmt::ExprStmt {
+        expr: self.boxed(expr),
+      },
+      Stmt::PrintStmt { keyword, expr } => Stmt::PrintStmt {
+        keyword,
+        expr: self.boxed(expr),
+      },
+      Stmt::VarDecl { id, variable, expr } => Stmt::VarDecl {
+        id,
+        variable,
+        expr: expr.map(|expr| self.boxed(expr)),
+      },
+      // Kept even when empty, as the block is a scope of the resolver.
+      Stmt::Block { stmts } => Stmt::Block {
+        stmts: self.optimize(stmts),
+      },
+      Stmt::If {
+        condition,
+        then_stmt,
+        else_stmt,
+      } => {
+        let condition = self.boxed(condition);
+        if let Expr::Literal(value) = condition.as_ref() {
+          return match value.is_truthy() {
+            true => self.stmt(*then_stmt),
+            false => else_stmt.and_then(|stmt| self.stmt(*stmt)),
+          };
+        }
+        Stmt::If {
+          condition,
+          then_stmt: Box::new(self.body(*then_stmt)),
+          else_stmt: else_stmt.and_then(|stmt| self.stmt(*stmt)).map(Box::new),
+        }
+      }
+      Stmt::While { condition, body } => {
+        let condition = self.boxed(condition);
+        if let Expr::Literal(value) = condition.as_ref()
+          && value.is_falsy()
+        {
+          return None;
+        }
+        Stmt::While {
+          condition,
+          body: Box::new(self.body(*body)),
+        }
+      }
+      Stmt::FunDecl { id, decl } => {
+        // Declarations are only shared once the program runs.
+        let decl = match Rc::try_unwrap(decl) {
+          Ok(FunctionDecl { name, params, body }) => Rc::new(FunctionDecl {
+            name,
+            params,
+            body: self.body(body),
+          }),
+          Err(decl) => decl,
+        };
+        Stmt::FunDecl { id, decl }
+      }
+      Stmt::Return { keyword, expr } => Stmt::Return {
+        keyword,
+        expr: self.boxed(expr),
+      },
+    };
+    Some(stmt)
+  }
+
+  /// Simplifies a statement that must stay, as the body of another.
+  fn body(&self, stmt: Stmt) -> Stmt {
+    self
+      .stmt(stmt)
+      .unwrap_or_else(|| Stmt::Block { stmts: Vec::new() })
+  }
+
+  /// Simplifies the expression in its own box.
+  fn boxed(&self, mut expr: Box<Expr>) -> Box<Expr> {
+    let taken = std::mem::replace(expr.as_mut(), Expr::Literal(Value::Nil));
+    *expr = self.expr(taken);
+    expr
+  }
+
+  fn expr(&self, expr: Expr) -> Expr {
+    match expr {
+      Expr::Unary { op, right } => {
+        let right = self.boxed(right);
+        match (op.kind(), right.as_ref()) {
+          (TokenKind::Minus, Expr::Literal(Value::Number(n))) => Expr::Literal(Value::Number(-n)),
+          (TokenKind::Bang, Expr::Literal(value)) => Expr::Literal(Value::Bool(value.is_falsy())),
+          _ => Expr::Unary { op, right },
+        }
+      }
+      Expr::Binary { left, op, right } => {
+        let left = self.boxed(left);
+        let right = self.boxed(right);
+        match self.fold_binary(&left, &op, &right) {
+          Some(value) => Expr::Literal(value),
+          None => Expr::Binary { left, op, right },
+        }
+      }
+      Expr::Grouping(expr) => match self.expr(*expr) {
+        Expr::Literal(value) => Expr::Literal(value),
+        expr => Expr::Grouping(Box::new(expr)),
+      },
+      Expr::Logical { left, op, right } => {
+        let left = self.boxed(left);
+        let right = self.boxed(right);
+        let Expr::Literal(value) = left.as_ref() else {
+          return Expr::Logical { left, op, right };
+        };
+        // The right operand is only evaluated when the left one does
+        // not decide the result.
+        match (op.kind(), value.is_truthy(), right.as_ref()) {
+          (TokenKind::Or, true, _) => Expr::Literal(Value::Bool(true)),
+          (TokenKind::And, false, _) => Expr::Literal(Value::Bool(false)),
+          (_, _, Expr::Literal(value)) => Expr::Literal(Value::Bool(value.is_truthy())),
+          _ => Expr::Logical { left, op, right },
+        }
+      }
+      Expr::Assignment { id, variable, expr } => Expr::Assignment {
+        id,
+        variable,
+        expr: self.boxed(expr),
+      },
+      Expr::FunCall {
+        callee,
+        paren,
+        args,
+      } => Expr::FunCall {
+        callee: self.boxed(callee),
+        paren,
+        args: args.into_iter().map(|arg| self.boxed(arg)).collect(),
+      },
+      expr @ (Expr::Literal(_) | Expr::Variable { .. }) => expr,
+    }
+  }
+
+  /// The result of a binary operator on two literals, unless it fails.
+  fn fold_binary(&self, left: &Expr, op: &Token, right: &Expr) -> Option<Value> {
+    let (Expr::Literal(left), Expr::Literal(right)) = (left, right) else {
+      return None;
+    };
+    let value = self
+      .interpreter
+      .binary(left.clone(), op, right.clone())
+      .ok()?;
+    // Strings in the tree are not counted as memory built by the
+    // program, like the literals of the source code.
+    Some(match value {
+      Value::Str(s) => Value::Str(LoxString::from(s.as_str())),
+      value => value,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::source::SourceId;
+
+  fn parse(source_code: &str) -> Vec<Stmt> {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    Parser::new(tokens).parse()
+  }
+
+  fn optimize(source_code: &str) -> String {
+    let interpreter = Interpreter::new(Resolver::new());
+    let stmts = Optimizer::new(&interpreter).optimize(parse(source_code));
+    stmts.iter().map(|stmt| format!("{stmt}\n")).collect()
+  }
+
+  fn print(source_code: &str) -> String {
+    parse(source_code)
+      .iter()
+      .map(|stmt| format!("{stmt}\n"))
+      .collect()
+  }
+
+  #[test]
+  fn folds_constant_expressions() {
+    assert_eq!(
+      optimize(
+        "
+        print 60 * 60 * 24;
+        print (\"a\" + \"b\") == \"ab\";
+        print -(1 + 2) < 0 and !nil;
+        print false or x;
+        print f(1 + 1, x = -y);
+        "
+      ),
+      print(
+        "
+        print 86400;
+        print true;
+        print true;
+        print false or x;
+        print f(2, x = -y);
+        "
+      )
+    );
+  }
+
+  #[test]
+  fn keeps_operators_that_fail() {
+    let source_code = "print \"a\" - 1; print -\"a\"; print 1 < nil;";
+    assert_eq!(optimize(source_code), print(source_code));
+    assert_eq!(
+      optimize("print (2 * 3) + \"a\";"),
+      print("print 6 + \"a\";")
+    );
+  }
+
+  #[test]
+  fn drops_dead_branches() {
+    assert_eq!(
+      optimize(
+        "
+        if (false) print 1; else print 2;
+        if (1 > 2) { print 3; }
+        while (nil) print 4;
+        fun f() { if (\"a\") return 1; if (0) return 2; while (true and x) if (!true) print 5; }
+        "
+      ),
+      print(
+        "
+        print 2;
+        fun f() { return 1; while (true and x) {} }
+        "
+      )
+    );
+  }
+}
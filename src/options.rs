@@ -0,0 +1,74 @@
+use crate::vm::Backend;
+
+/// How `run_source_code_with_options` and `run_compiled_with_options`
+/// run a program, as opposed to the `Limits` on what it may use.
+/// Sessions and the REPL always run on the tree-walking interpreter,
+/// without optimising, tracing or listing bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunOptions {
+  backend: Backend,
+  trace: bool,
+  dump_bytecode: bool,
+  opt_level: u8,
+}
+
+impl RunOptions {
+  /// Which backend runs the program, the tree-walking interpreter by
+  /// default.
+  pub fn with_backend(mut self, backend: Backend) -> RunOptions {
+    self.backend = backend;
+    self
+  }
+
+  /// Whether the VM prints its stack and each instruction to stderr
+  /// before running it, off by default. The tree-walking interpreter
+  /// does not trace.
+  pub fn with_trace(mut self, trace: bool) -> RunOptions {
+    self.trace = trace;
+    self
+  }
+
+  /// Whether to list the bytecode of the program, as the VM would run
+  /// it, before running it on either backend, off by default.
+  pub fn with_dump_bytecode(mut self, dump_bytecode: bool) -> RunOptions {
+    self.dump_bytecode = dump_bytecode;
+    self
+  }
+
+  /// How much the program is optimised before it runs. At 0 (the
+  /// default) it runs as written, so fuel is charged for every statement
+  /// and expression of the source, and at 1 or above constant
+  /// expressions are folded and branches that can never run are
+  /// dropped, which also saves the fuel they would use.
+  pub fn with_opt_level(mut self, opt_level: u8) -> RunOptions {
+    self.opt_level = opt_level;
+    self
+  }
+
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  pub fn trace(&self) -> bool {
+    self.trace
+  }
+
+  pub fn dump_bytecode(&self) -> bool {
+    self.dump_bytecode
+  }
+
+  pub fn opt_level(&self) -> u8 {
+    self.opt_level
+  }
+}
+
+impl Default for RunOptions {
+  fn default() -> RunOptions {
+    RunOptions {
+      backend: Backend::TreeWalker,
+      trace: false,
+      dump_bytecode: false,
+      opt_level: 0,
+    }
+  }
+}
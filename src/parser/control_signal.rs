@@ -1,6 +1,19 @@
-use super::Value;
+use std::rc::Rc;
+
+use super::{LoxCallable, Value};
+use crate::lexer::Token;
 
 pub enum ControlSignal {
   None,
   Return(Value),
+  /// A `return` of a call, which the function leaves to its caller to
+  /// make once the function returned, so that tail calls do not nest.
+  TailCall(TailCall),
+}
+
+/// A call in tail position, with its callee and arguments evaluated.
+pub struct TailCall {
+  pub callable: Rc<dyn LoxCallable>,
+  pub args: Vec<Value>,
+  pub call_site: Token,
 }
@@ -1,7 +1,10 @@
 use core::fmt;
 
+use crate::diagnostic::{Diagnostic, Span};
 use crate::lexer::Token;
 
+/// An error found while building the syntax tree, at the token
+/// the parser did not expect.
 #[derive(Debug, Clone)]
 pub struct ParseError {
   token: Token,
@@ -12,6 +15,35 @@ impl ParseError {
   pub fn at(token: Token, kind: ParseErrorKind) -> ParseError {
     ParseError { token, kind }
   }
+
+  pub fn kind(&self) -> &ParseErrorKind {
+    &self.kind
+  }
+
+  pub fn token(&self) -> &Token {
+    &self.token
+  }
+
+  pub fn span(&self) -> Span {
+    self.token.span()
+  }
+
+  pub fn code(&self) -> &'static str {
+    match self.kind {
+      ParseErrorKind::ExpectedExpression => "E0201",
+      ParseErrorKind::Expected(_) => "E0202",
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    let label = format!("found {}", self.token.kind());
+    let diagnostic =
+      Diagnostic::new(self.code(), self.kind.to_string()).with_span(self.token.span(), label);
+    match self.kind {
+      ParseErrorKind::Expected(";") => diagnostic.with_help("statements end with ';'"),
+      _ => diagnostic,
+    }
+  }
 }
 
 impl fmt::Display for ParseError {
@@ -20,7 +52,10 @@ impl fmt::Display for ParseError {
   }
 }
 
-#[derive(Debug, Clone)]
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum ParseErrorKind {
   ExpectedExpression,
   Expected(&'static str),
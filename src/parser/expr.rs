@@ -1,7 +1,7 @@
 use super::value::Value;
 use crate::lexer::Token;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum Expr {
   Unary {
     op: Token,
@@ -31,6 +31,10 @@ pub enum Expr {
   FunCall {
     callee: Box<Expr>,
     paren: Token,
+    #[allow(
+      clippy::vec_box,
+      reason = "arguments are boxed like every other operand"
+    )]
     args: Vec<Box<Expr>>,
   },
 }
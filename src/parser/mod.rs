@@ -1,8 +1,11 @@
+use std::rc::Rc;
+
 use crate::lexer::{Token, TokenKind};
 
 mod control_signal;
 mod errors;
 mod expr;
+mod printer;
 mod stmt;
 mod value;
 
@@ -25,6 +28,16 @@ impl Parser {
   /// The provided token list must always contain
   /// at least one token and end with an Eof.
   pub fn new(tokens: Vec<Token>) -> Parser {
+    Parser::with_var_id(tokens, 0)
+  }
+
+  /// Creates a parser whose expression ids continue after `curr_var_id`,
+  /// so that several parsed snippets can share one `Resolver`.
+  ///
+  /// # Panics
+  ///
+  /// Same as `Parser::new`.
+  pub fn with_var_id(tokens: Vec<Token>, curr_var_id: usize) -> Parser {
     match tokens.last() {
       Some(token) => match token.kind() {
         TokenKind::Eof => {}
@@ -36,7 +49,7 @@ impl Parser {
       tokens,
       pos: 0,
       errors: Vec::new(),
-      curr_var_id: 0,
+      curr_var_id,
     }
   }
 
@@ -54,7 +67,11 @@ impl Parser {
   }
 
   fn incr_var_id(&mut self) -> usize {
-    self.curr_var_id = self.curr_var_id + 1;
+    self.curr_var_id += 1;
+    self.curr_var_id
+  }
+
+  pub fn curr_var_id(&self) -> usize {
     self.curr_var_id
   }
 
@@ -133,7 +150,7 @@ impl Parser {
   fn consume_expect_identifier(&mut self) -> Result<Token, ParseError> {
     self
       .consume_expect_token(|t| matches!(t, TokenKind::Identifier(_)), "identifier")
-      .map(|t| t.clone())
+      .cloned()
   }
 
   fn synchronize(&mut self, error: ParseError) {
@@ -172,8 +189,9 @@ impl Parser {
     }
     self.consume_expect(TokenKind::Semicolon)?;
     Ok(Stmt::VarDecl {
+      id: self.incr_var_id(),
       variable: iden,
-      expr: expr.map(|t| Box::new(t)),
+      expr: expr.map(Box::new),
     })
   }
 
@@ -186,9 +204,8 @@ impl Parser {
     let body = self.block_stmt()?;
 
     Ok(Stmt::FunDecl {
-      name,
-      params,
-      body: Box::new(body),
+      id: self.incr_var_id(),
+      decl: Rc::new(FunctionDecl { name, params, body }),
     })
   }
 
@@ -219,10 +236,11 @@ impl Parser {
   }
 
   fn print_stmt(&mut self) -> Result<Stmt, ParseError> {
-    self.consume_expect(TokenKind::Print)?;
+    let keyword = self.consume_expect(TokenKind::Print)?.clone();
     let expr = self.expression()?;
     self.consume_expect(TokenKind::Semicolon)?;
     Ok(Stmt::PrintStmt {
+      keyword,
       expr: Box::new(expr),
     })
   }
@@ -253,7 +271,7 @@ impl Parser {
     Ok(Stmt::If {
       condition: Box::new(expr),
       then_stmt: Box::new(then_stmt),
-      else_stmt: else_stmt.map(|s| Box::new(s)),
+      else_stmt: else_stmt.map(Box::new),
     })
   }
 
@@ -282,21 +300,19 @@ impl Parser {
       initializer = Some(self.expr_stmt()?);
     }
 
-    let condition: Expr;
-    if self.peek_kind() == &TokenKind::Semicolon {
-      condition = Expr::Literal(Value::Bool(true));
+    let condition = if self.peek_kind() == &TokenKind::Semicolon {
+      Expr::Literal(Value::Bool(true))
     } else {
-      condition = self.expression()?;
-    }
+      self.expression()?
+    };
 
     self.consume_expect(TokenKind::Semicolon)?;
 
-    let increment: Option<Expr>;
-    if self.peek_kind() == &TokenKind::RightParen {
-      increment = None;
+    let increment = if self.peek_kind() == &TokenKind::RightParen {
+      None
     } else {
-      increment = Some(self.expression()?);
-    }
+      Some(self.expression()?)
+    };
 
     self.consume_expect(TokenKind::RightParen)?;
 
@@ -328,10 +344,11 @@ impl Parser {
   }
 
   fn return_stmt(&mut self) -> Result<Stmt, ParseError> {
-    self.consume_expect(TokenKind::Return)?;
+    let keyword = self.consume_expect(TokenKind::Return)?.clone();
     let expr = self.expression()?;
     self.consume_expect(TokenKind::Semicolon)?;
     Ok(Stmt::Return {
+      keyword,
       expr: Box::new(expr),
     })
   }
@@ -458,6 +475,10 @@ impl Parser {
     self.parse_binary(Self::unary, &[TokenKind::Star, TokenKind::Slash])
   }
 
+  #[allow(
+    clippy::never_loop,
+    reason = "runs at most once, breaking out to parse a call instead"
+  )]
   fn unary(&mut self) -> Result<Expr, ParseError> {
     loop {
       let token = self.peek();
@@ -493,6 +514,7 @@ impl Parser {
     Ok(expr)
   }
 
+  #[allow(clippy::vec_box, reason = "the arguments of `Expr::FunCall`")]
   fn arguments(&mut self) -> Result<Vec<Box<Expr>>, ParseError> {
     let mut args: Vec<Box<Expr>> = Vec::new();
     if self.peek_kind() != &TokenKind::RightParen {
@@ -511,8 +533,8 @@ impl Parser {
     let token = self.peek();
 
     let expr = match token.kind() {
-      TokenKind::Number(n) => Expr::Literal(Value::Number(n.clone())),
-      TokenKind::String(s) => Expr::Literal(Value::Str(s.clone())),
+      TokenKind::Number(n) => Expr::Literal(Value::Number(*n)),
+      TokenKind::String(s) => Expr::Literal(Value::Str(LoxString::from(s.as_str()))),
       TokenKind::True => Expr::Literal(Value::Bool(true)),
       TokenKind::False => Expr::Literal(Value::Bool(false)),
       TokenKind::Nil => Expr::Literal(Value::Nil),
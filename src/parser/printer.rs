@@ -0,0 +1,105 @@
+use std::fmt;
+
+use super::{Expr, FunctionDecl, Stmt, Value};
+
+/// Prints the tree as an s-expression, e.g. `(print (+ 1 (group 2)))`.
+impl fmt::Display for Expr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Expr::Unary { op, right } => write!(f, "({} {})", op.kind().name(), right),
+      Expr::Binary { left, op, right } | Expr::Logical { left, op, right } => {
+        write!(f, "({} {} {})", op.kind().name(), left, right)
+      }
+      Expr::Grouping(expr) => write!(f, "(group {})", expr),
+      Expr::Literal(Value::Str(s)) => write!(f, "{:?}", s),
+      Expr::Literal(value) => write!(f, "{}", value),
+      Expr::Variable { variable, .. } => write!(f, "{}", variable.extract_identifier()),
+      Expr::Assignment { variable, expr, .. } => {
+        write!(f, "(= {} {})", variable.extract_identifier(), expr)
+      }
+      Expr::FunCall { callee, args, .. } => {
+        write!(f, "(call {}", callee)?;
+        for arg in args {
+          write!(f, " {}", arg)?;
+        }
+        write!(f, ")")
+      }
+    }
+  }
+}
+
+impl fmt::Display for Stmt {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Stmt::ExprStmt { expr } => write!(f, "(expr {})", expr),
+      Stmt::PrintStmt { expr, .. } => write!(f, "(print {})", expr),
+      Stmt::VarDecl { variable, expr, .. } => match expr {
+        Some(expr) => write!(f, "(var {} {})", variable.extract_identifier(), expr),
+        None => write!(f, "(var {})", variable.extract_identifier()),
+      },
+      Stmt::Block { stmts } => {
+        write!(f, "(block")?;
+        for stmt in stmts {
+          write!(f, " {}", stmt)?;
+        }
+        write!(f, ")")
+      }
+      Stmt::If {
+        condition,
+        then_stmt,
+        else_stmt,
+      } => match else_stmt {
+        Some(else_stmt) => write!(f, "(if {} {} {})", condition, then_stmt, else_stmt),
+        None => write!(f, "(if {} {})", condition, then_stmt),
+      },
+      Stmt::While { condition, body } => write!(f, "(while {} {})", condition, body),
+      Stmt::FunDecl { decl, .. } => {
+        let FunctionDecl { name, params, body } = &**decl;
+        write!(f, "(fun {} (", name.extract_identifier())?;
+        for (i, param) in params.iter().enumerate() {
+          if i > 0 {
+            write!(f, " ")?;
+          }
+          write!(f, "{}", param.extract_identifier())?;
+        }
+        write!(f, ") {})", body)
+      }
+      Stmt::Return { expr, .. } => write!(f, "(return {})", expr),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::source::SourceId;
+
+  fn print_ast(source_code: &str) -> String {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    stmts
+      .iter()
+      .map(|s| s.to_string())
+      .collect::<Vec<_>>()
+      .join("\n")
+  }
+
+  #[test]
+  fn prints_expressions() {
+    assert_eq!(
+      print_ast("print -1 + 2 * (3 - x) or f(\"a\", nil);"),
+      "(print (or (+ (- 1) (* 2 (group (- 3 x)))) (call f \"a\" nil)))"
+    );
+  }
+
+  #[test]
+  fn prints_statements() {
+    assert_eq!(
+      print_ast("fun f(a, b) { if (a) return b; else { var c = 1; } }"),
+      "(fun f (a b) (block (if a (return b) (block (var c 1)))))"
+    );
+  }
+}
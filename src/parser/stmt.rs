@@ -1,15 +1,24 @@
+use std::rc::Rc;
+
 use super::Expr;
 use crate::lexer::Token;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub enum Stmt {
+  #[allow(
+    clippy::enum_variant_names,
+    reason = "an expression statement, not an `Expr`"
+  )]
   ExprStmt {
     expr: Box<Expr>,
   },
+  #[allow(clippy::enum_variant_names, reason = "the statement form of `print`")]
   PrintStmt {
+    keyword: Token,
     expr: Box<Expr>,
   },
   VarDecl {
+    id: usize,
     variable: Token,
     expr: Option<Box<Expr>>,
   },
@@ -26,11 +35,21 @@ pub enum Stmt {
     body: Box<Stmt>,
   },
   FunDecl {
-    name: Token,
-    params: Vec<Token>,
-    body: Box<Stmt>,
+    id: usize,
+    decl: Rc<FunctionDecl>,
   },
   Return {
+    keyword: Token,
     expr: Box<Expr>,
   },
 }
+
+/// The parts of a function declaration. They are shared by the AST and
+/// by every function created from the declaration, so that running a
+/// `fun` statement does not copy the body.
+#[derive(Debug)]
+pub struct FunctionDecl {
+  pub name: Token,
+  pub params: Vec<Token>,
+  pub body: Stmt,
+}
@@ -1,17 +1,25 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
 use std::rc::Rc;
 
 mod callable;
+mod convert;
+mod memory;
 
 pub use callable::*;
+pub use convert::*;
+pub use memory::*;
 
 #[derive(Debug, Clone)]
 pub enum Value {
   Number(f64),
-  Str(String),
+  Str(LoxString),
   Bool(bool),
   Nil,
   Callable(Rc<dyn LoxCallable>),
+  List(Rc<RefCell<Vec<Value>>>),
+  Map(Rc<RefCell<HashMap<String, Value>>>),
 }
 
 impl Value {
@@ -19,15 +27,27 @@ impl Value {
     match self {
       Value::Bool(b) => *b,
       Value::Number(n) => *n != 0.0,
-      Value::Str(s) => s.len() > 0,
+      Value::Str(s) => !s.is_empty(),
       Value::Nil => false,
-      Value::Callable(_) => true,
+      Value::Callable(_) | Value::List(_) | Value::Map(_) => true,
     }
   }
 
   pub fn is_falsy(&self) -> bool {
     !self.is_truthy()
   }
+
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Value::Number(_) => "number",
+      Value::Str(_) => "string",
+      Value::Bool(_) => "bool",
+      Value::Nil => "nil",
+      Value::Callable(_) => "callable",
+      Value::List(_) => "list",
+      Value::Map(_) => "map",
+    }
+  }
 }
 
 impl fmt::Display for Value {
@@ -38,6 +58,29 @@ impl fmt::Display for Value {
       Value::Bool(b) => write!(f, "{b}"),
       Value::Nil => write!(f, "nil"),
       Value::Callable(rc) => write!(f, "<callable {}>", rc.name()),
+      Value::List(items) => {
+        write!(f, "[")?;
+        for (i, item) in items.borrow().iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{item}")?;
+        }
+        write!(f, "]")
+      }
+      Value::Map(entries) => {
+        let entries = entries.borrow();
+        let mut keys: Vec<&String> = entries.keys().collect();
+        keys.sort();
+        write!(f, "{{")?;
+        for (i, key) in keys.into_iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{key}: {}", entries[key])?;
+        }
+        write!(f, "}}")
+      }
     }
   }
 }
@@ -1,9 +1,15 @@
 use super::Value;
-use crate::interpreter::{Interpreter, RuntimeError};
+use crate::interpreter::{Capability, Interpreter, RuntimeError};
+use std::any::Any;
 use std::fmt;
 
-pub trait LoxCallable: fmt::Debug {
+pub trait LoxCallable: fmt::Debug + Any {
   fn name(&self) -> &str;
   fn arity(&self) -> usize;
   fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError>;
+
+  /// The capability the program needs to be granted to call this.
+  fn capability(&self) -> Option<Capability> {
+    None
+  }
 }
@@ -0,0 +1,251 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::{LoxString, Value};
+use crate::interpreter::{RuntimeError, RuntimeErrorKind};
+
+/// Conversion of a Rust value into a Lox value.
+pub trait IntoLox {
+  fn into_lox(self) -> Value;
+}
+
+/// Conversion of a Lox value into a Rust value.
+///
+/// # Errors
+///
+/// Returns `RuntimeErrorKind::ConversionFailed` if the value has the wrong type.
+pub trait FromLox: Sized {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError>;
+}
+
+/// Return types of typed native functions, either a plain value
+/// or a `Result` for natives that can fail.
+pub trait IntoLoxResult {
+  fn into_lox_result(self) -> Result<Value, RuntimeError>;
+}
+
+impl<T: IntoLox> IntoLoxResult for T {
+  fn into_lox_result(self) -> Result<Value, RuntimeError> {
+    Ok(self.into_lox())
+  }
+}
+
+impl<T: IntoLox> IntoLoxResult for Result<T, RuntimeError> {
+  fn into_lox_result(self) -> Result<Value, RuntimeError> {
+    self.map(IntoLox::into_lox)
+  }
+}
+
+fn mismatch(expected: &'static str, found: &Value) -> RuntimeError {
+  RuntimeErrorKind::ConversionFailed {
+    expected,
+    found: found.type_name(),
+    call_site: None,
+  }
+  .into()
+}
+
+impl IntoLox for Value {
+  fn into_lox(self) -> Value {
+    self
+  }
+}
+
+impl FromLox for Value {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    Ok(value)
+  }
+}
+
+impl IntoLox for () {
+  fn into_lox(self) -> Value {
+    Value::Nil
+  }
+}
+
+impl IntoLox for f64 {
+  fn into_lox(self) -> Value {
+    Value::Number(self)
+  }
+}
+
+impl FromLox for f64 {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::Number(n) => Ok(n),
+      other => Err(mismatch("number", &other)),
+    }
+  }
+}
+
+macro_rules! impl_integer {
+  ($($int:ty),*) => {
+    $(
+      impl IntoLox for $int {
+        fn into_lox(self) -> Value {
+          Value::Number(self as f64)
+        }
+      }
+
+      impl FromLox for $int {
+        fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+          // `MAX as f64` rounds up to the next power of two for the wide
+          // integers, so the upper bound is exclusive.
+          let limit = ((<$int>::MAX >> 1) + 1) as f64 * 2.0;
+          match value {
+            Value::Number(n) if n.fract() == 0.0 && n >= <$int>::MIN as f64 && n < limit => {
+              Ok(n as $int)
+            }
+            other => Err(mismatch(stringify!($int), &other)),
+          }
+        }
+      }
+    )*
+  };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+impl IntoLox for bool {
+  fn into_lox(self) -> Value {
+    Value::Bool(self)
+  }
+}
+
+impl FromLox for bool {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::Bool(b) => Ok(b),
+      other => Err(mismatch("bool", &other)),
+    }
+  }
+}
+
+impl IntoLox for String {
+  fn into_lox(self) -> Value {
+    Value::Str(LoxString::new(self))
+  }
+}
+
+impl IntoLox for &str {
+  fn into_lox(self) -> Value {
+    Value::Str(LoxString::from(self))
+  }
+}
+
+impl FromLox for String {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::Str(s) => Ok(String::from(s)),
+      other => Err(mismatch("string", &other)),
+    }
+  }
+}
+
+impl<T: IntoLox> IntoLox for Option<T> {
+  fn into_lox(self) -> Value {
+    match self {
+      Some(value) => value.into_lox(),
+      None => Value::Nil,
+    }
+  }
+}
+
+impl<T: FromLox> FromLox for Option<T> {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::Nil => Ok(None),
+      other => T::from_lox(other).map(Some),
+    }
+  }
+}
+
+impl<T: IntoLox> IntoLox for Vec<T> {
+  fn into_lox(self) -> Value {
+    let items = self.into_iter().map(IntoLox::into_lox).collect();
+    Value::List(Rc::new(RefCell::new(items)))
+  }
+}
+
+impl<T: FromLox> FromLox for Vec<T> {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::List(items) => items.borrow().iter().cloned().map(T::from_lox).collect(),
+      other => Err(mismatch("list", &other)),
+    }
+  }
+}
+
+impl<T: IntoLox> IntoLox for HashMap<String, T> {
+  fn into_lox(self) -> Value {
+    let entries = self.into_iter().map(|(k, v)| (k, v.into_lox())).collect();
+    Value::Map(Rc::new(RefCell::new(entries)))
+  }
+}
+
+impl<T: FromLox> FromLox for HashMap<String, T> {
+  fn from_lox(value: Value) -> Result<Self, RuntimeError> {
+    match value {
+      Value::Map(entries) => entries
+        .borrow()
+        .iter()
+        .map(|(k, v)| T::from_lox(v.clone()).map(|v| (k.clone(), v)))
+        .collect(),
+      other => Err(mismatch("map", &other)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn round_trips_primitives() {
+    assert_eq!(f64::from_lox(1.5.into_lox()).unwrap(), 1.5);
+    assert_eq!(i32::from_lox((-7).into_lox()).unwrap(), -7);
+    assert!(bool::from_lox(true.into_lox()).unwrap());
+    assert_eq!(String::from_lox("lox".into_lox()).unwrap(), "lox");
+  }
+
+  #[test]
+  fn round_trips_collections() {
+    let list = vec![Some(1.0), None].into_lox();
+    assert_eq!(list.to_string(), "[1, nil]");
+    assert_eq!(
+      Vec::<Option<f64>>::from_lox(list).unwrap(),
+      vec![Some(1.0), None]
+    );
+
+    let map = HashMap::from([(String::from("a"), 1u8)]).into_lox();
+    let map = HashMap::<String, u8>::from_lox(map).unwrap();
+    assert_eq!(map.get("a"), Some(&1));
+  }
+
+  #[test]
+  fn reports_mismatched_types() {
+    let err = f64::from_lox("lox".into_lox()).unwrap_err();
+    assert!(matches!(
+      err.kind(),
+      RuntimeErrorKind::ConversionFailed {
+        expected: "number",
+        found: "string",
+        call_site: None,
+      }
+    ));
+    assert!(u8::from_lox(1.5.into_lox()).is_err());
+    assert!(u8::from_lox((-1).into_lox()).is_err());
+  }
+
+  #[test]
+  fn rejects_integers_out_of_range() {
+    assert_eq!(u8::from_lox(255.into_lox()).unwrap(), 255);
+    assert!(u8::from_lox(256.into_lox()).is_err());
+    assert_eq!(i8::from_lox((-128).into_lox()).unwrap(), -128);
+    assert!(i8::from_lox(128.into_lox()).is_err());
+    assert!(i64::from_lox(Value::Number(2f64.powi(63))).is_err());
+    assert!(i64::from_lox(Value::Number(-(2f64.powi(63)))).is_ok());
+    assert!(u64::from_lox(Value::Number(2f64.powi(64))).is_err());
+  }
+}
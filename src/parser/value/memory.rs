@@ -0,0 +1,283 @@
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem::size_of;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+use super::Value;
+
+/// Counts the bytes held by the values an interpreter created. Strings
+/// add their size when they are created and remove it when the last
+/// clone is dropped. Lists and maps are measured while they are alive.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryCounter(Rc<Counts>);
+
+#[derive(Debug, Default)]
+struct Counts {
+  strings: Cell<usize>,
+  /// The lists and maps created so far, by address.
+  collections: RefCell<HashMap<usize, Collection>>,
+}
+
+#[derive(Debug)]
+enum Collection {
+  List(Weak<RefCell<Vec<Value>>>),
+  Map(Weak<RefCell<HashMap<String, Value>>>),
+}
+
+impl Collection {
+  /// Whether the collection was not freed yet. Unlike `size`, this does
+  /// not borrow it, which a caller counting its items may still do.
+  fn is_alive(&self) -> bool {
+    match self {
+      Collection::List(items) => items.strong_count() > 0,
+      Collection::Map(entries) => entries.strong_count() > 0,
+    }
+  }
+
+  /// The bytes held by the collection itself, or `None` once it was freed.
+  /// The strings in it are counted on their own.
+  fn size(&self) -> Option<usize> {
+    match self {
+      Collection::List(items) => Some(items.upgrade()?.borrow().len() * size_of::<Value>()),
+      Collection::Map(entries) => Some(
+        entries
+          .upgrade()?
+          .borrow()
+          .keys()
+          .map(|key| key.len() + size_of::<String>() + size_of::<Value>())
+          .sum(),
+      ),
+    }
+  }
+}
+
+impl MemoryCounter {
+  pub fn new() -> MemoryCounter {
+    MemoryCounter::default()
+  }
+
+  /// Bytes held by the values that are alive.
+  pub fn used(&self) -> usize {
+    let mut collections = self.0.collections.borrow_mut();
+    let mut used = self.0.strings.get();
+    collections.retain(|_, collection| match collection.size() {
+      Some(size) => {
+        used += size;
+        true
+      }
+      None => false,
+    });
+    used
+  }
+
+  /// Counts a value created outside of the interpreter, such as the
+  /// result of a native, with the strings, lists and maps in it.
+  /// Strings that are shared with other values are not new, and stay
+  /// uncounted.
+  pub fn count(&self, value: &mut Value) {
+    match value {
+      Value::Str(string) if string.counter.is_none() && string.is_unique() => {
+        *string = LoxString::counted(Rc::clone(&string.value), self);
+      }
+      Value::List(items) => {
+        let address = Rc::as_ptr(items) as usize;
+        if self.track(address, Collection::List(Rc::downgrade(items))) {
+          for item in items.borrow_mut().iter_mut() {
+            self.count(item);
+          }
+        }
+      }
+      Value::Map(entries) => {
+        let address = Rc::as_ptr(entries) as usize;
+        if self.track(address, Collection::Map(Rc::downgrade(entries))) {
+          for value in entries.borrow_mut().values_mut() {
+            self.count(value);
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// Starts measuring a collection, returning whether it is new.
+  fn track(&self, address: usize, collection: Collection) -> bool {
+    let mut collections = self.0.collections.borrow_mut();
+    if collections.get(&address).is_some_and(Collection::is_alive) {
+      return false;
+    }
+    collections.insert(address, collection);
+    true
+  }
+
+  fn add(&self, bytes: usize) {
+    self.0.strings.set(self.0.strings.get() + bytes);
+  }
+
+  fn sub(&self, bytes: usize) {
+    self
+      .0
+      .strings
+      .set(self.0.strings.get().saturating_sub(bytes));
+  }
+}
+
+/// The contents of a string value, shared by its clones. Strings built
+/// while the program runs, by the program or by natives, are counted by
+/// the interpreter's `MemoryCounter`; literals are not.
+#[derive(Clone)]
+pub struct LoxString {
+  value: Rc<str>,
+  counter: Option<MemoryCounter>,
+}
+
+impl LoxString {
+  pub fn new(value: impl Into<Rc<str>>) -> LoxString {
+    LoxString {
+      value: value.into(),
+      counter: None,
+    }
+  }
+
+  /// A string whose bytes are counted by `counter` while it is alive.
+  pub fn counted(value: impl Into<Rc<str>>, counter: &MemoryCounter) -> LoxString {
+    let value = value.into();
+    counter.add(value.len());
+    LoxString {
+      value,
+      counter: Some(counter.clone()),
+    }
+  }
+
+  pub fn as_str(&self) -> &str {
+    &self.value
+  }
+
+  /// Whether this is the last clone, which holds the bytes alone.
+  fn is_unique(&self) -> bool {
+    Rc::strong_count(&self.value) == 1
+  }
+}
+
+impl Drop for LoxString {
+  fn drop(&mut self) {
+    if let Some(counter) = &self.counter
+      && self.is_unique()
+    {
+      counter.sub(self.value.len());
+    }
+  }
+}
+
+impl Deref for LoxString {
+  type Target = str;
+
+  fn deref(&self) -> &str {
+    &self.value
+  }
+}
+
+impl From<String> for LoxString {
+  fn from(value: String) -> LoxString {
+    LoxString::new(value)
+  }
+}
+
+impl From<&str> for LoxString {
+  fn from(value: &str) -> LoxString {
+    LoxString::new(value)
+  }
+}
+
+impl From<LoxString> for String {
+  fn from(string: LoxString) -> String {
+    string.as_str().to_string()
+  }
+}
+
+impl PartialEq for LoxString {
+  fn eq(&self, other: &LoxString) -> bool {
+    self.value == other.value
+  }
+}
+
+impl PartialOrd for LoxString {
+  fn partial_cmp(&self, other: &LoxString) -> Option<Ordering> {
+    self.value.partial_cmp(&other.value)
+  }
+}
+
+impl fmt::Debug for LoxString {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Debug::fmt(&self.value, f)
+  }
+}
+
+impl fmt::Display for LoxString {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    fmt::Display::fmt(&self.value, f)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn counts_live_strings() {
+    let counter = MemoryCounter::new();
+    let a = LoxString::counted("lox", &counter);
+    let b = a.clone();
+    assert_eq!(counter.used(), 3);
+    drop(a);
+    assert_eq!(counter.used(), 3);
+    assert_eq!(String::from(b), "lox");
+    assert_eq!(counter.used(), 0);
+  }
+
+  #[test]
+  fn counts_collections_while_they_are_alive() {
+    let counter = MemoryCounter::new();
+    let list = Rc::new(RefCell::new(vec![Value::Str(LoxString::from("lox"))]));
+    let mut value = Value::List(Rc::clone(&list));
+    counter.count(&mut value);
+    counter.count(&mut value);
+    assert_eq!(counter.used(), size_of::<Value>() + 3);
+    drop((list, value));
+    assert_eq!(counter.used(), 0);
+  }
+
+  #[test]
+  fn counts_lists_that_hold_themselves() {
+    let counter = MemoryCounter::new();
+    let list = Rc::new(RefCell::new(Vec::new()));
+    list.borrow_mut().push(Value::List(Rc::clone(&list)));
+    counter.count(&mut Value::List(Rc::clone(&list)));
+    assert_eq!(counter.used(), size_of::<Value>());
+    list.borrow_mut().clear();
+  }
+
+  #[test]
+  fn counts_lists_that_hold_each_other() {
+    let counter = MemoryCounter::new();
+    let a = Rc::new(RefCell::new(Vec::new()));
+    let b = Rc::new(RefCell::new(vec![Value::List(Rc::clone(&a))]));
+    a.borrow_mut().push(Value::List(Rc::clone(&b)));
+    counter.count(&mut Value::List(Rc::clone(&a)));
+    assert_eq!(counter.used(), 2 * size_of::<Value>());
+    a.borrow_mut().clear();
+    assert_eq!(counter.used(), size_of::<Value>());
+  }
+
+  #[test]
+  fn ignores_uncounted_strings() {
+    let counter = MemoryCounter::new();
+    let literal = LoxString::from("lox");
+    let _copy = literal.clone();
+    assert_eq!(counter.used(), 0);
+    assert_eq!(&*literal, "lox");
+    assert_eq!(format!("{literal:?}"), "\"lox\"");
+  }
+}
@@ -0,0 +1,160 @@
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::Ordering;
+
+use crate::interpreter::Limits;
+use crate::lexer::{Lexer, TokenKind};
+use crate::parser::Value;
+use crate::session::Session;
+use crate::source::SourceId;
+
+const HELP: &str = "\
+Enter Lox statements to run them. The value of a trailing expression
+statement is printed. Input continues while braces or parens are open.
+
+Commands:
+  :help         show this message
+  :reset        forget every global and function
+  :load <file>  run a file in the current session
+  :env          list the globals and their values
+";
+
+/// An interactive read-eval-print loop on top of a `Session`.
+pub struct Repl<'a> {
+  session: Session<'a>,
+  err: Box<dyn Write + 'a>,
+  pending: String,
+  /// How many snippets were typed so far, to give each a distinct name.
+  snippets: usize,
+}
+
+impl<'a> Repl<'a> {
+  pub fn new(out: Box<dyn Write + 'a>, err: Box<dyn Write + 'a>) -> Self {
+    let mut session = Session::new();
+    session.set_out_writer(out);
+    Repl {
+      session,
+      err,
+      pending: String::new(),
+      snippets: 0,
+    }
+  }
+
+  /// Reads lines from `input` until it is exhausted or calls `exit`.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if reading the input or writing the output fails.
+  pub fn run<R: BufRead>(&mut self, input: R) -> io::Result<()> {
+    self.prompt()?;
+    for line in input.lines() {
+      self.feed_line(&line?)?;
+      if self.exit_code().is_some() {
+        return Ok(());
+      }
+      self.prompt()?;
+    }
+    writeln!(self.session.out())
+  }
+
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.session.set_limits(limits);
+  }
+
+  /// The exit code, once the input has called `exit(code)`.
+  pub fn exit_code(&self) -> Option<i32> {
+    self.session.exit_code()
+  }
+
+  /// Feeds a single line, running the pending input once it is complete.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if writing the output fails.
+  pub fn feed_line(&mut self, line: &str) -> io::Result<()> {
+    if self.pending.is_empty() && line.trim_start().starts_with(':') {
+      return self.command(line.trim());
+    }
+
+    self.pending.push_str(line);
+    self.pending.push('\n');
+    if !is_complete(&self.pending) {
+      return Ok(());
+    }
+    let source_code = std::mem::take(&mut self.pending);
+    self.snippets += 1;
+    self.eval(&format!("<repl:{}>", self.snippets), &source_code)
+  }
+
+  fn prompt(&mut self) -> io::Result<()> {
+    let prompt = if self.pending.is_empty() {
+      "> "
+    } else {
+      "... "
+    };
+    let out = self.session.out();
+    write!(out, "{prompt}")?;
+    out.flush()
+  }
+
+  fn eval(&mut self, name: &str, source_code: &str) -> io::Result<()> {
+    // An interrupt that arrived while waiting for input is stale.
+    self
+      .session
+      .interrupt_handle()
+      .store(false, Ordering::Relaxed);
+    match self.session.run_named(name, source_code) {
+      Ok(Some(Value::Nil)) | Ok(None) => Ok(()),
+      Ok(Some(value)) => writeln!(self.session.out(), "{value}"),
+      Err(errs) => {
+        for err in errs {
+          writeln!(self.err, "{}", self.session.sources().render(&err))?;
+        }
+        Ok(())
+      }
+    }
+  }
+
+  fn command(&mut self, line: &str) -> io::Result<()> {
+    let (name, arg) = match line.split_once(char::is_whitespace) {
+      Some((name, arg)) => (name, arg.trim()),
+      None => (line, ""),
+    };
+
+    match name {
+      ":help" => write!(self.session.out(), "{HELP}"),
+      ":reset" => {
+        self.session.reset();
+        writeln!(self.session.out(), "Session reset.")
+      }
+      ":load" if arg.is_empty() => writeln!(self.err, "usage: :load <file>"),
+      ":load" => match std::fs::read_to_string(arg) {
+        Ok(source_code) => self.eval(arg, &source_code),
+        Err(err) => writeln!(self.err, "{arg}: {err}"),
+      },
+      ":env" => {
+        for (name, value) in self.session.globals() {
+          writeln!(self.session.out(), "{name} = {value}")?;
+        }
+        Ok(())
+      }
+      _ => writeln!(self.err, "unknown command '{name}', try :help"),
+    }
+  }
+}
+
+/// Whether every brace and paren in the input is closed. Input that does
+/// not lex is considered complete so that the error gets reported.
+fn is_complete(source_code: &str) -> bool {
+  let Ok(tokens) = Lexer::new(source_code, SourceId::detached("<repl>")).tokenize() else {
+    return true;
+  };
+  let mut depth: i64 = 0;
+  for token in &tokens {
+    match token.kind() {
+      TokenKind::LeftBrace | TokenKind::LeftParen => depth += 1,
+      TokenKind::RightBrace | TokenKind::RightParen => depth -= 1,
+      _ => {}
+    }
+  }
+  depth <= 0
+}
@@ -0,0 +1,9 @@
+/// Where a variable lives at runtime, as computed by the resolver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+  /// The `slot`-th variable of the environment `depth` scopes out from
+  /// the one the variable is used in.
+  Local { depth: usize, slot: usize },
+  /// A slot of the globals table, such as a native function.
+  Global(usize),
+}
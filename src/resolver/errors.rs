@@ -1,12 +1,97 @@
+use crate::diagnostic::{Diagnostic, Span};
+use crate::interpreter::Capability;
 use crate::lexer::Token;
 use std::fmt;
 
+/// An error found while binding variables to their declarations.
+/// Each variant holds the token of the offending variable.
 #[derive(Debug, Clone)]
+#[non_exhaustive]
 pub enum ResolveError {
   UnassignedVariable(Token),
   UnusedVariable(Token),
   UndeclaredVariable(Token),
-  OvershadowingSameBlock(Token),
+  /// The variable declared again, and where it was declared before.
+  OvershadowingSameBlock(Token, Span),
+  /// A native, or `print`, that needs a capability the program lacks.
+  CapabilityDisabled(Token, Capability),
+  /// A `return` outside of any function, with its keyword.
+  ReturnOutsideFunction(Token),
+}
+
+impl ResolveError {
+  pub fn token(&self) -> &Token {
+    match self {
+      ResolveError::UnassignedVariable(tok)
+      | ResolveError::UnusedVariable(tok)
+      | ResolveError::UndeclaredVariable(tok)
+      | ResolveError::OvershadowingSameBlock(tok, _)
+      | ResolveError::CapabilityDisabled(tok, _)
+      | ResolveError::ReturnOutsideFunction(tok) => tok,
+    }
+  }
+
+  pub fn span(&self) -> Span {
+    self.token().span()
+  }
+
+  pub fn code(&self) -> &'static str {
+    match self {
+      ResolveError::UnassignedVariable(_) => "E0301",
+      ResolveError::UnusedVariable(_) => "E0302",
+      ResolveError::UndeclaredVariable(_) => "E0303",
+      ResolveError::OvershadowingSameBlock(..) => "E0304",
+      ResolveError::CapabilityDisabled(..) => "E0305",
+      ResolveError::ReturnOutsideFunction(_) => "E0306",
+    }
+  }
+
+  pub fn diagnostic(&self) -> Diagnostic {
+    match self {
+      ResolveError::UnassignedVariable(tok) => Diagnostic::new(
+        self.code(),
+        format!("variable '{}' is never assigned", tok.extract_identifier()),
+      )
+      .with_span(tok.span(), "declared here")
+      .with_help(format!(
+        "initialize it with `var {} = ...;`",
+        tok.extract_identifier()
+      )),
+      ResolveError::UnusedVariable(tok) => Diagnostic::new(
+        self.code(),
+        format!("variable '{}' is never read", tok.extract_identifier()),
+      )
+      .with_span(tok.span(), "declared here")
+      .with_help("remove the variable or use it"),
+      ResolveError::UndeclaredVariable(tok) => Diagnostic::new(
+        self.code(),
+        format!("variable '{}' is not declared", tok.extract_identifier()),
+      )
+      .with_span(tok.span(), "not found in this scope")
+      .with_note("variables must be declared before they are used"),
+      ResolveError::OvershadowingSameBlock(tok, previous) => Diagnostic::new(
+        self.code(),
+        format!(
+          "variable '{}' is already declared in this block",
+          tok.extract_identifier()
+        ),
+      )
+      .with_span(tok.span(), "declared again here")
+      .with_related(previous.clone(), "previously declared here")
+      .with_help("assign to the existing variable or rename this one"),
+      ResolveError::CapabilityDisabled(tok, capability) => Diagnostic::new(
+        self.code(),
+        format!("capability '{capability}' is not granted"),
+      )
+      .with_span(tok.span(), format!("needs '{capability}'"))
+      .with_help("ask the host running the program to grant it"),
+      ResolveError::ReturnOutsideFunction(tok) => {
+        Diagnostic::new(self.code(), "cannot return outside of a function")
+          .with_span(tok.span(), "not inside a function")
+          .with_help("use `exit(code)` to stop the program early")
+      }
+    }
+  }
 }
 
 impl fmt::Display for ResolveError {
@@ -15,9 +100,15 @@ impl fmt::Display for ResolveError {
       ResolveError::UnassignedVariable(tok) => write!(f, "{} unassigned reference", tok),
       ResolveError::UnusedVariable(tok) => write!(f, "{} unused reference", tok),
       ResolveError::UndeclaredVariable(tok) => write!(f, "{} undeclared reference", tok),
-      ResolveError::OvershadowingSameBlock(tok) => {
+      ResolveError::OvershadowingSameBlock(tok, _) => {
         write!(f, "{} overshadowing reference in the same block", tok)
       }
+      ResolveError::CapabilityDisabled(tok, capability) => {
+        write!(f, "{} capability '{}' is not granted", tok, capability)
+      }
+      ResolveError::ReturnOutsideFunction(tok) => write!(f, "{} return outside of a function", tok),
     }
   }
 }
+
+impl std::error::Error for ResolveError {}
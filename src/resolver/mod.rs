@@ -1,37 +1,49 @@
-use crate::lexer::Token;
-use crate::parser::{Expr, Stmt};
+use crate::interpreter::{Capabilities, Capability};
+use crate::lexer::{Symbol, Token, TokenKind};
+use crate::parser::{Expr, FunctionDecl, Stmt};
+use crate::source::SourceId;
 use std::collections::HashMap;
 
+mod binding;
 mod errors;
 mod variable_state;
 
+pub use binding::*;
 pub use errors::*;
 pub use variable_state::*;
 
-type ExprId = usize;
-type LexicalDepth = usize;
-
 pub struct Resolver {
-  scopes: Vec<HashMap<String, VariableState>>,
-  bindings: HashMap<ExprId, LexicalDepth>,
+  scopes: Vec<HashMap<Symbol, VariableState>>,
+  /// The binding of every variable use and declaration, by its id.
+  bindings: Vec<Option<Binding>>,
   errors: Vec<ResolveError>,
+  relaxed_top_level: bool,
+  /// How many function bodies enclose the code being resolved.
+  function_depth: usize,
+  capabilities: Capabilities,
+  global_capabilities: HashMap<Symbol, Capability>,
 }
 
 impl Resolver {
   pub fn new() -> Self {
     Resolver {
       scopes: vec![HashMap::new()],
-      bindings: HashMap::new(),
+      bindings: Vec::new(),
       errors: Vec::new(),
+      relaxed_top_level: false,
+      function_depth: 0,
+      capabilities: Capabilities::all(),
+      global_capabilities: HashMap::new(),
     }
   }
 
-  pub fn resolve(&mut self, stmts: &Vec<Stmt>) {
+  pub fn resolve(&mut self, stmts: &[Stmt]) {
     self.begin_scope();
+    let depth = self.scopes.len();
     for stmt in stmts {
       if let Err(err) = self.resolve_stmt(stmt) {
         self.errors.push(err);
-        continue;
+        self.scopes.truncate(depth);
       }
     }
 
@@ -40,23 +52,129 @@ impl Resolver {
     }
   }
 
+  /// Resolves a snippet inside a top-level scope that outlives the call,
+  /// so that later snippets can refer to its declarations. Top-level
+  /// variables are never checked for being unused and may be declared
+  /// again, and on error the top-level scope is restored to its state
+  /// before the call.
+  pub fn resolve_incremental(&mut self, stmts: &[Stmt]) {
+    self.errors.clear();
+    if self.scopes.len() == 1 {
+      self.begin_scope();
+      self.relaxed_top_level = true;
+    }
+    let depth = self.scopes.len();
+    let snapshot = self.get_last_scope_mut().clone();
+    for stmt in stmts {
+      if let Err(err) = self.resolve_stmt(stmt) {
+        self.errors.push(err);
+        self.scopes.truncate(depth);
+      }
+    }
+
+    if !self.errors.is_empty() {
+      *self.get_last_scope_mut() = snapshot;
+    }
+  }
+
+  /// Declares a variable that lives outside of the program's
+  /// top-level scope, such as a native function. Referring to it
+  /// requires `capability` to be granted, when there is one. Returns
+  /// the slot of the global, which is kept when it is declared again.
+  pub fn declare_global(&mut self, name: &str, capability: Option<Capability>) -> usize {
+    let symbol = Symbol::intern(name);
+    let token = Token::new(
+      SourceId::detached("<native>"),
+      0,
+      0,
+      0,
+      TokenKind::Identifier(symbol),
+    );
+    let globals = &mut self.scopes[0];
+    let slot = globals
+      .get(&symbol)
+      .map_or(globals.len(), VariableState::slot);
+    let mut variable_state = VariableState::new(token, slot);
+    variable_state.mark_assigned();
+    variable_state.mark_read();
+    globals.insert(symbol, variable_state);
+    match capability {
+      Some(capability) => self.global_capabilities.insert(symbol, capability),
+      None => self.global_capabilities.remove(&symbol),
+    };
+    slot
+  }
+
+  /// The variables of the top-level scope that outlives
+  /// `resolve_incremental`, with their slots.
+  pub fn top_level(&self) -> Vec<(String, usize)> {
+    match self.scopes.get(1) {
+      Some(scope) => scope
+        .iter()
+        .map(|(name, variable)| (name.to_string(), variable.slot()))
+        .collect(),
+      None => Vec::new(),
+    }
+  }
+
+  /// The capabilities granted to the programs resolved from now on.
+  pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+    self.capabilities = capabilities;
+  }
+
+  /// Forgets everything but the globals.
+  pub fn reset(&mut self) {
+    self.scopes.truncate(1);
+    self.bindings.clear();
+    self.errors.clear();
+    self.relaxed_top_level = false;
+  }
+
   pub fn errors(&self) -> &Vec<ResolveError> {
     &self.errors
   }
 
-  pub fn get_bound_depth(&self, id: usize) -> LexicalDepth {
+  pub fn binding(&self, id: usize) -> Binding {
     self
-      .bindings
-      .get(&id)
-      .copied()
+      .get_binding(id)
       .expect("expected that reference is resolved")
   }
 
+  /// The binding of the variable `id`, if it was resolved.
+  pub(crate) fn get_binding(&self, id: usize) -> Option<Binding> {
+    self.bindings.get(id).copied().flatten()
+  }
+
+  /// Binds the use of a global named by `token`, for programs resolved
+  /// ahead of time against other globals. Fails like resolving the use
+  /// would, when the global is not declared or not granted.
+  pub(crate) fn bind_global(&mut self, id: usize, token: &Token) -> Result<(), ResolveError> {
+    let name = token.extract_identifier();
+    let variable = self.scopes[0]
+      .get(&name)
+      .ok_or_else(|| ResolveError::UndeclaredVariable(token.clone()))?;
+    if let Some(&capability) = self.global_capabilities.get(&name)
+      && !self.capabilities.contains(capability)
+    {
+      return Err(ResolveError::CapabilityDisabled(token.clone(), capability));
+    }
+    let slot = variable.slot();
+    self.bind(id, Binding::Global(slot));
+    Ok(())
+  }
+
+  pub(crate) fn bind(&mut self, id: usize, binding: Binding) {
+    if self.bindings.len() <= id {
+      self.bindings.resize(id + 1, None);
+    }
+    self.bindings[id] = Some(binding);
+  }
+
   fn begin_scope(&mut self) {
     self.scopes.push(HashMap::new());
   }
 
-  fn pop_last_scope(&mut self) -> HashMap<String, VariableState> {
+  fn pop_last_scope(&mut self) -> HashMap<Symbol, VariableState> {
     self
       .scopes
       .pop()
@@ -82,53 +200,71 @@ impl Resolver {
       }
     }
 
-    if errors.len() == 0 {
+    if errors.is_empty() {
       Ok(())
     } else {
       Err(errors)
     }
   }
 
-  fn get_last_scope_mut(&mut self) -> &mut HashMap<String, VariableState> {
+  fn get_last_scope_mut(&mut self) -> &mut HashMap<Symbol, VariableState> {
     self
       .scopes
       .last_mut()
       .expect("expected that we are inside of at least one scope")
   }
 
+  /// Declares a variable in the current scope and returns its slot. A
+  /// variable declared again in the relaxed top-level scope keeps its slot.
   fn declare_optional_assigned(
     &mut self,
     variable_tok: &Token,
     assigned: bool,
-  ) -> Result<(), ResolveError> {
+  ) -> Result<usize, ResolveError> {
     let name = variable_tok.extract_identifier();
+    let redeclarable = self.relaxed_top_level && self.scopes.len() == 2;
     let last = self.get_last_scope_mut();
 
-    if let None = last.get(name) {
-      let mut variable_state = VariableState::new(variable_tok.clone());
-      if assigned {
-        variable_state.mark_assigned();
+    let slot = match last.get(&name) {
+      Some(previous) if !redeclarable => {
+        return Err(ResolveError::OvershadowingSameBlock(
+          variable_tok.clone(),
+          previous.token().span(),
+        ));
       }
-      last.insert(name.clone(), variable_state);
-      Ok(())
-    } else {
-      Err(ResolveError::OvershadowingSameBlock(variable_tok.clone()))
+      Some(previous) => previous.slot(),
+      None => last.len(),
+    };
+    let mut variable_state = VariableState::new(variable_tok.clone(), slot);
+    if assigned {
+      variable_state.mark_assigned();
     }
+    last.insert(name, variable_state);
+    Ok(slot)
   }
 
-  fn declare(&mut self, variable: &Token) -> Result<(), ResolveError> {
-    self.declare_optional_assigned(variable, false)
+  fn declare(&mut self, id: usize, variable: &Token) -> Result<(), ResolveError> {
+    let slot = self.declare_optional_assigned(variable, false)?;
+    self.bind(id, Binding::Local { depth: 0, slot });
+    Ok(())
   }
 
-  fn declare_assigned(&mut self, variable: &Token) -> Result<(), ResolveError> {
-    self.declare_optional_assigned(variable, true)
+  fn declare_assigned(&mut self, id: usize, variable: &Token) -> Result<(), ResolveError> {
+    let slot = self.declare_optional_assigned(variable, true)?;
+    self.bind(id, Binding::Local { depth: 0, slot });
+    Ok(())
+  }
+
+  fn declare_param(&mut self, param: &Token) -> Result<(), ResolveError> {
+    self.declare_optional_assigned(param, true)?;
+    Ok(())
   }
 
   fn assign_curr_scope_non_binding(&mut self, iden: &Token) {
     let name = iden.extract_identifier();
     let last = self.get_last_scope_mut();
     let variable_state = last
-      .get_mut(name)
+      .get_mut(&name)
       .expect("expected that non binding variable is in current scope");
     variable_state.mark_assigned();
   }
@@ -139,19 +275,32 @@ impl Resolver {
     token: &Token,
     should_assign: bool,
   ) -> Result<(), ResolveError> {
-    let mut depth = 0;
     let name = token.extract_identifier();
-    for scope in self.scopes.iter_mut().rev() {
-      if let Some(variable) = scope.get_mut(name) {
+    let globals_depth = self.scopes.len() - 1;
+    for (depth, scope) in self.scopes.iter_mut().rev().enumerate() {
+      if let Some(variable) = scope.get_mut(&name) {
+        if depth == globals_depth
+          && let Some(&capability) = self.global_capabilities.get(&name)
+          && !self.capabilities.contains(capability)
+        {
+          return Err(ResolveError::CapabilityDisabled(token.clone(), capability));
+        }
         if should_assign {
           variable.mark_assigned();
         } else {
           variable.mark_read();
         }
-        self.bindings.insert(id, depth);
+        let binding = if depth == globals_depth {
+          Binding::Global(variable.slot())
+        } else {
+          Binding::Local {
+            depth,
+            slot: variable.slot(),
+          }
+        };
+        self.bind(id, binding);
         return Ok(());
       }
-      depth = depth + 1;
     }
 
     Err(ResolveError::UndeclaredVariable(token.clone()))
@@ -165,8 +314,8 @@ impl Resolver {
     self.bind_assign_or_access(id, iden, false)
   }
 
-  fn resolve_expr(&mut self, expr: &Box<Expr>) -> Result<(), ResolveError> {
-    match &**expr {
+  fn resolve_expr(&mut self, expr: &Expr) -> Result<(), ResolveError> {
+    match expr {
       Expr::Unary { op, right } => self.resolve_unary_expr(op, right),
       Expr::Binary { left, op, right } => self.resolve_binary_expr(left, op, right),
       Expr::Grouping(expr) => self.resolve_expr(expr),
@@ -182,15 +331,15 @@ impl Resolver {
     }
   }
 
-  fn resolve_unary_expr(&mut self, _op: &Token, right: &Box<Expr>) -> Result<(), ResolveError> {
+  fn resolve_unary_expr(&mut self, _op: &Token, right: &Expr) -> Result<(), ResolveError> {
     self.resolve_expr(right)
   }
 
   fn resolve_binary_expr(
     &mut self,
-    left: &Box<Expr>,
+    left: &Expr,
     _op: &Token,
-    right: &Box<Expr>,
+    right: &Expr,
   ) -> Result<(), ResolveError> {
     self.resolve_expr(left)?;
     self.resolve_expr(right)?;
@@ -205,7 +354,7 @@ impl Resolver {
     &mut self,
     id: &usize,
     variable: &Token,
-    expr: &Box<Expr>,
+    expr: &Expr,
   ) -> Result<(), ResolveError> {
     self.resolve_expr(expr)?;
     self.bind_assign(*id, variable)
@@ -213,9 +362,9 @@ impl Resolver {
 
   fn resolve_logical_expr(
     &mut self,
-    left: &Box<Expr>,
+    left: &Expr,
     _op: &Token,
-    right: &Box<Expr>,
+    right: &Expr,
   ) -> Result<(), ResolveError> {
     self.resolve_expr(left)?;
     self.resolve_expr(right)?;
@@ -224,9 +373,9 @@ impl Resolver {
 
   fn resolve_fun_call(
     &mut self,
-    callee: &Box<Expr>,
+    callee: &Expr,
     _paren: &Token,
-    args: &Vec<Box<Expr>>,
+    args: &[Box<Expr>],
   ) -> Result<(), ResolveError> {
     self.resolve_expr(callee)?;
     for arg in args {
@@ -237,9 +386,9 @@ impl Resolver {
 
   fn resolve_stmt(&mut self, stmt: &Stmt) -> Result<(), ResolveError> {
     match stmt {
-      Stmt::PrintStmt { expr } => self.resolve_print_stmt(expr),
+      Stmt::PrintStmt { keyword, expr } => self.resolve_print_stmt(keyword, expr),
       Stmt::ExprStmt { expr } => self.resolve_expr_stmt(expr),
-      Stmt::VarDecl { variable, expr } => self.resolve_var_decl(variable, expr),
+      Stmt::VarDecl { id, variable, expr } => self.resolve_var_decl(*id, variable, expr),
       Stmt::Block { stmts } => self.resolve_block_stmt(stmts),
       Stmt::If {
         condition,
@@ -247,35 +396,44 @@ impl Resolver {
         else_stmt,
       } => self.resolve_if_stmt(condition, then_stmt, else_stmt),
       Stmt::While { condition, body } => self.resolve_while_stmt(condition, body),
-      Stmt::FunDecl { name, params, body } => self.resolve_fun_decl(name, params, body),
-      Stmt::Return { expr } => self.resolve_return_stmt(expr),
+      Stmt::FunDecl { id, decl } => self.resolve_fun_decl(*id, decl),
+      Stmt::Return { keyword, expr } => self.resolve_return_stmt(keyword, expr),
     }
   }
 
-  fn resolve_print_stmt(&mut self, expr: &Box<Expr>) -> Result<(), ResolveError> {
+  fn resolve_print_stmt(&mut self, keyword: &Token, expr: &Expr) -> Result<(), ResolveError> {
+    // The expression is still resolved, so that the variables it reads
+    // are not reported as unused.
+    if !self.capabilities.contains(Capability::IoStdout) {
+      self.errors.push(ResolveError::CapabilityDisabled(
+        keyword.clone(),
+        Capability::IoStdout,
+      ));
+    }
     self.resolve_expr(expr)
   }
 
-  fn resolve_expr_stmt(&mut self, expr: &Box<Expr>) -> Result<(), ResolveError> {
+  fn resolve_expr_stmt(&mut self, expr: &Expr) -> Result<(), ResolveError> {
     self.resolve_expr(expr)
   }
 
   fn resolve_var_decl(
     &mut self,
+    id: usize,
     variable: &Token,
     expr: &Option<Box<Expr>>,
   ) -> Result<(), ResolveError> {
     if let Some(expr) = expr {
       self.resolve_expr(expr)?;
     }
-    self.declare(variable)?;
-    if let Some(_) = expr {
+    self.declare(id, variable)?;
+    if expr.is_some() {
       self.assign_curr_scope_non_binding(variable);
     }
     Ok(())
   }
 
-  fn resolve_block_stmt(&mut self, stmts: &Vec<Stmt>) -> Result<(), ResolveError> {
+  fn resolve_block_stmt(&mut self, stmts: &[Stmt]) -> Result<(), ResolveError> {
     self.begin_scope();
     for stmt in stmts {
       self.resolve_stmt(stmt)?;
@@ -285,8 +443,8 @@ impl Resolver {
 
   fn resolve_if_stmt(
     &mut self,
-    condition: &Box<Expr>,
-    then_stmt: &Box<Stmt>,
+    condition: &Expr,
+    then_stmt: &Stmt,
     else_stmt: &Option<Box<Stmt>>,
   ) -> Result<(), ResolveError> {
     self.resolve_expr(condition)?;
@@ -297,33 +455,80 @@ impl Resolver {
     Ok(())
   }
 
-  fn resolve_while_stmt(
-    &mut self,
-    condition: &Box<Expr>,
-    body: &Box<Stmt>,
-  ) -> Result<(), ResolveError> {
+  fn resolve_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> Result<(), ResolveError> {
     self.resolve_expr(condition)?;
     self.resolve_stmt(body)?;
     Ok(())
   }
 
-  fn resolve_fun_decl(
-    &mut self,
-    name: &Token,
-    params: &Vec<Token>,
-    body: &Box<Stmt>,
-  ) -> Result<(), ResolveError> {
-    self.declare_assigned(name)?;
+  /// Parameters take the first slots of the environment of a call, in order.
+  fn resolve_fun_decl(&mut self, id: usize, decl: &FunctionDecl) -> Result<(), ResolveError> {
+    self.declare_assigned(id, &decl.name)?;
+    self.function_depth += 1;
+    let resolved = self.resolve_function_body(decl);
+    self.function_depth -= 1;
+    resolved
+  }
+
+  fn resolve_function_body(&mut self, decl: &FunctionDecl) -> Result<(), ResolveError> {
     self.begin_scope();
-    for param in params {
-      self.declare_assigned(param)?;
+    for param in &decl.params {
+      self.declare_param(param)?;
     }
-    self.resolve_stmt(body)?;
+    self.resolve_stmt(&decl.body)?;
     self.end_scope()?;
     Ok(())
   }
 
-  fn resolve_return_stmt(&mut self, expr: &Box<Expr>) -> Result<(), ResolveError> {
+  /// Returns are only allowed inside functions: a program has no caller
+  /// to return a value to.
+  fn resolve_return_stmt(&mut self, keyword: &Token, expr: &Expr) -> Result<(), ResolveError> {
+    if self.function_depth == 0 {
+      self
+        .errors
+        .push(ResolveError::ReturnOutsideFunction(keyword.clone()));
+    }
     self.resolve_expr(expr)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+
+  fn resolve(source_code: &str) -> Resolver {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    let mut resolver = Resolver::new();
+    resolver.declare_global("clock", None);
+    resolver.resolve(&stmts);
+    assert!(resolver.errors().is_empty(), "{:?}", resolver.errors());
+    resolver
+  }
+
+  #[test]
+  fn assigns_slots_in_declaration_order() {
+    let resolver = resolve("var a = 1; var b = clock; fun f(x) { var y = x; return a + y; } f(b);");
+    // Ids are handed out as the parser finishes each use or declaration.
+    let bindings: Vec<Binding> = (1..=10).map(|id| resolver.binding(id)).collect();
+    assert_eq!(
+      bindings,
+      vec![
+        Binding::Local { depth: 0, slot: 0 }, // var a
+        Binding::Global(0),                   // clock
+        Binding::Local { depth: 0, slot: 1 }, // var b
+        Binding::Local { depth: 1, slot: 0 }, // x, the first parameter
+        Binding::Local { depth: 0, slot: 0 }, // var y
+        Binding::Local { depth: 2, slot: 0 }, // a, from inside f
+        Binding::Local { depth: 0, slot: 0 }, // y
+        Binding::Local { depth: 0, slot: 2 }, // fun f
+        Binding::Local { depth: 0, slot: 2 }, // f
+        Binding::Local { depth: 0, slot: 1 }, // b
+      ]
+    );
+  }
+}
@@ -1,22 +1,33 @@
 use super::ResolveError;
 use crate::lexer::Token;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VariableState {
   token: Token,
+  slot: usize,
   ever_assigned: bool,
   ever_read: bool,
 }
 
 impl VariableState {
-  pub fn new(token: Token) -> VariableState {
+  pub fn new(token: Token, slot: usize) -> VariableState {
     VariableState {
       token,
+      slot,
       ever_assigned: false,
       ever_read: false,
     }
   }
 
+  pub fn token(&self) -> &Token {
+    &self.token
+  }
+
+  /// The index of the variable in its environment.
+  pub fn slot(&self) -> usize {
+    self.slot
+  }
+
   pub fn mark_assigned(&mut self) {
     self.ever_assigned = true;
   }
@@ -0,0 +1,164 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+
+use crate::errors::LoxError;
+use crate::interpreter::{Completion, GcStats, Interpreter, Limits, define_natives};
+use crate::lexer::Lexer;
+use crate::parser::{Parser, Value};
+use crate::resolver::Resolver;
+use crate::source::SourceMap;
+
+/// How many snippets a session keeps the source code of, for rendering
+/// diagnostics.
+const MAX_SOURCES: usize = 256;
+
+/// A long-lived interpreter that evaluates snippets one after another,
+/// keeping globals and functions between them.
+pub struct Session<'a> {
+  interpreter: Interpreter<'a>,
+  sources: SourceMap,
+  curr_var_id: usize,
+  exit_code: Option<i32>,
+}
+
+impl<'a> Session<'a> {
+  pub fn new() -> Self {
+    let mut interpreter = Interpreter::new(Resolver::new());
+    define_natives(&mut interpreter, Vec::new());
+    Session {
+      interpreter,
+      sources: SourceMap::bounded(MAX_SOURCES),
+      curr_var_id: 0,
+      exit_code: None,
+    }
+  }
+
+  /// The exit code, once a snippet has called `exit(code)`.
+  pub fn exit_code(&self) -> Option<i32> {
+    self.exit_code
+  }
+
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.interpreter.set_limits(limits);
+  }
+
+  /// Bytes held by the strings the snippets built that are still alive.
+  pub fn memory_used(&self) -> usize {
+    self.interpreter.memory_used()
+  }
+
+  /// Frees the functions and environments that are only kept alive by
+  /// reference cycles, and returns how many objects were freed.
+  pub fn collect_garbage(&mut self) -> usize {
+    self.interpreter.collect_garbage()
+  }
+
+  pub fn gc_stats(&self) -> GcStats {
+    self.interpreter.gc_stats()
+  }
+
+  /// A flag that cancels the running snippet once set, from any thread.
+  pub fn interrupt_handle(&self) -> Arc<AtomicBool> {
+    self.interpreter.interrupt_handle()
+  }
+
+  pub fn set_out_writer(&mut self, out: Box<dyn Write + 'a>) {
+    self.interpreter.set_out_writer(out);
+  }
+
+  pub fn out(&mut self) -> &mut (dyn Write + 'a) {
+    self.interpreter.out()
+  }
+
+  /// Forgets every global and function declared so far.
+  pub fn reset(&mut self) {
+    self.interpreter.reset();
+  }
+
+  /// Every global declared so far, sorted by name.
+  pub fn globals(&self) -> Vec<(String, Value)> {
+    let mut globals = self.interpreter.variables();
+    globals.sort_by(|a, b| a.0.cmp(&b.0));
+    globals
+  }
+
+  /// The most recent snippets run so far, for rendering diagnostics.
+  pub fn sources(&self) -> &SourceMap {
+    &self.sources
+  }
+
+  /// Runs a snippet named `<eval>`, see `Session::run_named`.
+  ///
+  /// # Errors
+  ///
+  /// Same as `Session::run_named`.
+  pub fn run(&mut self, source_code: &str) -> Result<Option<Value>, Vec<LoxError>> {
+    self.run_named("<eval>", source_code)
+  }
+
+  /// Runs a snippet on top of everything run so far. If the snippet ends
+  /// with an expression statement, its value is returned. Errors refer to
+  /// the snippet by `name`.
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if the snippet cannot be lexed, parsed, resolved
+  /// or interpreted. Declarations of a snippet that fails statically
+  /// are discarded; a runtime error leaves the declarations made so far.
+  pub fn run_named(
+    &mut self,
+    name: &str,
+    source_code: &str,
+  ) -> Result<Option<Value>, Vec<LoxError>> {
+    let source = self.sources.add(name, source_code);
+    let tokens = Lexer::new(source_code, source)
+      .tokenize()
+      .map_err(|err| vec![LoxError::LexerError(err)])?;
+
+    let mut parser = Parser::with_var_id(tokens, self.curr_var_id);
+    let stmts = parser.parse();
+    self.curr_var_id = parser.curr_var_id();
+    if !parser.errors().is_empty() {
+      return Err(
+        parser
+          .errors()
+          .iter()
+          .map(|t| LoxError::ParseError(t.clone()))
+          .collect(),
+      );
+    }
+
+    let resolver = self.interpreter.resolver_mut();
+    resolver.resolve_incremental(&stmts);
+    if !resolver.errors().is_empty() {
+      return Err(
+        resolver
+          .errors()
+          .iter()
+          .map(|t| LoxError::ResolveError(t.clone()))
+          .collect(),
+      );
+    }
+
+    match self.interpreter.interpret_with_value(stmts) {
+      Ok((Completion::Finished, value)) => Ok(value),
+      Ok((Completion::Exit(code), _)) => {
+        self.exit_code = Some(code);
+        Ok(None)
+      }
+      Err(err) => {
+        // The resolver already knows about every declaration in the
+        // snippet, so the ones that never ran are defined as nil.
+        self.interpreter.declare_skipped();
+        Err(vec![LoxError::RuntimeError(err)])
+      }
+    }
+  }
+}
+
+impl Default for Session<'_> {
+  fn default() -> Self {
+    Self::new()
+  }
+}
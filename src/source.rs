@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::errors::LoxError;
+
+/// Identifies the source code a token was read from, such as a file
+/// path, `<stdin>`, `<repl:3>` or `<eval>`. Cheap to clone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceId(Rc<SourceName>);
+
+#[derive(Debug, PartialEq, Eq)]
+struct SourceName {
+  index: usize,
+  name: Box<str>,
+}
+
+impl SourceId {
+  fn new(index: usize, name: &str) -> SourceId {
+    SourceId(Rc::new(SourceName {
+      index,
+      name: Box::from(name),
+    }))
+  }
+
+  /// A source that is not registered in any `SourceMap`.
+  pub fn detached(name: &str) -> SourceId {
+    SourceId::new(usize::MAX, name)
+  }
+
+  pub fn name(&self) -> &str {
+    &self.0.name
+  }
+}
+
+impl fmt::Display for SourceId {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0.name)
+  }
+}
+
+/// Every piece of source code read by a program, so that diagnostics
+/// can show the lines they point at.
+#[derive(Debug, Default)]
+pub struct SourceMap {
+  files: VecDeque<(SourceId, Rc<str>)>,
+  /// The index of the oldest file still kept.
+  first: usize,
+  /// How many files to keep, if not all of them.
+  max_files: Option<usize>,
+}
+
+impl SourceMap {
+  pub fn new() -> SourceMap {
+    SourceMap::default()
+  }
+
+  /// A map that only keeps the `max_files` most recent files, for
+  /// sessions that read source code for as long as they run. Errors in
+  /// older files are rendered without their source line.
+  pub fn bounded(max_files: usize) -> SourceMap {
+    SourceMap {
+      max_files: Some(max_files),
+      ..SourceMap::default()
+    }
+  }
+
+  pub fn add(&mut self, name: &str, source_code: &str) -> SourceId {
+    let id = SourceId::new(self.first + self.files.len(), name);
+    if self.max_files == Some(self.files.len()) {
+      self.files.pop_front();
+      self.first += 1;
+    }
+    self.files.push_back((id.clone(), Rc::from(source_code)));
+    id
+  }
+
+  pub fn source_code(&self, id: &SourceId) -> Option<&str> {
+    self
+      .files
+      .get(id.0.index.checked_sub(self.first)?)
+      .filter(|(file_id, _)| file_id == id)
+      .map(|(_, source_code)| &**source_code)
+  }
+
+  /// Renders the error together with the line of source code it points at.
+  pub fn render(&self, err: &LoxError) -> String {
+    let diagnostic = err.diagnostic();
+    let source_code = diagnostic
+      .span()
+      .and_then(|span| self.source_code(span.source_id()))
+      .unwrap_or("");
+    diagnostic.render(source_code)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn looks_up_source_code_by_id() {
+    let mut sources = SourceMap::new();
+    let a = sources.add("a.lox", "print 1;");
+    let b = sources.add("<repl>", "print 2;");
+    assert_eq!(sources.source_code(&a), Some("print 1;"));
+    assert_eq!(sources.source_code(&b), Some("print 2;"));
+    assert_eq!(b.to_string(), "<repl>");
+    assert_eq!(sources.source_code(&SourceId::detached("a.lox")), None);
+  }
+
+  #[test]
+  fn forgets_the_oldest_files_past_the_bound() {
+    let mut sources = SourceMap::bounded(2);
+    let a = sources.add("a.lox", "print 1;");
+    let b = sources.add("b.lox", "print 2;");
+    let c = sources.add("c.lox", "print 3;");
+    assert_eq!(sources.source_code(&a), None);
+    assert_eq!(sources.source_code(&b), Some("print 2;"));
+    assert_eq!(sources.source_code(&c), Some("print 3;"));
+  }
+}
@@ -0,0 +1,270 @@
+use std::rc::Rc;
+
+use super::Function;
+use crate::lexer::Token;
+use crate::parser::Value;
+
+/// The instructions of the VM. The comment of each opcode lists the
+/// operands that follow it. Jump offsets are little-endian `u16`s, and
+/// any other operand is one byte; both are a little-endian `u32` when a
+/// `Wide` prefix comes before the opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpCode {
+  /// `constant`: pushes a value of the constant pool.
+  Constant,
+  Nil,
+  True,
+  False,
+  Pop,
+  /// `slot`: pushes a local of the current call.
+  GetLocal,
+  /// `slot`: stores the top of the stack in a local, leaving it there.
+  SetLocal,
+  /// `index`: pushes a variable captured by the running closure.
+  GetUpvalue,
+  /// `index`: stores the top of the stack in a captured variable.
+  SetUpvalue,
+  /// `slot`: pushes a global, such as a native function.
+  GetGlobal,
+  /// `slot`: stores the top of the stack in a global.
+  SetGlobal,
+  Equal,
+  NotEqual,
+  Greater,
+  GreaterEqual,
+  Less,
+  LessEqual,
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Not,
+  Negate,
+  /// Replaces the top of the stack with whether it is truthy.
+  Truthy,
+  Print,
+  /// `offset`: jumps forward.
+  Jump,
+  /// `offset`: pops the condition and jumps forward if it is falsy.
+  JumpIfFalse,
+  /// `offset`: jumps backward.
+  Loop,
+  /// `argc`: fails unless the value below where the arguments go is
+  /// callable with `argc` arguments.
+  CheckCall,
+  /// `argc`: calls the value below the arguments.
+  Call,
+  /// `argc`: calls the value below the arguments in place of the
+  /// running function, and returns what it returns.
+  TailCall,
+  /// `function`, then `is_local` and `index` per captured variable:
+  /// pushes a closure of one of the chunk's functions.
+  Closure,
+  /// Moves the local on top of the stack to the heap, for the closures
+  /// that captured it, and pops it.
+  CloseUpvalue,
+  Return,
+  /// Charges one unit of fuel.
+  Tick,
+  /// Widens the operands of the next instruction to four bytes.
+  Wide,
+}
+
+impl OpCode {
+  const ALL: [OpCode; 36] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::GetGlobal,
+    OpCode::SetGlobal,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::Greater,
+    OpCode::GreaterEqual,
+    OpCode::Less,
+    OpCode::LessEqual,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Truthy,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::CheckCall,
+    OpCode::Call,
+    OpCode::TailCall,
+    OpCode::Closure,
+    OpCode::CloseUpvalue,
+    OpCode::Return,
+    OpCode::Tick,
+    OpCode::Wide,
+  ];
+
+  pub fn from_byte(byte: u8) -> Option<OpCode> {
+    OpCode::ALL.get(usize::from(byte)).copied()
+  }
+}
+
+/// The compiled code of one function: its instructions, the constants
+/// and functions they refer to, and where in the source each
+/// instruction comes from.
+#[derive(Debug, Default)]
+pub struct Chunk {
+  code: Vec<u8>,
+  /// The offset where each run of bytes from the same source line
+  /// starts, and the line, in increasing order.
+  lines: Vec<(usize, usize)>,
+  constants: Vec<Value>,
+  functions: Vec<Rc<Function>>,
+  /// The tokens that errors raised by the instructions point at, by
+  /// the offset of the instruction, in increasing order.
+  tokens: Vec<(usize, Token)>,
+}
+
+impl Chunk {
+  pub fn new() -> Chunk {
+    Chunk::default()
+  }
+
+  pub fn code(&self) -> &[u8] {
+    &self.code
+  }
+
+  pub fn len(&self) -> usize {
+    self.code.len()
+  }
+
+  pub fn constant(&self, index: usize) -> &Value {
+    &self.constants[index]
+  }
+
+  pub fn function(&self, index: usize) -> &Rc<Function> {
+    &self.functions[index]
+  }
+
+  pub fn functions(&self) -> &[Rc<Function>] {
+    &self.functions
+  }
+
+  /// The source line of the byte at `offset`.
+  pub fn line(&self, offset: usize) -> usize {
+    let run = self.lines.partition_point(|(start, _)| *start <= offset);
+    self.lines[run - 1].1
+  }
+
+  /// Reads the jump offset at `ip`, four bytes long if it is `wide`,
+  /// and moves `ip` past it.
+  #[inline]
+  pub fn read_jump(&self, ip: &mut usize, wide: bool) -> usize {
+    if wide {
+      return self.read_operand(ip, true);
+    }
+    *ip += 2;
+    usize::from(u16::from_le_bytes([self.code[*ip - 2], self.code[*ip - 1]]))
+  }
+
+  /// Reads the operand at `ip`, four bytes long if it is `wide`, and
+  /// moves `ip` past it.
+  #[inline]
+  pub fn read_operand(&self, ip: &mut usize, wide: bool) -> usize {
+    if wide {
+      let bytes = [
+        self.code[*ip],
+        self.code[*ip + 1],
+        self.code[*ip + 2],
+        self.code[*ip + 3],
+      ];
+      *ip += 4;
+      u32::from_le_bytes(bytes) as usize
+    } else {
+      *ip += 1;
+      usize::from(self.code[*ip - 1])
+    }
+  }
+
+  /// The token of the instruction at `offset`, for its errors.
+  pub fn token(&self, offset: usize) -> &Token {
+    let index = self
+      .tokens
+      .binary_search_by_key(&offset, |(offset, _)| *offset)
+      .expect("expected a token for every instruction that can fail");
+    &self.tokens[index].1
+  }
+
+  /// Appends an instruction and returns its offset.
+  pub fn write_op(&mut self, op: OpCode, line: usize) -> usize {
+    self.write_byte(op as u8, line);
+    self.code.len() - 1
+  }
+
+  /// Appends an instruction whose errors point at `token`.
+  pub fn write_op_with_token(&mut self, op: OpCode, token: &Token) -> usize {
+    let offset = self.write_op(op, token.line());
+    self.tokens.push((offset, token.clone()));
+    offset
+  }
+
+  pub fn write_byte(&mut self, byte: u8, line: usize) {
+    if self.lines.last().is_none_or(|(_, last)| *last != line) {
+      self.lines.push((self.code.len(), line));
+    }
+    self.code.push(byte);
+  }
+
+  /// Appends a jump offset, four bytes long if it is `wide`.
+  pub fn write_jump(&mut self, distance: usize, wide: bool, line: usize) {
+    if wide {
+      self.write_operand(distance, true, line);
+    } else {
+      let distance = u16::try_from(distance).expect("expected a wide jump past 64 KiB");
+      for byte in distance.to_le_bytes() {
+        self.write_byte(byte, line);
+      }
+    }
+  }
+
+  /// Appends an operand, four bytes long if it is `wide`.
+  pub fn write_operand(&mut self, value: usize, wide: bool, line: usize) {
+    if wide {
+      let value = u32::try_from(value).expect("expected an operand that fits in 32 bits");
+      for byte in value.to_le_bytes() {
+        self.write_byte(byte, line);
+      }
+    } else {
+      let value = u8::try_from(value).expect("expected a wide operand above 255");
+      self.write_byte(value, line);
+    }
+  }
+
+  /// Overwrites the jump offset that starts at `offset`.
+  pub fn patch_jump(&mut self, offset: usize, distance: usize, wide: bool) {
+    if wide {
+      let distance = u32::try_from(distance).expect("expected a jump shorter than 4 GiB");
+      self.code[offset..offset + 4].copy_from_slice(&distance.to_le_bytes());
+    } else {
+      let distance = u16::try_from(distance).expect("expected a wide jump past 64 KiB");
+      self.code[offset..offset + 2].copy_from_slice(&distance.to_le_bytes());
+    }
+  }
+
+  pub fn add_constant(&mut self, value: Value) -> usize {
+    self.constants.push(value);
+    self.constants.len() - 1
+  }
+
+  pub fn add_function(&mut self, function: Rc<Function>) -> usize {
+    self.functions.push(function);
+    self.functions.len() - 1
+  }
+}
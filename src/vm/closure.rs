@@ -0,0 +1,98 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use super::Chunk;
+use crate::interpreter::{Interpreter, RuntimeError};
+use crate::parser::{LoxCallable, Value};
+
+/// A compiled function declaration, or the top-level code of a script.
+pub struct Function {
+  name: String,
+  arity: usize,
+  upvalues: usize,
+  chunk: Chunk,
+}
+
+impl Function {
+  pub fn new(name: String, arity: usize, upvalues: usize, chunk: Chunk) -> Function {
+    Function {
+      name,
+      arity,
+      upvalues,
+      chunk,
+    }
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn arity(&self) -> usize {
+    self.arity
+  }
+
+  /// How many variables of enclosing functions the function captures.
+  pub fn upvalues(&self) -> usize {
+    self.upvalues
+  }
+
+  pub fn chunk(&self) -> &Chunk {
+    &self.chunk
+  }
+}
+
+impl fmt::Debug for Function {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "<fn {}/{}>", self.name, self.arity)
+  }
+}
+
+/// A variable captured by a closure. It stays on the stack while the
+/// call that declared it runs, and moves into the upvalue once that
+/// call returns or its block ends.
+#[derive(Debug)]
+pub enum Upvalue {
+  /// The variable in this slot of the stack.
+  Open(usize),
+  Closed(Value),
+}
+
+/// A function together with the variables it captured.
+#[derive(Debug)]
+pub struct Closure {
+  function: Rc<Function>,
+  upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Closure {
+  pub fn new(function: Rc<Function>, upvalues: Vec<Rc<RefCell<Upvalue>>>) -> Closure {
+    Closure { function, upvalues }
+  }
+
+  pub fn function(&self) -> &Rc<Function> {
+    &self.function
+  }
+
+  pub fn upvalues(&self) -> &[Rc<RefCell<Upvalue>>] {
+    &self.upvalues
+  }
+}
+
+impl LoxCallable for Closure {
+  fn name(&self) -> &str {
+    self.function.name()
+  }
+
+  fn arity(&self) -> usize {
+    self.function.arity()
+  }
+
+  /// Runs the closure on a VM of its own, which shares the stack of the
+  /// VM that called the native calling this.
+  fn call(&self, interpreter: &mut Interpreter, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    assert_eq!(self.function.arity(), args.len());
+    let closure = Closure::new(Rc::clone(&self.function), self.upvalues.clone());
+    super::call(interpreter, Rc::new(closure), args)
+  }
+}
@@ -0,0 +1,497 @@
+use std::rc::Rc;
+
+use super::{Chunk, Function, OpCode};
+use crate::lexer::{Token, TokenKind};
+use crate::parser::{Expr, FunctionDecl, Stmt, Value};
+use crate::resolver::{Binding, Resolver};
+
+/// Compiles resolved statements into the chunk of a function that runs
+/// them, using the slots the resolver assigned to every variable.
+///
+/// Every call gets a window of the VM's stack: the callee, then the
+/// arguments, then the variables of the blocks of the body as they are
+/// declared. Variables of enclosing functions are reached through the
+/// upvalues of the closure instead.
+pub struct Compiler<'r> {
+  resolver: &'r Resolver,
+  /// Whether every statement and expression charges fuel.
+  metered: bool,
+  /// The functions being compiled, innermost last.
+  functions: Vec<FunctionState>,
+  /// The scopes the resolver saw, innermost last.
+  scopes: Vec<Scope>,
+  line: usize,
+  /// Whether forward jumps take four bytes, which they do once a jump
+  /// of two bytes turned out too short.
+  wide_jumps: bool,
+  /// Whether a forward jump was too long for two bytes.
+  jump_overflowed: bool,
+}
+
+struct FunctionState {
+  name: String,
+  arity: usize,
+  chunk: Chunk,
+  /// Whether a closure captures the variable, by its slot in the call.
+  locals: Vec<bool>,
+  /// Where each captured variable comes from: a local of the enclosing
+  /// function, or one of its upvalues.
+  upvalues: Vec<(bool, usize)>,
+}
+
+struct Scope {
+  function: usize,
+  /// The slot in the call of the scope's first variable.
+  base: usize,
+}
+
+/// Where the compiled code finds a variable.
+enum Access {
+  Local(usize),
+  Upvalue(usize),
+  Global(usize),
+}
+
+impl<'r> Compiler<'r> {
+  pub fn new(resolver: &'r Resolver, metered: bool) -> Compiler<'r> {
+    Compiler {
+      resolver,
+      metered,
+      functions: Vec::new(),
+      scopes: Vec::new(),
+      line: 1,
+      wide_jumps: false,
+      jump_overflowed: false,
+    }
+  }
+
+  /// Compiles a script into a function that takes no arguments.
+  pub fn compile(self, stmts: &[Stmt]) -> Function {
+    let mut wide = Compiler::new(self.resolver, self.metered);
+    wide.wide_jumps = true;
+    // Forward jumps are emitted before their length is known, so a
+    // script with one too long for two bytes is compiled again.
+    self
+      .compile_script(stmts)
+      .or_else(|| wide.compile_script(stmts))
+      .expect("expected wide jumps to reach anywhere")
+  }
+
+  fn compile_script(mut self, stmts: &[Stmt]) -> Option<Function> {
+    self.begin_function("script", 0);
+    self.begin_scope();
+    for stmt in stmts {
+      self.compile_stmt(stmt);
+    }
+    self.scopes.pop();
+    let function = self.end_function();
+    (!self.jump_overflowed).then_some(function)
+  }
+
+  fn chunk(&mut self) -> &mut Chunk {
+    &mut self
+      .functions
+      .last_mut()
+      .expect("expected a function being compiled")
+      .chunk
+  }
+
+  fn emit(&mut self, op: OpCode) -> usize {
+    let line = self.line;
+    self.chunk().write_op(op, line)
+  }
+
+  fn emit_with_token(&mut self, op: OpCode, token: &Token) -> usize {
+    self.line = token.line();
+    self.chunk().write_op_with_token(op, token)
+  }
+
+  /// Emits an instruction and its operand, behind a `Wide` prefix if
+  /// the operand does not fit in a byte.
+  fn emit_with_operand(&mut self, op: OpCode, operand: usize) {
+    let wide = is_wide(operand);
+    if wide {
+      self.emit(OpCode::Wide);
+    }
+    self.emit(op);
+    self.emit_operand(operand, wide);
+  }
+
+  fn emit_with_token_and_operand(&mut self, op: OpCode, token: &Token, operand: usize) {
+    self.line = token.line();
+    let wide = is_wide(operand);
+    if wide {
+      self.emit(OpCode::Wide);
+    }
+    self.chunk().write_op_with_token(op, token);
+    self.emit_operand(operand, wide);
+  }
+
+  fn emit_operand(&mut self, operand: usize, wide: bool) {
+    let line = self.line;
+    self.chunk().write_operand(operand, wide, line);
+  }
+
+  fn emit_tick(&mut self) {
+    if self.metered {
+      self.emit(OpCode::Tick);
+    }
+  }
+
+  /// Emits a forward jump and returns the offset of its operand.
+  fn emit_jump(&mut self, op: OpCode) -> usize {
+    let wide = self.wide_jumps;
+    if wide {
+      self.emit(OpCode::Wide);
+    }
+    self.emit(op);
+    let line = self.line;
+    self.chunk().write_jump(0, wide, line);
+    self.chunk().len() - jump_width(wide)
+  }
+
+  /// Makes the jump whose operand is at `offset` land here.
+  fn patch_jump(&mut self, offset: usize) {
+    let wide = self.wide_jumps;
+    let distance = self.chunk().len() - offset - jump_width(wide);
+    if !wide && distance > usize::from(u16::MAX) {
+      self.jump_overflowed = true;
+      return;
+    }
+    self.chunk().patch_jump(offset, distance, wide);
+  }
+
+  fn emit_loop(&mut self, start: usize) {
+    // The distance is known, so only loops that need it are wide.
+    let wide = self.chunk().len() + 1 + jump_width(false) - start > usize::from(u16::MAX);
+    if wide {
+      self.emit(OpCode::Wide);
+    }
+    self.emit(OpCode::Loop);
+    let distance = self.chunk().len() + jump_width(wide) - start;
+    let line = self.line;
+    self.chunk().write_jump(distance, wide, line);
+  }
+
+  fn begin_function(&mut self, name: &str, arity: usize) {
+    self.functions.push(FunctionState {
+      name: name.to_string(),
+      arity,
+      chunk: Chunk::new(),
+      // The callee takes the first slot.
+      locals: vec![false],
+      upvalues: Vec::new(),
+    });
+  }
+
+  fn end_function(&mut self) -> Function {
+    self.emit(OpCode::Nil);
+    self.emit(OpCode::Return);
+    let state = self
+      .functions
+      .pop()
+      .expect("expected a function being compiled");
+    Function::new(state.name, state.arity, state.upvalues.len(), state.chunk)
+  }
+
+  fn current(&mut self) -> &mut FunctionState {
+    self
+      .functions
+      .last_mut()
+      .expect("expected a function being compiled")
+  }
+
+  fn begin_scope(&mut self) {
+    let function = self.functions.len() - 1;
+    let base = self.current().locals.len();
+    self.scopes.push(Scope { function, base });
+  }
+
+  /// Pops the variables of the innermost scope, moving the captured
+  /// ones to the heap.
+  fn end_scope(&mut self) {
+    let scope = self.scopes.pop().expect("expected a scope to end");
+    while self.current().locals.len() > scope.base {
+      let captured = self.current().locals.pop() == Some(true);
+      self.emit(if captured {
+        OpCode::CloseUpvalue
+      } else {
+        OpCode::Pop
+      });
+    }
+  }
+
+  /// Adds the variable of the declaration `id`, whose value is about to
+  /// be pushed, to the innermost scope.
+  fn declare(&mut self, id: usize) {
+    let Binding::Local { slot, .. } = self.resolver.binding(id) else {
+      panic!("expected a declaration to bind a local");
+    };
+    let scope = self.scopes.last().expect("expected a scope to declare in");
+    debug_assert_eq!(scope.base + slot, self.current().locals.len());
+    self.current().locals.push(false);
+  }
+
+  fn access(&mut self, id: usize) -> Access {
+    match self.resolver.binding(id) {
+      Binding::Global(slot) => Access::Global(slot),
+      Binding::Local { depth, slot } => {
+        let scope = &self.scopes[self.scopes.len() - 1 - depth];
+        let (function, index) = (scope.function, scope.base + slot);
+        let current = self.functions.len() - 1;
+        if function == current {
+          Access::Local(index)
+        } else {
+          Access::Upvalue(self.upvalue(current, function, index))
+        }
+      }
+    }
+  }
+
+  /// The upvalue through which `function` reaches the local `index` of
+  /// the enclosing function `owner`.
+  fn upvalue(&mut self, function: usize, owner: usize, index: usize) -> usize {
+    let source = if function - 1 == owner {
+      self.functions[owner].locals[index] = true;
+      (true, index)
+    } else {
+      (false, self.upvalue(function - 1, owner, index))
+    };
+    let upvalues = &mut self.functions[function].upvalues;
+    upvalues
+      .iter()
+      .position(|upvalue| *upvalue == source)
+      .unwrap_or_else(|| {
+        upvalues.push(source);
+        upvalues.len() - 1
+      })
+  }
+
+  fn compile_stmt(&mut self, stmt: &Stmt) {
+    self.emit_tick();
+    match stmt {
+      Stmt::PrintStmt { keyword, expr } => {
+        self.compile_expr(expr);
+        self.emit_with_token(OpCode::Print, keyword);
+      }
+      Stmt::ExprStmt { expr } => {
+        self.compile_expr(expr);
+        self.emit(OpCode::Pop);
+      }
+      Stmt::VarDecl { id, variable, expr } => {
+        match expr {
+          Some(expr) => self.compile_expr(expr),
+          None => {
+            self.line = variable.line();
+            self.emit(OpCode::Nil);
+          }
+        }
+        self.declare(*id);
+      }
+      Stmt::Block { stmts } => {
+        self.begin_scope();
+        for stmt in stmts {
+          self.compile_stmt(stmt);
+        }
+        self.end_scope();
+      }
+      Stmt::If {
+        condition,
+        then_stmt,
+        else_stmt,
+      } => {
+        self.compile_expr(condition);
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.compile_stmt(then_stmt);
+        match else_stmt {
+          Some(else_stmt) => {
+            let else_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(then_jump);
+            self.compile_stmt(else_stmt);
+            self.patch_jump(else_jump);
+          }
+          None => self.patch_jump(then_jump),
+        }
+      }
+      Stmt::While { condition, body } => {
+        let start = self.chunk().len();
+        self.compile_expr(condition);
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.compile_stmt(body);
+        self.emit_loop(start);
+        self.patch_jump(exit_jump);
+      }
+      Stmt::FunDecl { id, decl } => {
+        // The function can refer to itself, so its variable comes first.
+        self.declare(*id);
+        self.compile_function(decl);
+      }
+      Stmt::Return { expr, .. } => match expr.as_ref() {
+        // A returned call replaces the function, so that tail calls do
+        // not nest. The script has no caller to return to.
+        Expr::FunCall {
+          callee,
+          paren,
+          args,
+        } if self.functions.len() > 1 => {
+          self.emit_tick();
+          self.compile_call(callee, paren, args, OpCode::TailCall);
+        }
+        expr => {
+          self.compile_expr(expr);
+          self.emit(OpCode::Return);
+        }
+      },
+    }
+  }
+
+  fn compile_function(&mut self, decl: &FunctionDecl) {
+    self.line = decl.name.line();
+    let name = decl.name.extract_identifier();
+    self.begin_function(name.as_str(), decl.params.len());
+    self.begin_scope();
+    for _ in &decl.params {
+      self.current().locals.push(false);
+    }
+    self.compile_stmt(&decl.body);
+    self.scopes.pop();
+    let upvalues = self
+      .functions
+      .last()
+      .expect("expected a function being compiled")
+      .upvalues
+      .clone();
+    let function = self.end_function();
+
+    self.line = decl.name.line();
+    let index = self.chunk().add_function(Rc::new(function));
+    // One prefix widens the function and every upvalue index.
+    let wide = is_wide(index) || upvalues.iter().any(|(_, index)| is_wide(*index));
+    if wide {
+      self.emit(OpCode::Wide);
+    }
+    self.emit(OpCode::Closure);
+    self.emit_operand(index, wide);
+    let line = self.line;
+    for (is_local, index) in upvalues {
+      self.chunk().write_byte(u8::from(is_local), line);
+      self.emit_operand(index, wide);
+    }
+  }
+
+  fn compile_expr(&mut self, expr: &Expr) {
+    self.emit_tick();
+    match expr {
+      Expr::Unary { op, right } => {
+        self.compile_expr(right);
+        match op.kind() {
+          TokenKind::Minus => self.emit_with_token(OpCode::Negate, op),
+          TokenKind::Bang => self.emit_with_token(OpCode::Not, op),
+          _ => panic!("unary node with non-unary token"),
+        };
+      }
+      Expr::Binary { left, op, right } => {
+        self.compile_expr(left);
+        self.compile_expr(right);
+        let code = match op.kind() {
+          TokenKind::Star => OpCode::Multiply,
+          TokenKind::Slash => OpCode::Divide,
+          TokenKind::Plus => OpCode::Add,
+          TokenKind::Minus => OpCode::Subtract,
+          TokenKind::Greater => OpCode::Greater,
+          TokenKind::GreaterEqual => OpCode::GreaterEqual,
+          TokenKind::Less => OpCode::Less,
+          TokenKind::LessEqual => OpCode::LessEqual,
+          TokenKind::EqualEqual => OpCode::Equal,
+          TokenKind::BangEqual => OpCode::NotEqual,
+          _ => panic!("binary node received non-binary token"),
+        };
+        self.emit_with_token(code, op);
+      }
+      Expr::Grouping(expr) => self.compile_expr(expr),
+      Expr::Literal(value) => match value {
+        Value::Nil => {
+          self.emit(OpCode::Nil);
+        }
+        Value::Bool(true) => {
+          self.emit(OpCode::True);
+        }
+        Value::Bool(false) => {
+          self.emit(OpCode::False);
+        }
+        value => {
+          let index = self.chunk().add_constant(value.clone());
+          self.emit_with_operand(OpCode::Constant, index);
+        }
+      },
+      Expr::Variable { id, variable } => {
+        self.line = variable.line();
+        let (op, operand) = match self.access(*id) {
+          Access::Local(slot) => (OpCode::GetLocal, slot),
+          Access::Upvalue(index) => (OpCode::GetUpvalue, index),
+          Access::Global(slot) => (OpCode::GetGlobal, slot),
+        };
+        self.emit_with_operand(op, operand);
+      }
+      Expr::Assignment { id, variable, expr } => {
+        self.compile_expr(expr);
+        self.line = variable.line();
+        let (op, operand) = match self.access(*id) {
+          Access::Local(slot) => (OpCode::SetLocal, slot),
+          Access::Upvalue(index) => (OpCode::SetUpvalue, index),
+          Access::Global(slot) => (OpCode::SetGlobal, slot),
+        };
+        self.emit_with_operand(op, operand);
+      }
+      // Logical operators evaluate to whether the operands are truthy.
+      Expr::Logical { left, op, right } => {
+        self.compile_expr(left);
+        self.line = op.line();
+        let falsy_jump = self.emit_jump(OpCode::JumpIfFalse);
+        match op.kind() {
+          TokenKind::Or => {
+            self.emit(OpCode::True);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(falsy_jump);
+            self.compile_expr(right);
+            self.emit(OpCode::Truthy);
+            self.patch_jump(end_jump);
+          }
+          TokenKind::And => {
+            self.compile_expr(right);
+            self.emit(OpCode::Truthy);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(falsy_jump);
+            self.emit(OpCode::False);
+            self.patch_jump(end_jump);
+          }
+          _ => panic!("logical node received non-logical token"),
+        }
+      }
+      Expr::FunCall {
+        callee,
+        paren,
+        args,
+      } => self.compile_call(callee, paren, args, OpCode::Call),
+    }
+  }
+
+  fn compile_call(&mut self, callee: &Expr, paren: &Token, args: &[Box<Expr>], op: OpCode) {
+    self.compile_expr(callee);
+    // Like the tree-walker, check the callee before the arguments run.
+    self.emit_with_token_and_operand(OpCode::CheckCall, paren, args.len());
+    for arg in args {
+      self.compile_expr(arg);
+    }
+    self.emit_with_token_and_operand(op, paren, args.len());
+  }
+}
+
+fn is_wide(operand: usize) -> bool {
+  operand > usize::from(u8::MAX)
+}
+
+/// The bytes of a jump offset.
+fn jump_width(wide: bool) -> usize {
+  if wide { 4 } else { 2 }
+}
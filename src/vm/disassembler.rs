@@ -0,0 +1,224 @@
+use std::fmt::Write;
+
+use super::{Chunk, Function, OpCode};
+
+/// Renders the chunk of a function, then those of the functions it
+/// declares, in the format of clox: the offset of each instruction, its
+/// source line (`|` when it is the line of the previous byte), its name
+/// and its operands.
+///
+/// ```text
+/// == script ==
+/// 0000    1 OP_CONSTANT         0 '1'
+/// 0002    | OP_PRINT
+/// ```
+pub fn disassemble(function: &Function) -> String {
+  let mut out = String::new();
+  disassemble_function(function, &mut out);
+  out
+}
+
+fn disassemble_function(function: &Function, out: &mut String) {
+  writeln!(out, "== {} ==", function.name()).unwrap();
+  let chunk = function.chunk();
+  let mut offset = 0;
+  while offset < chunk.len() {
+    offset = disassemble_instruction(chunk, offset, out);
+  }
+  for function in chunk.functions() {
+    out.push('\n');
+    disassemble_function(function, out);
+  }
+}
+
+/// Renders the instruction at `offset`, along with the one it prefixes
+/// if it is `Wide`, and returns the offset of the next instruction.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize, out: &mut String) -> usize {
+  instruction(chunk, offset, false, out)
+}
+
+fn instruction(chunk: &Chunk, offset: usize, wide: bool, out: &mut String) -> usize {
+  write!(out, "{offset:04} ").unwrap();
+  if offset > 0 && chunk.line(offset) == chunk.line(offset - 1) {
+    out.push_str("   | ");
+  } else {
+    write!(out, "{:4} ", chunk.line(offset)).unwrap();
+  }
+
+  let byte = chunk.code()[offset];
+  let Some(op) = OpCode::from_byte(byte) else {
+    writeln!(out, "Unknown opcode {byte}").unwrap();
+    return offset + 1;
+  };
+  let name = name(op);
+  let mut ip = offset + 1;
+  match op {
+    OpCode::Wide => {
+      writeln!(out, "{name}").unwrap();
+      return instruction(chunk, ip, true, out);
+    }
+    OpCode::Constant => {
+      let index = chunk.read_operand(&mut ip, wide);
+      writeln!(out, "{name:<16} {index:4} '{}'", chunk.constant(index)).unwrap();
+    }
+    OpCode::GetLocal
+    | OpCode::SetLocal
+    | OpCode::GetUpvalue
+    | OpCode::SetUpvalue
+    | OpCode::GetGlobal
+    | OpCode::SetGlobal
+    | OpCode::CheckCall
+    | OpCode::Call
+    | OpCode::TailCall => {
+      let operand = chunk.read_operand(&mut ip, wide);
+      writeln!(out, "{name:<16} {operand:4}").unwrap();
+    }
+    OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+      let distance = chunk.read_jump(&mut ip, wide);
+      let target = if op == OpCode::Loop {
+        ip - distance
+      } else {
+        ip + distance
+      };
+      writeln!(out, "{name:<16} {offset:4} -> {target}").unwrap();
+    }
+    OpCode::Closure => {
+      let index = chunk.read_operand(&mut ip, wide);
+      let function = chunk.function(index);
+      writeln!(out, "{name:<16} {index:4} <fn {}>", function.name()).unwrap();
+      for _ in 0..function.upvalues() {
+        let start = ip;
+        let kind = if chunk.code()[ip] == 1 {
+          "local"
+        } else {
+          "upvalue"
+        };
+        ip += 1;
+        let index = chunk.read_operand(&mut ip, wide);
+        writeln!(out, "{start:04}    |                     {kind} {index}").unwrap();
+      }
+    }
+    _ => writeln!(out, "{name}").unwrap(),
+  }
+  ip
+}
+
+fn name(op: OpCode) -> &'static str {
+  match op {
+    OpCode::Constant => "OP_CONSTANT",
+    OpCode::Nil => "OP_NIL",
+    OpCode::True => "OP_TRUE",
+    OpCode::False => "OP_FALSE",
+    OpCode::Pop => "OP_POP",
+    OpCode::GetLocal => "OP_GET_LOCAL",
+    OpCode::SetLocal => "OP_SET_LOCAL",
+    OpCode::GetUpvalue => "OP_GET_UPVALUE",
+    OpCode::SetUpvalue => "OP_SET_UPVALUE",
+    OpCode::GetGlobal => "OP_GET_GLOBAL",
+    OpCode::SetGlobal => "OP_SET_GLOBAL",
+    OpCode::Equal => "OP_EQUAL",
+    OpCode::NotEqual => "OP_NOT_EQUAL",
+    OpCode::Greater => "OP_GREATER",
+    OpCode::GreaterEqual => "OP_GREATER_EQUAL",
+    OpCode::Less => "OP_LESS",
+    OpCode::LessEqual => "OP_LESS_EQUAL",
+    OpCode::Add => "OP_ADD",
+    OpCode::Subtract => "OP_SUBTRACT",
+    OpCode::Multiply => "OP_MULTIPLY",
+    OpCode::Divide => "OP_DIVIDE",
+    OpCode::Not => "OP_NOT",
+    OpCode::Negate => "OP_NEGATE",
+    OpCode::Truthy => "OP_TRUTHY",
+    OpCode::Print => "OP_PRINT",
+    OpCode::Jump => "OP_JUMP",
+    OpCode::JumpIfFalse => "OP_JUMP_IF_FALSE",
+    OpCode::Loop => "OP_LOOP",
+    OpCode::CheckCall => "OP_CHECK_CALL",
+    OpCode::Call => "OP_CALL",
+    OpCode::TailCall => "OP_TAIL_CALL",
+    OpCode::Closure => "OP_CLOSURE",
+    OpCode::CloseUpvalue => "OP_CLOSE_UPVALUE",
+    OpCode::Return => "OP_RETURN",
+    OpCode::Tick => "OP_TICK",
+    OpCode::Wide => "OP_WIDE",
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::source::SourceId;
+  use crate::vm::Compiler;
+
+  fn compile(source_code: &str) -> Function {
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    let mut resolver = Resolver::new();
+    resolver.resolve(&stmts);
+    assert!(resolver.errors().is_empty());
+    Compiler::new(&resolver, false).compile(&stmts)
+  }
+
+  #[test]
+  fn prints_every_function() {
+    let source_code = "\
+fun make(n) {
+  fun get() { return n; }
+  return get;
+}
+var get = make(1);
+if (true) print get();
+";
+    let expected = "\
+== script ==
+0000    1 OP_CLOSURE          0 <fn make>
+0002    5 OP_GET_LOCAL        1
+0004    | OP_CHECK_CALL       1
+0006    | OP_CONSTANT         0 '1'
+0008    | OP_CALL             1
+0010    | OP_TRUE
+0011    | OP_JUMP_IF_FALSE   11 -> 21
+0014    6 OP_GET_LOCAL        2
+0016    | OP_CHECK_CALL       0
+0018    | OP_CALL             0
+0020    | OP_PRINT
+0021    | OP_NIL
+0022    | OP_RETURN
+
+== make ==
+0000    2 OP_CLOSURE          0 <fn get>
+0002    |                     local 1
+0004    3 OP_GET_LOCAL        2
+0006    | OP_RETURN
+0007    | OP_POP
+0008    | OP_NIL
+0009    | OP_RETURN
+
+== get ==
+0000    2 OP_GET_UPVALUE      0
+0002    | OP_RETURN
+0003    | OP_NIL
+0004    | OP_RETURN
+";
+    assert_eq!(disassemble(&compile(source_code)), expected);
+  }
+
+  #[test]
+  fn widens_large_operands() {
+    let source_code: String = (0..300).map(|i| format!("print {i}.5;")).collect();
+    let out = disassemble(&compile(&source_code));
+    assert!(
+      out.contains("0765    | OP_CONSTANT       255 '255.5'\n"),
+      "{out}"
+    );
+    assert!(
+      out.contains("0768    | OP_WIDE\n0769    | OP_CONSTANT       256 '256.5'\n"),
+      "{out}"
+    );
+  }
+}
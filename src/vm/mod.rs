@@ -0,0 +1,593 @@
+use std::any::Any;
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::interpreter::{
+  Capability, Completion, Frame, Interpreter, RuntimeError, RuntimeErrorKind, is_equal,
+};
+use crate::lexer::Token;
+use crate::parser::{LoxCallable, Stmt, Value};
+
+mod chunk;
+mod closure;
+mod compiler;
+mod disassembler;
+
+pub use chunk::*;
+pub use closure::*;
+pub use compiler::*;
+pub use disassembler::*;
+
+/// How programs run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+  /// Evaluates the syntax tree directly.
+  #[default]
+  TreeWalker,
+  /// Compiles the syntax tree to bytecode and runs it on a stack machine,
+  /// which is faster and behaves the same.
+  Vm,
+}
+
+/// The stack of the VM, kept by the interpreter while a native runs, so
+/// that the functions the native calls run on top of it.
+#[derive(Default)]
+pub struct Stack {
+  values: Vec<Value>,
+  /// The upvalues that still point into `values`, by increasing slot.
+  open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+  /// The calls running below the top of the stack.
+  depth: usize,
+}
+
+/// Compiles the resolved statements and runs them on the VM, until they
+/// finish or the program calls `exit`. Every run starts with the fuel
+/// given by the limits.
+///
+/// # Errors
+///
+/// Returns the first runtime error raised by the statements.
+pub fn run(interpreter: &mut Interpreter, stmts: &[Stmt]) -> Result<Completion, RuntimeError> {
+  interpreter.refuel();
+  interpreter.check_interrupt()?;
+  let metered = interpreter.limits().fuel().is_some();
+  let script = Compiler::new(interpreter.resolver(), metered).compile(stmts);
+  let script = Rc::new(Closure::new(Rc::new(script), Vec::new()));
+  match call(interpreter, script, Vec::new()) {
+    Ok(_) => Ok(Completion::Finished),
+    Err(err) => match err.exit_code() {
+      Some(code) => Ok(Completion::Exit(code)),
+      None => Err(err),
+    },
+  }
+}
+
+/// Calls a closure with the given arguments, on top of the stack of the
+/// VM that is running, if any.
+pub fn call(
+  interpreter: &mut Interpreter,
+  closure: Rc<Closure>,
+  args: Vec<Value>,
+) -> Result<Value, RuntimeError> {
+  let stack = std::mem::take(interpreter.vm_stack());
+  let trace = interpreter.is_tracing();
+  let mut vm = Vm {
+    interpreter,
+    stack: stack.values,
+    open_upvalues: stack.open_upvalues,
+    depth: stack.depth,
+    frames: Vec::new(),
+    trace,
+  };
+  let result = vm.call(closure, args);
+  *vm.interpreter.vm_stack() = Stack {
+    values: vm.stack,
+    open_upvalues: vm.open_upvalues,
+    depth: vm.depth,
+  };
+  result
+}
+
+/// A call running on the VM.
+struct CallFrame {
+  closure: Rc<Closure>,
+  /// The offset of the next instruction.
+  ip: usize,
+  /// The slot of the callee, below the arguments.
+  base: usize,
+  /// The offset of the instruction of the caller that made the call.
+  call_site: usize,
+}
+
+struct Vm<'i, 'a> {
+  interpreter: &'i mut Interpreter<'a>,
+  stack: Vec<Value>,
+  open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+  /// The calls running outside of this VM, such as the native that
+  /// called the closure it runs.
+  depth: usize,
+  /// The callers of the running call.
+  frames: Vec<CallFrame>,
+  /// Whether to print the stack and each instruction before running it.
+  trace: bool,
+}
+
+impl Vm<'_, '_> {
+  fn call(&mut self, closure: Rc<Closure>, args: Vec<Value>) -> Result<Value, RuntimeError> {
+    let base = self.stack.len();
+    self
+      .stack
+      .push(Value::Callable(Rc::clone(&closure) as Rc<dyn LoxCallable>));
+    self.stack.extend(args);
+    let mut frame = CallFrame {
+      closure,
+      ip: 0,
+      base,
+      call_site: 0,
+    };
+    let mut result = self.execute(&mut frame);
+    if let Err(err) = &mut result {
+      if err.exit_code().is_none() {
+        self.unwind(frame, err);
+      }
+      self.close_upvalues(base);
+      self.stack.truncate(base);
+      self.frames.clear();
+    }
+    result
+  }
+
+  /// Adds the calls that were running to the backtrace of the error,
+  /// innermost first. The call the VM started with is not one of them.
+  fn unwind(&mut self, frame: CallFrame, err: &mut RuntimeError) {
+    let mut callee = frame;
+    while let Some(caller) = self.frames.pop() {
+      let call_site = caller.closure.function().chunk().token(callee.call_site);
+      err.push_frame(Frame::new(callee.closure, call_site.clone()));
+      callee = caller;
+    }
+  }
+
+  /// Runs instructions until the call in `frame` returns.
+  fn execute(&mut self, frame: &mut CallFrame) -> Result<Value, RuntimeError> {
+    let trace = self.trace;
+    let mut wide = false;
+    loop {
+      let chunk = frame.closure.function().chunk();
+      let offset = frame.ip;
+      if trace && !wide {
+        self.trace_instruction(chunk, offset);
+      }
+      let op = OpCode::from_byte(chunk.code()[offset]).expect("expected a valid opcode");
+      frame.ip += 1;
+      let wide = std::mem::replace(&mut wide, op == OpCode::Wide);
+      match op {
+        OpCode::Constant => {
+          let index = chunk.read_operand(&mut frame.ip, wide);
+          self.stack.push(chunk.constant(index).clone());
+        }
+        OpCode::Nil => self.stack.push(Value::Nil),
+        OpCode::True => self.stack.push(Value::Bool(true)),
+        OpCode::False => self.stack.push(Value::Bool(false)),
+        OpCode::Pop => {
+          self.pop();
+        }
+        OpCode::GetLocal => {
+          let slot = frame.base + chunk.read_operand(&mut frame.ip, wide);
+          self.stack.push(self.stack[slot].clone());
+        }
+        OpCode::SetLocal => {
+          let slot = frame.base + chunk.read_operand(&mut frame.ip, wide);
+          self.stack[slot] = self.peek(0).clone();
+        }
+        OpCode::GetUpvalue => {
+          let index = chunk.read_operand(&mut frame.ip, wide);
+          let value = match &*frame.closure.upvalues()[index].borrow() {
+            Upvalue::Open(slot) => self.stack[*slot].clone(),
+            Upvalue::Closed(value) => value.clone(),
+          };
+          self.stack.push(value);
+        }
+        OpCode::SetUpvalue => {
+          let index = chunk.read_operand(&mut frame.ip, wide);
+          let value = self.peek(0).clone();
+          match &mut *frame.closure.upvalues()[index].borrow_mut() {
+            Upvalue::Open(slot) => self.stack[*slot] = value,
+            Upvalue::Closed(closed) => *closed = value,
+          }
+        }
+        OpCode::GetGlobal => {
+          let slot = chunk.read_operand(&mut frame.ip, wide);
+          let value = self.interpreter.global(slot).clone();
+          self.stack.push(value);
+        }
+        OpCode::SetGlobal => {
+          let slot = chunk.read_operand(&mut frame.ip, wide);
+          let value = self.peek(0).clone();
+          self.interpreter.set_global(slot, value);
+        }
+        OpCode::Equal | OpCode::NotEqual => {
+          let right = self.pop();
+          let left = self.pop();
+          let equal = is_equal(&left, &right);
+          self.stack.push(Value::Bool(equal == (op == OpCode::Equal)));
+        }
+        OpCode::Greater
+        | OpCode::GreaterEqual
+        | OpCode::Less
+        | OpCode::LessEqual
+        | OpCode::Add
+        | OpCode::Subtract
+        | OpCode::Multiply
+        | OpCode::Divide => {
+          let right = self.pop();
+          let left = self.pop();
+          let result = match (op, &left, &right) {
+            (OpCode::Greater, Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
+            (OpCode::GreaterEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a >= b),
+            (OpCode::Less, Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
+            (OpCode::LessEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a <= b),
+            (OpCode::Add, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            (OpCode::Subtract, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+            (OpCode::Multiply, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+            (OpCode::Divide, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+            _ => self.interpreter.binary(left, chunk.token(offset), right)?,
+          };
+          self.stack.push(result);
+        }
+        OpCode::Not => {
+          let value = self.pop();
+          self.stack.push(Value::Bool(value.is_falsy()));
+        }
+        OpCode::Negate => match self.pop() {
+          Value::Number(n) => self.stack.push(Value::Number(-n)),
+          _ => {
+            return Err(RuntimeErrorKind::ExpectedNumber(chunk.token(offset).clone()).into());
+          }
+        },
+        OpCode::Truthy => {
+          let value = self.pop();
+          self.stack.push(Value::Bool(value.is_truthy()));
+        }
+        OpCode::Print => {
+          let value = self.pop();
+          self
+            .interpreter
+            .check_capability(Capability::IoStdout, chunk.token(offset))?;
+          writeln!(self.interpreter.out(), "{value}")
+            .expect("expected that writing to out buffer works");
+        }
+        OpCode::Jump => {
+          let distance = chunk.read_jump(&mut frame.ip, wide);
+          frame.ip += distance;
+        }
+        OpCode::JumpIfFalse => {
+          let distance = chunk.read_jump(&mut frame.ip, wide);
+          if self.pop().is_falsy() {
+            frame.ip += distance;
+          }
+        }
+        OpCode::Loop => {
+          let distance = chunk.read_jump(&mut frame.ip, wide);
+          frame.ip -= distance;
+          self.interpreter.check_interrupt()?;
+        }
+        OpCode::CheckCall => {
+          let argc = chunk.read_operand(&mut frame.ip, wide);
+          match self.peek(0) {
+            Value::Callable(callable) if callable.arity() == argc => {}
+            Value::Callable(_) => {
+              return Err(
+                RuntimeErrorKind::CallableBadArgsCount(chunk.token(offset).clone()).into(),
+              );
+            }
+            _ => {
+              return Err(RuntimeErrorKind::ExpectedCallable(chunk.token(offset).clone()).into());
+            }
+          }
+        }
+        OpCode::Call => {
+          let argc = chunk.read_operand(&mut frame.ip, wide);
+          self.interpreter.check_interrupt()?;
+          let base = self.stack.len() - 1 - argc;
+          let Value::Callable(callable) = &self.stack[base] else {
+            panic!("expected the callee to be checked before the call");
+          };
+          let callable = Rc::clone(callable);
+          let call_site = chunk.token(offset);
+          if let Some(capability) = callable.capability() {
+            self.interpreter.check_capability(capability, call_site)?;
+          }
+          self.check_stack(call_site)?;
+          match (Rc::clone(&callable) as Rc<dyn Any>).downcast::<Closure>() {
+            Ok(closure) => {
+              let callee = CallFrame {
+                closure,
+                ip: 0,
+                base,
+                call_site: offset,
+              };
+              self.frames.push(std::mem::replace(frame, callee));
+            }
+            Err(_) => match self.call_native(&callable, base, call_site) {
+              Ok(value) => self.stack.push(value),
+              Err(mut err) => {
+                if err.exit_code().is_none() {
+                  err.push_frame(Frame::new(callable, call_site.clone()));
+                }
+                return Err(err);
+              }
+            },
+          }
+        }
+        OpCode::TailCall => {
+          let argc = chunk.read_operand(&mut frame.ip, wide);
+          self.interpreter.check_interrupt()?;
+          let base = self.stack.len() - 1 - argc;
+          let Value::Callable(callable) = &self.stack[base] else {
+            panic!("expected the callee to be checked before the call");
+          };
+          let callable = Rc::clone(callable);
+          let call_site = chunk.token(offset);
+          if let Some(capability) = callable.capability() {
+            self.interpreter.check_capability(capability, call_site)?;
+          }
+          match (Rc::clone(&callable) as Rc<dyn Any>).downcast::<Closure>() {
+            // The callee and its arguments take the slots of the running
+            // function, which keeps its caller and call site.
+            Ok(closure) => {
+              self.close_upvalues(frame.base);
+              self.stack.drain(frame.base..base);
+              frame.closure = closure;
+              frame.ip = 0;
+            }
+            Err(_) => {
+              self.check_stack(call_site)?;
+              let value = match self.call_native(&callable, base, call_site) {
+                Ok(value) => value,
+                Err(mut err) => {
+                  if err.exit_code().is_none() {
+                    err.push_frame(Frame::new(callable, call_site.clone()));
+                  }
+                  return Err(err);
+                }
+              };
+              if let Some(value) = self.finish_call(frame, value) {
+                return Ok(value);
+              }
+            }
+          }
+        }
+        OpCode::Closure => {
+          let function = Rc::clone(chunk.function(chunk.read_operand(&mut frame.ip, wide)));
+          let mut upvalues = Vec::with_capacity(function.upvalues());
+          for _ in 0..function.upvalues() {
+            let is_local = chunk.code()[frame.ip] == 1;
+            frame.ip += 1;
+            let index = chunk.read_operand(&mut frame.ip, wide);
+            upvalues.push(if is_local {
+              self.capture(frame.base + index)
+            } else {
+              Rc::clone(&frame.closure.upvalues()[index])
+            });
+          }
+          let closure = self
+            .interpreter
+            .allocate_closure(Closure::new(function, upvalues));
+          self.stack.push(Value::Callable(closure));
+          if self.interpreter.should_collect() {
+            self.interpreter.collect_garbage();
+          }
+        }
+        OpCode::CloseUpvalue => {
+          self.close_upvalues(self.stack.len() - 1);
+          self.pop();
+        }
+        OpCode::Return => {
+          let value = self.pop();
+          if let Some(value) = self.finish_call(frame, value) {
+            return Ok(value);
+          }
+        }
+        OpCode::Tick => self.interpreter.tick()?,
+        OpCode::Wide => {}
+      }
+    }
+  }
+
+  /// Returns `value` from the call in `frame` to its caller, or hands it
+  /// back when the call is the one the VM started with.
+  fn finish_call(&mut self, frame: &mut CallFrame, value: Value) -> Option<Value> {
+    self.close_upvalues(frame.base);
+    self.stack.truncate(frame.base);
+    match self.frames.pop() {
+      Some(caller) => {
+        *frame = caller;
+        self.stack.push(value);
+        None
+      }
+      None => Some(value),
+    }
+  }
+
+  /// Prints the stack and the instruction at `offset` to the trace
+  /// writer of the interpreter.
+  #[cold]
+  fn trace_instruction(&mut self, chunk: &Chunk, offset: usize) {
+    let mut trace = String::from("          ");
+    for value in &self.stack {
+      write!(trace, "[ {value} ]").unwrap();
+    }
+    trace.push('\n');
+    disassemble_instruction(chunk, offset, &mut trace);
+    if let Some(out) = self.interpreter.trace_writer() {
+      write!(out, "{trace}").expect("expected that writing the trace works");
+    }
+  }
+
+  /// Calls a native whose arguments are on top of the stack, above the
+  /// native in `base`.
+  fn call_native(
+    &mut self,
+    native: &Rc<dyn LoxCallable>,
+    base: usize,
+    call_site: &Token,
+  ) -> Result<Value, RuntimeError> {
+    let args = self.stack.split_off(base + 1);
+    self.stack.truncate(base);
+    let stack = Stack {
+      values: std::mem::take(&mut self.stack),
+      open_upvalues: std::mem::take(&mut self.open_upvalues),
+      depth: self.depth + self.frames.len() + 1,
+    };
+    *self.interpreter.vm_stack() = stack;
+    let result = native.call(self.interpreter, args);
+    let stack = std::mem::take(self.interpreter.vm_stack());
+    self.stack = stack.values;
+    self.open_upvalues = stack.open_upvalues;
+    let value = result?;
+    self.interpreter.check_memory(call_site)?;
+    Ok(value)
+  }
+
+  /// Fails before a call would nest deeper than the limits allow. The
+  /// VM keeps its calls on the heap, so the stack bytes it counts are
+  /// the ones of its own stack.
+  fn check_stack(&self, call_site: &Token) -> Result<(), RuntimeError> {
+    let limits = self.interpreter.limits();
+    let stack_used =
+      self.stack.len() * size_of::<Value>() + self.frames.len() * size_of::<CallFrame>();
+    if self.depth + self.frames.len() >= limits.max_call_depth()
+      || stack_used > limits.max_stack_bytes()
+    {
+      return Err(RuntimeErrorKind::StackOverflow(call_site.clone()).into());
+    }
+    Ok(())
+  }
+
+  fn pop(&mut self) -> Value {
+    self.stack.pop().expect("expected a value on the stack")
+  }
+
+  fn peek(&self, distance: usize) -> &Value {
+    &self.stack[self.stack.len() - 1 - distance]
+  }
+
+  /// The upvalue of the variable in `slot`, shared by every closure that
+  /// captures it while it is on the stack.
+  fn capture(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+    let position = self
+      .open_upvalues
+      .iter()
+      .rposition(|upvalue| open_slot(upvalue) <= slot);
+    if let Some(position) = position
+      && open_slot(&self.open_upvalues[position]) == slot
+    {
+      return Rc::clone(&self.open_upvalues[position]);
+    }
+    let upvalue = Rc::new(RefCell::new(Upvalue::Open(slot)));
+    let index = position.map_or(0, |position| position + 1);
+    self.open_upvalues.insert(index, Rc::clone(&upvalue));
+    upvalue
+  }
+
+  /// Moves the variables from `slot` up into their upvalues.
+  fn close_upvalues(&mut self, slot: usize) {
+    while let Some(upvalue) = self.open_upvalues.last() {
+      let open = open_slot(upvalue);
+      if open < slot {
+        break;
+      }
+      let value = self.stack.get(open).cloned().unwrap_or(Value::Nil);
+      *upvalue.borrow_mut() = Upvalue::Closed(value);
+      self.open_upvalues.pop();
+    }
+  }
+}
+
+fn open_slot(upvalue: &Rc<RefCell<Upvalue>>) -> usize {
+  match *upvalue.borrow() {
+    Upvalue::Open(slot) => slot,
+    Upvalue::Closed(_) => panic!("expected an open upvalue"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::interpreter::NativeFunction;
+  use crate::lexer::Lexer;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::source::SourceId;
+
+  #[test]
+  fn natives_call_closures_on_the_same_stack() {
+    let source_code = "
+      fun outer() {
+        var count = 0;
+        fun add(n) { count = count + n; return count; }
+        apply(add, 2);
+        return apply(add, 3) + count;
+      }
+      print outer();
+    ";
+    let mut out = Vec::new();
+    let mut interpreter = Interpreter::new(Resolver::new());
+    interpreter.set_out_writer(Box::new(&mut out));
+    interpreter.define_native(NativeFunction::with_interpreter(
+      "apply",
+      2,
+      |interpreter, mut args| {
+        let arg = args.pop().unwrap();
+        let Some(Value::Callable(callable)) = args.pop() else {
+          panic!("expected a callable");
+        };
+        callable.call(interpreter, vec![arg])
+      },
+    ));
+    let tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+      .tokenize()
+      .unwrap();
+    let stmts = Parser::new(tokens).parse();
+    interpreter.resolver_mut().resolve(&stmts);
+    assert!(interpreter.resolver_mut().errors().is_empty());
+    assert_eq!(run(&mut interpreter, &stmts).unwrap(), Completion::Finished);
+    drop(interpreter);
+    assert_eq!(String::from_utf8(out).unwrap(), "10\n");
+  }
+
+  #[test]
+  fn jumps_over_bodies_larger_than_a_short_jump() {
+    let lex = |source_code: &str| {
+      let mut tokens = Lexer::new(source_code, SourceId::detached("main.lox"))
+        .tokenize()
+        .unwrap();
+      tokens.pop();
+      tokens
+    };
+    let statement = lex("total = total + 1;");
+    let body: Vec<Token> = (0..10000).flat_map(|_| statement.clone()).collect();
+    let mut tokens = lex("var total = 0; var i = 0; while (i < 2) { if (i > 0) {");
+    tokens.extend(body.iter().cloned());
+    tokens.extend(lex("}"));
+    tokens.extend(body);
+    tokens.extend(lex("i = i + 1; } print total;"));
+    tokens.extend(
+      Lexer::new("", SourceId::detached("main.lox"))
+        .tokenize()
+        .unwrap(),
+    );
+
+    let mut out = Vec::new();
+    let mut interpreter = Interpreter::new(Resolver::new());
+    interpreter.set_out_writer(Box::new(&mut out));
+    let stmts = Parser::new(tokens).parse();
+    interpreter.resolver_mut().resolve(&stmts);
+    assert!(interpreter.resolver_mut().errors().is_empty());
+    assert_eq!(run(&mut interpreter, &stmts).unwrap(), Completion::Finished);
+    drop(interpreter);
+    assert_eq!(String::from_utf8(out).unwrap(), "30000\n");
+  }
+}
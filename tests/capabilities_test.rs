@@ -0,0 +1,87 @@
+mod common;
+
+use common::run_with_limits;
+use rlox::{Capabilities, Capability, Limits, LoxError, ResolveError, RuntimeErrorKind, Session};
+
+fn run_with(source_code: &str, capabilities: Capabilities) -> Result<String, Vec<LoxError>> {
+  let (out, result) = run_with_limits(
+    source_code,
+    Limits::default().with_capabilities(capabilities),
+  );
+  result.map(|_| out)
+}
+
+fn disabled_capability(errs: &[LoxError]) -> Capability {
+  match &errs[0] {
+    LoxError::ResolveError(ResolveError::CapabilityDisabled(_, capability)) => *capability,
+    err => panic!("expected a disabled capability, found {err:?}"),
+  }
+}
+
+#[test]
+fn every_capability_is_granted_by_default() {
+  let out = run_with("write(clock() > 0); print \"!\";", Capabilities::default()).unwrap();
+  assert_eq!(out, "true!\n");
+}
+
+#[test]
+fn print_needs_stdout() {
+  let errs = run_with("print 1;", Capabilities::none()).unwrap_err();
+  assert_eq!(disabled_capability(&errs), Capability::IoStdout);
+  assert_eq!(errs[0].code(), "E0305");
+  assert!(errs[0].render("print 1;").contains("--> main.lox:1:1"));
+
+  let errs = run_with("var a = 1; print a;", Capabilities::none()).unwrap_err();
+  assert_eq!(errs.len(), 1);
+  assert_eq!(disabled_capability(&errs), Capability::IoStdout);
+}
+
+#[test]
+fn natives_of_disabled_capabilities_fail_to_resolve() {
+  let capabilities = Capabilities::all().without(Capability::Env);
+  let errs = run_with("fun f() { return env(\"HOME\"); }", capabilities).unwrap_err();
+  assert_eq!(disabled_capability(&errs), Capability::Env);
+
+  let errs = run_with("read_file(\"main.lox\");", Capabilities::none()).unwrap_err();
+  assert_eq!(disabled_capability(&errs), Capability::FsRead);
+}
+
+#[test]
+fn ungrouped_natives_need_no_capability() {
+  let out = run_with("args(); exit(0);", Capabilities::none());
+  assert_eq!(out.unwrap(), "");
+}
+
+#[test]
+fn local_variables_may_shadow_disabled_natives() {
+  let source_code = "fun f(clock) { return clock; } var write = 1; f(write);";
+  assert!(run_with(source_code, Capabilities::none()).is_ok());
+}
+
+#[test]
+fn calling_a_captured_native_is_denied_at_runtime() {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  session.run("var now = clock;").unwrap();
+  let capabilities = Capabilities::all().without(Capability::Time);
+  session.set_limits(Limits::default().with_capabilities(capabilities));
+  let errs = session.run("now();").unwrap_err();
+  match &errs[0] {
+    LoxError::RuntimeError(err) => assert!(matches!(
+      err.kind(),
+      RuntimeErrorKind::PermissionDenied(_, Capability::Time)
+    )),
+    err => panic!("expected a runtime error, found {err:?}"),
+  }
+  assert_eq!(errs[0].code(), "E0411");
+}
+
+#[test]
+fn files_can_be_written_and_read_back() {
+  let path = std::env::temp_dir().join(format!("rlox-capabilities-{}.txt", std::process::id()));
+  let path = path.to_str().unwrap().replace('\\', "/");
+  let source_code = format!("print write_file(\"{path}\", \"lox\"); print read_file(\"{path}\");");
+  let out = run_with(&source_code, Capabilities::all()).unwrap();
+  std::fs::remove_file(&path).unwrap();
+  assert_eq!(out, "true\nlox\n");
+}
@@ -0,0 +1,278 @@
+use std::process::{Command, Output};
+
+fn rlox(args: &[&str]) -> Output {
+  Command::new(env!("CARGO_BIN_EXE_rlox"))
+    .args(args)
+    .output()
+    .unwrap()
+}
+
+#[test]
+fn runs_inline_code() {
+  let out = rlox(&["-e", "print 1 + 2;"]);
+  assert_eq!(out.status.code(), Some(0));
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "3\n");
+}
+
+#[test]
+fn exits_with_65_on_static_errors() {
+  let out = rlox(&["check", "-e", "print a;"]);
+  assert_eq!(out.status.code(), Some(65));
+  assert!(out.stdout.is_empty());
+  assert!(!out.stderr.is_empty());
+}
+
+#[test]
+fn check_reports_capabilities_it_does_not_allow() {
+  let out = rlox(&["check", "--allow=io.stdout", "-e", "print clock();"]);
+  assert_eq!(out.status.code(), Some(65));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.starts_with("error[E0305]"), "{stderr}");
+  assert_eq!(
+    rlox(&["check", "--allow=io.stdout,time", "-e", "print clock();"])
+      .status
+      .code(),
+    Some(0)
+  );
+}
+
+#[test]
+fn exits_with_70_on_runtime_errors() {
+  let out = rlox(&["run", "-e", "print 1; print -\"a\";"]);
+  assert_eq!(out.status.code(), Some(70));
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "1\n");
+  assert!(!out.stderr.is_empty());
+}
+
+#[test]
+fn exits_with_64_on_bad_usage() {
+  assert_eq!(rlox(&["--bogus"]).status.code(), Some(64));
+  assert_eq!(rlox(&["check"]).status.code(), Some(64));
+  assert_eq!(
+    rlox(&["--opt-level=2", "-e", "print 1;"]).status.code(),
+    Some(64)
+  );
+  assert_eq!(rlox(&["repl", "--backend=vm"]).status.code(), Some(64));
+}
+
+#[test]
+fn runs_at_every_opt_level() {
+  for level in ["--opt-level=0", "--opt-level=1"] {
+    let out = rlox(&[level, "-e", "if (1 < 2) print 60 * 60; else print nil;"]);
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(String::from_utf8(out.stdout).unwrap(), "3600\n");
+  }
+}
+
+#[test]
+fn prints_ast() {
+  let out = rlox(&["ast", "-e", "var a = 1 + 2;"]);
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "(var a (+ 1 2))\n");
+}
+
+#[test]
+fn prints_bytecode() {
+  let out = rlox(&["bytecode", "-e", "print 1 + 2;"]);
+  assert_eq!(
+    String::from_utf8(out.stdout).unwrap(),
+    "\
+== script ==
+0000    1 OP_CONSTANT         0 '1'
+0002    | OP_CONSTANT         1 '2'
+0004    | OP_ADD
+0005    | OP_PRINT
+0006    | OP_NIL
+0007    | OP_RETURN
+"
+  );
+  let out = rlox(&["bytecode", "--opt-level=1", "-e", "print 1 + 2;"]);
+  assert!(
+    String::from_utf8(out.stdout)
+      .unwrap()
+      .starts_with("== script ==\n0000    1 OP_CONSTANT         0 '3'\n0002    | OP_PRINT\n")
+  );
+}
+
+#[test]
+fn traces_the_vm() {
+  let out = rlox(&[
+    "--backend=vm",
+    "--trace",
+    "--opt-level=0",
+    "-e",
+    "print 1 + 2;",
+  ]);
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "3\n");
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.contains(
+    "          [ <callable script> ][ 1 ][ 2 ]\n0004    | OP_ADD\n          [ <callable script> ][ 3 ]\n"
+  ));
+}
+
+#[test]
+fn dumps_the_bytecode_before_running() {
+  let out = rlox(&["--dump-bytecode", "--opt-level=1", "-e", "print 1 + 2;"]);
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "3\n");
+  assert_eq!(
+    String::from_utf8(out.stderr).unwrap(),
+    "== script ==\n0000    1 OP_CONSTANT         0 '3'\n0002    | OP_PRINT\n0003    | OP_NIL\n0004    | OP_RETURN\n"
+  );
+}
+
+#[test]
+fn runs_compiled_programs() {
+  let dir = std::env::temp_dir();
+  let script = dir.join(format!("rlox-compiled-{}.lox", std::process::id()));
+  let compiled = script.with_extension("loxc");
+  std::fs::write(&script, "print args();\nprint 1 + nil;\n").unwrap();
+  let out = rlox(&["compile", script.to_str().unwrap()]);
+  assert_eq!(out.status.code(), Some(0));
+  std::fs::remove_file(&script).unwrap();
+
+  let out = rlox(&[compiled.to_str().unwrap(), "a"]);
+  assert_eq!(out.status.code(), Some(70));
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "[a]\n");
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.contains("2 | print 1 + nil;"), "{stderr}");
+
+  let bytes = std::fs::read(&compiled).unwrap();
+  std::fs::write(&compiled, &bytes[..bytes.len() / 2]).unwrap();
+  let out = rlox(&[compiled.to_str().unwrap()]);
+  std::fs::remove_file(&compiled).unwrap();
+  assert_eq!(out.status.code(), Some(65));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.starts_with("error[E0503]: compiled program is corrupt"));
+}
+
+#[test]
+fn compile_needs_an_output_for_inline_code() {
+  assert_eq!(rlox(&["compile", "-e", "print 1;"]).status.code(), Some(64));
+}
+
+#[test]
+fn passes_script_arguments_and_exit_code() {
+  let out = rlox(&["-e", "print args(); exit(3);", "--", "-a", "b"]);
+  assert_eq!(out.status.code(), Some(3));
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "[-a, b]\n");
+}
+
+#[test]
+fn prints_json_errors() {
+  let out = rlox(&[
+    "check",
+    "--error-format=json",
+    "-e",
+    "var a = 1;\nvar a = 2;\nprint a;",
+  ]);
+  assert_eq!(out.status.code(), Some(65));
+  assert_eq!(
+    String::from_utf8(out.stderr).unwrap(),
+    "{\"code\":\"E0304\",\"severity\":\"error\",\
+     \"message\":\"variable 'a' is already declared in this block\",\
+     \"file\":\"<eval>\",\"start_line\":2,\"start_col\":5,\"end_line\":2,\"end_col\":6,\
+     \"label\":\"declared again here\",\
+     \"related\":[{\"file\":\"<eval>\",\"start_line\":1,\"start_col\":5,\
+     \"end_line\":1,\"end_col\":6,\"message\":\"previously declared here\"}],\
+     \"notes\":[],\"help\":\"assign to the existing variable or rename this one\"}\n"
+  );
+}
+
+#[test]
+fn prints_one_json_object_per_error() {
+  let out = rlox(&["--error-format=json", "-e", "print a; print b;"]);
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert_eq!(stderr.lines().count(), 2);
+  assert!(
+    stderr
+      .lines()
+      .all(|line| line.starts_with("{\"code\":\"E0303\""))
+  );
+}
+
+#[test]
+fn deep_recursion_is_a_runtime_error() {
+  let out = rlox(&["-e", "fun f() { return 1 + f(); } f();"]);
+  assert_eq!(out.status.code(), Some(70));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.starts_with("error[E0407]: maximum call depth exceeded\n"));
+  assert!(stderr.contains("... and 984 more calls\n"));
+
+  let source_code = "fun f(n) { if (n > 0) { return 1 + f(n - 1); } return 0; } print f(1500);";
+  let out = rlox(&["--max-call-depth=2000", "-e", source_code]);
+  assert_eq!(String::from_utf8(out.stdout).unwrap(), "1500\n");
+}
+
+#[test]
+fn vm_backend_behaves_like_the_tree_walker() {
+  let source_code = "fun f(n) { print n; return n / -nil; }\nfun g() { return f(1) + 1; }\ng();";
+  let tree = rlox(&["--backend=tree", "-e", source_code]);
+  let vm = rlox(&["--backend=vm", "-e", source_code]);
+  assert_eq!(vm.status.code(), Some(70));
+  assert_eq!(vm.status.code(), tree.status.code());
+  assert_eq!(String::from_utf8(vm.stdout).unwrap(), "1\n");
+  assert_eq!(vm.stderr, tree.stderr);
+}
+
+#[test]
+fn stops_when_out_of_fuel() {
+  let out = rlox(&["--fuel=1000", "-e", "while (true) {}"]);
+  assert_eq!(out.status.code(), Some(70));
+  let stderr = String::from_utf8(out.stderr).unwrap();
+  assert!(stderr.starts_with("error[E0408]: execution budget exhausted\n"));
+}
+
+// Ctrl-C only cancels programs on the targets whose signal handling
+// rlox knows.
+#[cfg(all(
+  any(target_os = "linux", target_os = "macos"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[test]
+fn ctrl_c_interrupts_the_script() {
+  let child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+    .args(["-e", "while (true) {}"])
+    .stderr(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  std::thread::sleep(std::time::Duration::from_millis(300));
+  let status = Command::new("kill")
+    .args(["-INT", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+  let out = child.wait_with_output().unwrap();
+  assert_eq!(out.status.code(), Some(130));
+  assert!(
+    String::from_utf8(out.stderr)
+      .unwrap()
+      .starts_with("error[E0409]: interrupted\n")
+  );
+}
+
+// Ctrl-C only cancels programs on the targets whose signal handling
+// rlox knows.
+#[cfg(all(
+  any(target_os = "linux", target_os = "macos"),
+  any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+#[test]
+fn ctrl_c_ends_the_repl_while_it_waits_for_input() {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_rlox"))
+    .arg("repl")
+    .stdin(std::process::Stdio::piped())
+    .stdout(std::process::Stdio::piped())
+    .spawn()
+    .unwrap();
+  let mut prompt = [0; 2];
+  std::io::Read::read_exact(child.stdout.as_mut().unwrap(), &mut prompt).unwrap();
+  assert_eq!(&prompt, b"> ");
+  std::thread::sleep(std::time::Duration::from_millis(100));
+  let status = Command::new("kill")
+    .args(["-INT", &child.id().to_string()])
+    .status()
+    .unwrap();
+  assert!(status.success());
+  // Waiting closes stdin, which would end the REPL on its own.
+  let _stdin = child.stdin.take();
+  assert_eq!(child.wait().unwrap().code(), Some(130));
+}
@@ -1,5 +1,68 @@
+//! Helpers shared by the integration tests, each of which only uses some.
+#![allow(dead_code)]
+
+use std::io::Write;
+
+use rlox::{Backend, Completion, Limits, LoxError, RunOptions};
+
+/// What a program printed, and how it stopped.
+pub type Run = (String, Result<Completion, Vec<LoxError>>);
+
+/// Runs the source code on both backends, which must print the same.
 pub fn run_and_capture_output(source_code: &str) -> String {
-  let mut out_buf = Vec::new();
-  rlox::run_source_code(source_code, Some(Box::new(&mut out_buf))).unwrap();
-  String::from_utf8(out_buf).unwrap().trim().to_string()
+  let tree = run_on(Backend::TreeWalker, source_code);
+  let vm = run_on(Backend::Vm, source_code);
+  assert_eq!(tree, vm, "expected the backends to print the same");
+  tree
+}
+
+fn run_on(backend: Backend, source_code: &str) -> String {
+  let options = RunOptions::default().with_backend(backend);
+  let (out, result) = run_with_options(source_code, Limits::default(), options);
+  result.unwrap();
+  out.trim().to_string()
+}
+
+/// Runs the source code as `main.lox`, returning what it printed and
+/// how it stopped.
+pub fn run_with_limits(source_code: &str, limits: Limits) -> Run {
+  run_with_args(source_code, Vec::new(), limits)
+}
+
+/// Like `run_with_limits`, run as `options` say.
+pub fn run_with_options(source_code: &str, limits: Limits, options: RunOptions) -> Run {
+  capture(|out| {
+    rlox::run_source_code_with_options(
+      "main.lox",
+      source_code,
+      Vec::new(),
+      limits,
+      options,
+      Some(out),
+      None,
+    )
+  })
+}
+
+/// Like `run_with_limits`, with arguments for the script.
+pub fn run_with_args(source_code: &str, script_args: Vec<String>, limits: Limits) -> Run {
+  capture(|out| {
+    rlox::run_source_code_with_limits("main.lox", source_code, script_args, limits, Some(out))
+  })
+}
+
+/// Runs a program that prints to the given writer, and returns what it
+/// printed and how it stopped.
+pub fn capture(run: impl FnOnce(Box<dyn Write + '_>) -> Result<Completion, Vec<LoxError>>) -> Run {
+  let mut out = Vec::new();
+  let result = run(Box::new(&mut out));
+  (String::from_utf8(out).unwrap(), result)
+}
+
+/// Renders the errors of a run as they are shown to humans.
+pub fn render(
+  source_code: &str,
+  result: Result<Completion, Vec<LoxError>>,
+) -> Result<Completion, String> {
+  result.map_err(|errs| errs.iter().map(|err| err.render(source_code)).collect())
 }
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{capture, render};
+use rlox::{Backend, CompiledProgram, Completion, Limits, LoadError, LoxError, RunOptions};
+
+/// Runs a program compiled from the source code, returning what it
+/// printed and how it stopped, with errors rendered as they are for humans.
+fn run_compiled(source_code: &str, backend: Backend) -> (String, Result<Completion, String>) {
+  let bytes = rlox::compile_source_code("main.lox", source_code).unwrap();
+  let program = CompiledProgram::decode(&bytes).unwrap();
+  let options = RunOptions::default().with_backend(backend);
+  let (out, result) = capture(|out| {
+    rlox::run_compiled_with_options(
+      program,
+      Vec::new(),
+      Limits::default(),
+      options,
+      Some(out),
+      None,
+    )
+  });
+  (out, render(source_code, result))
+}
+
+#[test]
+fn compiled_programs_run_like_their_source() {
+  let source_code = "
+    fun counter() {
+      var n = 0;
+      fun next() { n = n + 1; return n; }
+      return next;
+    }
+    var next = counter();
+    for (var i = 0; i < 3; i = i + 1) print next();
+    print \"a\" + \"b\";
+    print env(\"RLOX_COMPILED_TEST_UNSET\") == nil;
+    print next() + nil;
+  ";
+  let mut out_buf = Vec::new();
+  let expected = rlox::run_source_code_with_args(
+    "main.lox",
+    source_code,
+    Vec::new(),
+    Some(Box::new(&mut out_buf)),
+  )
+  .map_err(|errs| errs[0].render(source_code));
+  let expected = (String::from_utf8(out_buf).unwrap(), expected);
+  assert_eq!(expected.0, "1\n2\n3\nab\ntrue\n");
+  assert!(
+    expected
+      .1
+      .as_ref()
+      .unwrap_err()
+      .contains("--> main.lox:11:18")
+  );
+
+  assert_eq!(run_compiled(source_code, Backend::TreeWalker), expected);
+  assert_eq!(run_compiled(source_code, Backend::Vm), expected);
+}
+
+#[test]
+fn static_errors_are_reported_when_compiling() {
+  let errs = rlox::compile_source_code("main.lox", "print x;").unwrap_err();
+  assert_eq!(errs[0].code(), "E0303");
+}
+
+#[test]
+fn corrupt_programs_are_rejected() {
+  let bytes = rlox::compile_source_code("main.lox", "print \"hello\";").unwrap();
+  let decode = |bytes: &[u8]| CompiledProgram::decode(bytes).err();
+
+  assert_eq!(decode(b"print \"hello\";"), Some(LoadError::NotCompiled));
+  assert_eq!(
+    decode(&bytes[..bytes.len() - 1]).unwrap().to_string(),
+    "compiled program is corrupt: the checksum does not match"
+  );
+  let mut other_version = bytes.clone();
+  other_version[4..6].copy_from_slice(&7u16.to_le_bytes());
+  let err = LoxError::LoadError(decode(&other_version).unwrap());
+  assert_eq!(err.code(), "E0502");
+  assert!(err.is_static());
+  assert!(err.render("").contains("unsupported version 7"));
+}
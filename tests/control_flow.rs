@@ -58,3 +58,20 @@ fn for_loops_works() {
   let out = run_and_capture_output(source_code);
   assert_eq!(out, "0\n1");
 }
+
+#[test]
+fn return_inside_while_exits_the_function() {
+  let source_code = r#"
+    fun f() {
+      var i = 0;
+      while (i < 3) {
+        i = i + 1;
+        if (i == 2) return i;
+      }
+      return 0;
+    }
+    print f();
+  "#;
+  let out = run_and_capture_output(source_code);
+  assert_eq!(out, "2");
+}
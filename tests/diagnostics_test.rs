@@ -0,0 +1,97 @@
+use std::error::Error;
+
+fn run_errors(name: &str, source_code: &str) -> Vec<rlox::LoxError> {
+  let out = Some(Box::new(std::io::sink()) as Box<dyn std::io::Write>);
+  rlox::run_source_code_with_args(name, source_code, Vec::new(), out).unwrap_err()
+}
+
+fn render_errors(source_code: &str) -> Vec<String> {
+  let errs = run_errors("main.lox", source_code);
+  errs.iter().map(|err| err.render(source_code)).collect()
+}
+
+#[test]
+fn runtime_errors_point_at_operator() {
+  let rendered = render_errors("var a = 1;\nprint a - \"b\";\n");
+  assert_eq!(
+    rendered,
+    vec![
+      "error[E0401]: operator '-' is not defined between a number and a string\n \
+       --> main.lox:2:9\n  \
+       |\n\
+       2 | print a - \"b\";\n  \
+       |         ^ cannot be applied to these operands\n"
+    ]
+  );
+}
+
+#[test]
+fn resolve_errors_underline_identifier() {
+  let rendered = render_errors("print missing;");
+  assert!(rendered[0].starts_with("error[E0303]: variable 'missing' is not declared\n"));
+  assert!(rendered[0].contains("1 | print missing;\n  |       ^^^^^^^ not found in this scope\n"));
+}
+
+#[test]
+fn lexer_errors_underline_number() {
+  let rendered = render_errors("var a = 1.2.3;");
+  assert!(rendered[0].contains("  |         ^^^^^ not a number\n"));
+}
+
+#[test]
+fn every_error_has_a_code() {
+  let sources = [
+    ("@", "E0101"),
+    ("\"open", "E0102"),
+    ("print ;", "E0201"),
+    ("print 1", "E0202"),
+    ("var a; print a;", "E0301"),
+    ("var a = 1;", "E0302"),
+    ("print a;", "E0303"),
+    ("var a = 1; var a = 2; print a;", "E0304"),
+    ("print 1 + nil;", "E0401"),
+    ("print -nil;", "E0402"),
+    ("fun f(a) { return a; } f();", "E0403"),
+    ("var a = 1; a();", "E0404"),
+    ("exit(nil);", "E0405"),
+    ("fun f() { return 1 + f(); } f();", "E0407"),
+  ];
+  for (source_code, code) in sources {
+    let errs = rlox::run_source_code(source_code, Some(Box::new(std::io::sink()))).unwrap_err();
+    assert_eq!(errs[0].code(), code, "{source_code}");
+  }
+}
+
+#[test]
+fn errors_report_the_real_path() {
+  let sources = ["@", "print 1", "print a;", "print -nil;"];
+  for source_code in sources {
+    let errs = run_errors("jobs/nightly.lox", source_code);
+    let err = errs[0].source().expect("expected a source error");
+    assert!(err.to_string().contains("jobs/nightly.lox:1:"), "{err}");
+    assert!(
+      errs[0]
+        .render(source_code)
+        .contains("--> jobs/nightly.lox:1:")
+    );
+  }
+}
+
+#[test]
+fn inline_code_is_named_eval() {
+  let errs = rlox::run_source_code("print -nil;", None).unwrap_err();
+  assert!(errs[0].render("print -nil;").contains("--> <eval>:1:7"));
+}
+
+#[test]
+fn runtime_errors_render_the_backtrace_innermost_first() {
+  let rendered =
+    render_errors("fun f(x) {\n  return -x;\n}\nfun g() { print f(nil); }\nprint g();");
+  assert!(rendered[0].ends_with(
+    "2 |   return -x;\n  \
+     |          ^ expected a number\n  \
+     |\n  \
+     = note: in f(), called at main.lox:4:22\n  \
+     = note: in g(), called at main.lox:5:9\n"
+  ));
+}
@@ -0,0 +1,135 @@
+use std::error::Error;
+
+use rlox::{
+  LexerErrorKind, LoxError, ParseErrorKind, ResolveError, RuntimeError, RuntimeErrorKind, TokenKind,
+};
+
+fn first_error(source_code: &str) -> LoxError {
+  let out = Some(Box::new(std::io::sink()) as Box<dyn std::io::Write>);
+  let mut errs = rlox::run_source_code_with_args("main.lox", source_code, Vec::new(), out)
+    .expect_err("expected that the program fails");
+  errs.remove(0)
+}
+
+#[test]
+fn lexer_errors_expose_kind_and_location() {
+  let LoxError::LexerError(err) = first_error("print 1;\n  @") else {
+    panic!("expected a lexer error");
+  };
+  assert_eq!(err.kind(), &LexerErrorKind::UnexpectedChar('@'));
+  assert_eq!((err.line(), err.col()), (2, 3));
+  assert_eq!(err.source_id().name(), "main.lox");
+  assert!(err.source().is_none());
+  assert_eq!(err.clone().span(), err.span());
+}
+
+#[test]
+fn parse_errors_expose_kind_and_token() {
+  let LoxError::ParseError(err) = first_error("print 1") else {
+    panic!("expected a parse error");
+  };
+  assert_eq!(err.kind(), &ParseErrorKind::Expected(";"));
+  assert_eq!(err.token().kind(), &TokenKind::Eof);
+  assert_eq!(err.span().line(), 1);
+}
+
+#[test]
+fn resolve_errors_expose_token() {
+  let LoxError::ResolveError(err) = first_error("print missing;") else {
+    panic!("expected a resolve error");
+  };
+  assert!(matches!(err, ResolveError::UndeclaredVariable(_)));
+  assert_eq!(err.token().extract_identifier().as_str(), "missing");
+  assert_eq!(err.span().col(), 7);
+}
+
+#[test]
+fn runtime_errors_expose_token() {
+  let LoxError::RuntimeError(err) = first_error("print 1 - \"a\";") else {
+    panic!("expected a runtime error");
+  };
+  assert!(matches!(
+    err.kind(),
+    RuntimeErrorKind::UndefinedOpBetween(..)
+  ));
+  assert_eq!(err.token().map(|tok| tok.kind()), Some(&TokenKind::Minus));
+  assert!(
+    err
+      .to_string()
+      .contains("between Number(1.0) and Str(\"a\")")
+  );
+}
+
+#[test]
+fn chains_to_the_wrapped_error() {
+  let err = first_error("print missing;");
+  assert_eq!(err.to_string(), "could not resolve the program");
+  let source = err.source().expect("expected a source error");
+  assert!(source.is::<ResolveError>());
+  assert_eq!(
+    source.to_string(),
+    "main.lox:1:7 token identifier 'missing' undeclared reference"
+  );
+
+  let err: Box<dyn Error> = Box::new(first_error("print -nil;"));
+  assert!(err.source().unwrap().is::<RuntimeError>());
+}
+
+#[test]
+fn chains_io_errors() {
+  let errs = rlox::run_file(String::from("does/not/exist.lox"), None).unwrap_err();
+  let source = errs[0].source().expect("expected a source error");
+  assert!(source.is::<std::io::Error>());
+}
+
+#[test]
+fn runtime_errors_carry_a_backtrace() {
+  let source_code = "\
+fun inner(x) {
+  return x - \"a\";
+}
+fun outer() {
+  print inner(1);
+}
+print outer();";
+  let LoxError::RuntimeError(err) = first_error(source_code) else {
+    panic!("expected a runtime error");
+  };
+  let frames: Vec<(&str, usize)> = err
+    .backtrace()
+    .iter()
+    .map(|frame| (frame.function(), frame.call_site().line()))
+    .collect();
+  assert_eq!(frames, vec![("inner", 5), ("outer", 7)]);
+}
+
+#[test]
+fn top_level_errors_have_an_empty_backtrace() {
+  let LoxError::RuntimeError(err) = first_error("print -nil;") else {
+    panic!("expected a runtime error");
+  };
+  assert!(err.backtrace().is_empty());
+}
+
+#[test]
+fn native_calls_are_part_of_the_backtrace() {
+  let LoxError::RuntimeError(err) = first_error("fun f() { env(1); }\nf();") else {
+    panic!("expected a runtime error");
+  };
+  assert!(matches!(
+    err.kind(),
+    RuntimeErrorKind::ConversionFailed { .. }
+  ));
+  assert_eq!(
+    err.span().map(|span| (span.line(), span.col())),
+    Some((1, 16))
+  );
+  assert_eq!(
+    err.backtrace()[0].to_string(),
+    "in env(), called at main.lox:1:16"
+  );
+  assert_eq!(
+    err.backtrace()[1].to_string(),
+    "in f(), called at main.lox:2:3"
+  );
+}
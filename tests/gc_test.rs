@@ -0,0 +1,94 @@
+use rlox::{Session, Value};
+
+/// Every call leaves a cycle between `inner` and the environment it is
+/// declared in, which holds a 4-byte string built at runtime.
+const MAKE: &str = "
+fun make() {
+  var s = \"ab\" + \"cd\";
+  fun inner() { return s + inner(); }
+  return inner;
+}
+";
+
+fn session() -> Session<'static> {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  session.run(MAKE).unwrap();
+  session
+}
+
+#[test]
+fn frees_cycles_between_closures_and_environments() {
+  let mut session = session();
+  session
+    .run("for (var i = 0; i < 100; i = i + 1) { make(); }")
+    .unwrap();
+  assert_eq!(session.memory_used(), 400);
+
+  // The environments of the calls and of their bodies, and `inner`.
+  assert_eq!(session.collect_garbage(), 300);
+  assert_eq!(session.memory_used(), 0);
+  assert_eq!(session.collect_garbage(), 0);
+
+  let stats = session.gc_stats();
+  assert_eq!(stats.collections(), 2);
+  assert_eq!(stats.objects_freed(), 300);
+  assert!(stats.bytes_allocated() > 0);
+}
+
+#[test]
+fn keeps_closures_that_are_still_reachable() {
+  let mut session = session();
+  session
+    .run(
+      "fun counter() { var count = 0; fun next() { count = count + 1; return count; } return next; }
+       var next = counter(); next();",
+    )
+    .unwrap();
+  session.collect_garbage();
+  assert!(matches!(
+    session.run("next();"),
+    Ok(Some(Value::Number(2.0)))
+  ));
+}
+
+#[test]
+fn keeps_closures_held_by_the_host() {
+  let mut session = session();
+  let inner = session.run("make();").unwrap().unwrap();
+  assert_eq!(session.collect_garbage(), 0);
+  assert_eq!(session.memory_used(), 4);
+
+  drop(inner);
+  assert_eq!(session.memory_used(), 4);
+  assert_eq!(session.collect_garbage(), 3);
+  assert_eq!(session.memory_used(), 0);
+}
+
+#[test]
+fn gc_native_returns_the_objects_freed() {
+  let mut session = session();
+  session.run("make(); make();").unwrap();
+  assert!(matches!(session.run("gc();"), Ok(Some(Value::Number(6.0)))));
+  assert!(matches!(session.run("gc();"), Ok(Some(Value::Number(0.0)))));
+}
+
+#[test]
+fn collects_as_functions_are_declared() {
+  let mut session = session();
+  session
+    .run("for (var i = 0; i < 5000; i = i + 1) { make(); }")
+    .unwrap();
+  let stats = session.gc_stats();
+  assert!(stats.collections() >= 1);
+  assert!(session.memory_used() < 5000 * 4);
+}
+
+#[test]
+fn reset_frees_the_cycles_of_top_level_functions() {
+  let mut session = session();
+  session.run("var keep = make();").unwrap();
+  assert_eq!(session.memory_used(), 4);
+  session.reset();
+  assert_eq!(session.memory_used(), 0);
+}
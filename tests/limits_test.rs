@@ -0,0 +1,182 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+mod common;
+
+use common::{run_with_args, run_with_limits, run_with_options};
+use rlox::{Limits, LoxError, RunOptions, RuntimeErrorKind, Session};
+
+const COUNT_DOWN: &str = "fun f(n) { if (n > 0) { return 1 + f(n - 1); } return 0; }";
+
+fn runtime_error_kind(errs: &[LoxError]) -> &RuntimeErrorKind {
+  match &errs[0] {
+    LoxError::RuntimeError(err) => err.kind(),
+    err => panic!("expected a runtime error, found {err:?}"),
+  }
+}
+
+fn is_stack_overflow(errs: &[LoxError]) -> bool {
+  matches!(runtime_error_kind(errs), RuntimeErrorKind::StackOverflow(_))
+}
+
+#[test]
+fn unbounded_recursion_fails_instead_of_crashing() {
+  let errs = run_with_limits("fun f(n) { return 1 + f(n + 1); } f(0);", Limits::default())
+    .1
+    .unwrap_err();
+  assert!(is_stack_overflow(&errs));
+  assert_eq!(errs[0].code(), "E0407");
+}
+
+#[test]
+fn call_depth_is_configurable() {
+  let limits = Limits::default().with_max_call_depth(50);
+  let source_code = format!("{COUNT_DOWN} f(49);");
+  assert!(run_with_limits(&source_code, limits.clone()).1.is_ok());
+  let source_code = format!("{COUNT_DOWN} f(50);");
+  let errs = run_with_limits(&source_code, limits).1.unwrap_err();
+  assert!(is_stack_overflow(&errs));
+  let LoxError::RuntimeError(err) = &errs[0] else {
+    unreachable!()
+  };
+  assert_eq!(err.backtrace().len(), 50);
+  assert_eq!(err.token().unwrap().col(), 43);
+}
+
+#[test]
+fn native_stack_is_limited() {
+  let limits = Limits::default()
+    .with_max_call_depth(usize::MAX)
+    .with_max_stack_bytes(64 * 1024);
+  let errs = run_with_limits("fun f() { return 1 + f(); } f();", limits)
+    .1
+    .unwrap_err();
+  assert!(is_stack_overflow(&errs));
+}
+
+#[test]
+fn session_runs_again_after_a_stack_overflow() {
+  let mut session = Session::new();
+  session.set_limits(Limits::default().with_max_call_depth(20));
+  session.run(COUNT_DOWN).unwrap();
+  assert!(is_stack_overflow(&session.run("f(100);").unwrap_err()));
+  assert_eq!(session.run("f(19);").unwrap().unwrap().to_string(), "19");
+}
+
+#[test]
+fn infinite_loops_run_out_of_fuel() {
+  let errs = run_with_limits("while (true) {}", Limits::default().with_fuel(10_000))
+    .1
+    .unwrap_err();
+  assert!(matches!(
+    runtime_error_kind(&errs),
+    RuntimeErrorKind::BudgetExhausted
+  ));
+  assert_eq!(errs[0].code(), "E0408");
+}
+
+#[test]
+fn fuel_is_charged_per_statement_and_expression() {
+  // The print statement, the binary expression and both literals, which
+  // are only evaluated as written when the program is not optimised.
+  let limits = Limits::default().with_fuel(4);
+  let options = RunOptions::default().with_opt_level(0);
+  let run = |source_code| run_with_options(source_code, limits.clone(), options).1;
+  assert!(run("print 1 + 2;").is_ok());
+  assert!(run("print 1 + 2; 3;").is_err());
+}
+
+#[test]
+fn session_refuels_every_run() {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  session.set_limits(Limits::default().with_fuel(100));
+  session.run("var i = 0;").unwrap();
+  for _ in 0..10 {
+    session.run("i = i + 1;").unwrap();
+  }
+  assert!(session.run("while (true) { i = i + 1; }").is_err());
+  // Each iteration uses 7 units of fuel, so 14 of them fit into 100.
+  assert_eq!(session.run("i;").unwrap().unwrap().to_string(), "24");
+}
+
+#[test]
+fn interrupt_cancels_a_running_program() {
+  let mut session = Session::new();
+  let interrupt = session.interrupt_handle();
+  let canceller = thread::spawn(move || {
+    thread::sleep(Duration::from_millis(50));
+    interrupt.store(true, Ordering::Relaxed);
+  });
+  let errs = session.run("while (true) {}").unwrap_err();
+  canceller.join().unwrap();
+  assert!(matches!(
+    runtime_error_kind(&errs),
+    RuntimeErrorKind::Interrupted
+  ));
+  assert!(session.run("1 + 1;").is_ok());
+}
+
+#[test]
+fn interrupt_handle_can_be_given_through_limits() {
+  let interrupt = Arc::new(AtomicBool::new(true));
+  let limits = Limits::default().with_interrupt(Arc::clone(&interrupt));
+  let errs = run_with_limits("print 1;", limits).1.unwrap_err();
+  assert!(matches!(
+    runtime_error_kind(&errs),
+    RuntimeErrorKind::Interrupted
+  ));
+  assert!(!interrupt.load(Ordering::Relaxed));
+}
+
+#[test]
+fn growing_strings_hit_the_memory_limit() {
+  let source_code = "var s = \"x\"; while (true) { s = s + s; }";
+  let limits = Limits::default().with_max_memory(1024 * 1024);
+  let errs = run_with_limits(source_code, limits).1.unwrap_err();
+  assert!(matches!(
+    runtime_error_kind(&errs),
+    RuntimeErrorKind::MemoryLimit(_)
+  ));
+  assert_eq!(errs[0].code(), "E0410");
+}
+
+#[test]
+fn values_from_natives_count_towards_the_limit() {
+  let limits = Limits::default().with_max_memory(1024);
+  let (_, result) = run_with_args("print args();", vec!["x".repeat(2048)], limits);
+  let errs = result.unwrap_err();
+  assert!(matches!(
+    runtime_error_kind(&errs),
+    RuntimeErrorKind::MemoryLimit(_)
+  ));
+}
+
+#[test]
+fn memory_usage_follows_live_strings() {
+  let mut session = Session::new();
+  session.run("var s = \"\";").unwrap();
+  assert_eq!(session.memory_used(), 0);
+  session
+    .run("for (var i = 0; i < 100; i = i + 1) { s = s + \"ab\"; }")
+    .unwrap();
+  assert_eq!(session.memory_used(), 200);
+  session.run("s = nil;").unwrap();
+  assert_eq!(session.memory_used(), 0);
+}
+
+#[test]
+fn freed_strings_do_not_count_towards_the_limit() {
+  let mut session = Session::new();
+  // Building the last string needs the old one, a copy of it and the result.
+  session.set_limits(Limits::default().with_max_memory(38 * 2 + 40));
+  session.run("var s = \"\";").unwrap();
+  for _ in 0..10 {
+    session
+      .run("s = \"\"; for (var i = 0; i < 20; i = i + 1) { s = s + \"ab\"; }")
+      .unwrap();
+  }
+  assert_eq!(session.memory_used(), 40);
+}
@@ -0,0 +1,414 @@
+use super::program::{Expr, Program, Stmt};
+
+/// A small, seeded generator of pseudo-random numbers (SplitMix64).
+pub struct Rng(u64);
+
+impl Rng {
+  pub fn new(seed: u64) -> Rng {
+    Rng(seed)
+  }
+
+  pub fn next(&mut self) -> u64 {
+    self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = self.0;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+  }
+
+  /// A number in `0..n`.
+  pub fn below(&mut self, n: usize) -> usize {
+    (self.next() % n as u64) as usize
+  }
+
+  pub fn chance(&mut self, percent: u64) -> bool {
+    self.next() % 100 < percent
+  }
+
+  pub fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+    &items[self.below(items.len())]
+  }
+}
+
+/// The type of the values a variable holds or a function returns, which
+/// keeps most programs from stopping at their first runtime error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+  Number,
+  Str,
+  Bool,
+  Nil,
+}
+
+const TYPES: [Type; 4] = [Type::Number, Type::Str, Type::Bool, Type::Nil];
+
+enum Kind {
+  Var(Type),
+  /// The counter of a loop, which nothing else assigns.
+  Counter,
+  Fun {
+    params: Vec<Type>,
+    returns: Type,
+  },
+}
+
+struct Binding {
+  name: String,
+  kind: Kind,
+  read: bool,
+}
+
+const MAX_DEPTH: usize = 3;
+const MAX_EXPR_DEPTH: usize = 3;
+const MAX_STMTS: usize = 25;
+
+/// Generates well-formed programs that terminate.
+///
+/// Every name is unique and every variable and function is read, so
+/// the programs pass the resolver. Loops count up to a small bound, and
+/// functions only call those declared before them, so nothing recurses.
+pub struct Generator {
+  rng: Rng,
+  names: usize,
+  stmts: usize,
+  scopes: Vec<Vec<Binding>>,
+  /// The return types of the functions being generated, innermost last.
+  returns: Vec<Type>,
+}
+
+impl Generator {
+  pub fn new(seed: u64) -> Generator {
+    Generator {
+      rng: Rng::new(seed),
+      names: 0,
+      stmts: 0,
+      scopes: Vec::new(),
+      returns: Vec::new(),
+    }
+  }
+
+  pub fn program(mut self) -> Program {
+    self.scopes.push(Vec::new());
+    let mut stmts = Vec::new();
+    for _ in 0..3 + self.rng.below(6) {
+      stmts.push(self.stmt(0));
+    }
+    self.end_scope(&mut stmts);
+    Program { stmts }
+  }
+
+  fn name(&mut self, prefix: &str) -> String {
+    self.names += 1;
+    format!("{prefix}{}", self.names)
+  }
+
+  fn declare(&mut self, name: &str, kind: Kind) {
+    let binding = Binding {
+      name: name.to_string(),
+      kind,
+      read: false,
+    };
+    self.scopes.last_mut().unwrap().push(binding);
+  }
+
+  /// Closes the innermost scope, printing the names it never read.
+  fn end_scope(&mut self, stmts: &mut Vec<Stmt>) {
+    for binding in self.scopes.pop().unwrap() {
+      if !binding.read {
+        stmts.push(Stmt::Print(Expr::Variable(binding.name)));
+      }
+    }
+  }
+
+  fn block(&mut self, depth: usize) -> Vec<Stmt> {
+    self.scopes.push(Vec::new());
+    let mut stmts = Vec::new();
+    for _ in 0..1 + self.rng.below(3) {
+      stmts.push(self.stmt(depth + 1));
+    }
+    self.end_scope(&mut stmts);
+    stmts
+  }
+
+  fn stmt(&mut self, depth: usize) -> Stmt {
+    self.stmts += 1;
+    let nested = depth < MAX_DEPTH && self.stmts < MAX_STMTS;
+    loop {
+      match self.rng.below(10) {
+        0 | 1 => {
+          let ty = *self.rng.pick(&TYPES);
+          return Stmt::Print(self.expr(ty, 0));
+        }
+        2 => return Stmt::Expr(self.effect()),
+        3 | 4 => {
+          let ty = *self.rng.pick(&TYPES);
+          let expr = self.expr(ty, 0);
+          let name = self.name("v");
+          self.declare(&name, Kind::Var(ty));
+          return Stmt::Var(name, expr);
+        }
+        5 if nested => return Stmt::Block(self.block(depth)),
+        6 if nested => {
+          let condition = self.expr(Type::Bool, 0);
+          let then_stmt = Box::new(Stmt::Block(self.block(depth)));
+          let else_stmt = match self.rng.chance(50) {
+            true => Some(Box::new(Stmt::Block(self.block(depth)))),
+            false => None,
+          };
+          return Stmt::If(condition, then_stmt, else_stmt);
+        }
+        7 if nested => return self.loop_stmt(depth),
+        8 if nested => return self.function(depth),
+        9 if !self.returns.is_empty() => {
+          let ty = *self.returns.last().unwrap();
+          return Stmt::Return(self.expr(ty, 0));
+        }
+        _ => {}
+      }
+    }
+  }
+
+  /// A `for` loop, or the `while` loop it stands for, counting up to a
+  /// small bound.
+  fn loop_stmt(&mut self, depth: usize) -> Stmt {
+    let counter = self.name("i");
+    let bound = Expr::Literal((1 + self.rng.below(3)).to_string());
+    let init = Stmt::Var(counter.clone(), Expr::Literal("0".to_string()));
+    let condition = Expr::Binary(
+      Box::new(Expr::Variable(counter.clone())),
+      "<",
+      Box::new(bound),
+    );
+    let increment = Expr::Assign(
+      counter.clone(),
+      Box::new(Expr::Binary(
+        Box::new(Expr::Variable(counter.clone())),
+        "+",
+        Box::new(Expr::Literal("1".to_string())),
+      )),
+    );
+
+    self.scopes.push(Vec::new());
+    self.declare(&counter, Kind::Counter);
+    self.scopes.last_mut().unwrap()[0].read = true;
+    let mut body = self.block(depth);
+    self.scopes.pop();
+
+    if self.rng.chance(50) {
+      return Stmt::For {
+        init: Box::new(init),
+        condition,
+        increment,
+        body: Box::new(Stmt::Block(body)),
+      };
+    }
+    body.push(Stmt::Expr(increment));
+    Stmt::Block(vec![
+      init,
+      Stmt::While(condition, Box::new(Stmt::Block(body))),
+    ])
+  }
+
+  fn function(&mut self, depth: usize) -> Stmt {
+    let name = self.name("f");
+    let returns = *self.rng.pick(&TYPES);
+    let params: Vec<Type> = (0..self.rng.below(4))
+      .map(|_| *self.rng.pick(&TYPES))
+      .collect();
+
+    self.scopes.push(Vec::new());
+    let param_names: Vec<String> = params
+      .iter()
+      .map(|ty| {
+        let param = self.name("p");
+        self.declare(&param, Kind::Var(*ty));
+        param
+      })
+      .collect();
+    self.returns.push(returns);
+    let mut body = Vec::new();
+    for _ in 0..1 + self.rng.below(3) {
+      body.push(self.stmt(depth + 1));
+    }
+    self.end_scope(&mut body);
+    body.push(Stmt::Return(self.expr(returns, 0)));
+    self.returns.pop();
+
+    // Declared after its body, so that the function never calls itself.
+    self.declare(&name, Kind::Fun { params, returns });
+    Stmt::Fun(name, param_names, body)
+  }
+
+  /// An expression run for its effect: an assignment or a call.
+  fn effect(&mut self) -> Expr {
+    let ty = *self.rng.pick(&TYPES);
+    if self.rng.chance(50)
+      && let Some(expr) = self.assign(ty, 0)
+    {
+      return expr;
+    }
+    self.call(ty, 0).unwrap_or_else(|| self.expr(ty, 0))
+  }
+
+  fn expr(&mut self, ty: Type, depth: usize) -> Expr {
+    if depth >= MAX_EXPR_DEPTH || self.rng.chance(30) {
+      return self.leaf(ty);
+    }
+    // Now and then, an operator on the wrong types.
+    if self.rng.chance(2) {
+      let left = self.operand(Type::Str, depth);
+      let right = self.operand(Type::Number, depth);
+      let op = *self.rng.pick(&["-", "<"]);
+      return Expr::Binary(Box::new(left), op, Box::new(right));
+    }
+    let expr = match self.rng.below(5) {
+      0 => self.call(ty, depth),
+      1 => self.assign(ty, depth),
+      2 => Some(Expr::Grouping(Box::new(self.expr(ty, depth + 1)))),
+      _ => self.operation(ty, depth),
+    };
+    expr.unwrap_or_else(|| self.leaf(ty))
+  }
+
+  /// An operand of an operator, grouped unless it is a single token or
+  /// a call, so that the printed program parses as generated.
+  fn operand(&mut self, ty: Type, depth: usize) -> Expr {
+    match self.expr(ty, depth + 1) {
+      expr @ (Expr::Literal(_) | Expr::Variable(_) | Expr::Call(..) | Expr::Grouping(_)) => expr,
+      expr => Expr::Grouping(Box::new(expr)),
+    }
+  }
+
+  fn operation(&mut self, ty: Type, depth: usize) -> Option<Expr> {
+    let expr = match ty {
+      Type::Number => match self.rng.chance(20) {
+        true => Expr::Unary("-", Box::new(self.operand(Type::Number, depth))),
+        false => {
+          let left = self.operand(Type::Number, depth);
+          let op = *self.rng.pick(&["+", "-", "*", "/"]);
+          Expr::Binary(
+            Box::new(left),
+            op,
+            Box::new(self.operand(Type::Number, depth)),
+          )
+        }
+      },
+      Type::Str => {
+        let left = self.operand(Type::Str, depth);
+        Expr::Binary(
+          Box::new(left),
+          "+",
+          Box::new(self.operand(Type::Str, depth)),
+        )
+      }
+      Type::Bool => match self.rng.below(4) {
+        0 => {
+          let operand_ty = *self.rng.pick(&TYPES);
+          Expr::Unary("!", Box::new(self.operand(operand_ty, depth)))
+        }
+        1 => {
+          let left = self.operand(Type::Number, depth);
+          let op = *self.rng.pick(&["<", "<=", ">", ">="]);
+          Expr::Binary(
+            Box::new(left),
+            op,
+            Box::new(self.operand(Type::Number, depth)),
+          )
+        }
+        2 => {
+          let left_ty = *self.rng.pick(&TYPES);
+          let right_ty = match self.rng.chance(70) {
+            true => left_ty,
+            false => *self.rng.pick(&TYPES),
+          };
+          let left = self.operand(left_ty, depth);
+          let op = *self.rng.pick(&["==", "!="]);
+          Expr::Binary(Box::new(left), op, Box::new(self.operand(right_ty, depth)))
+        }
+        _ => {
+          let left_ty = *self.rng.pick(&TYPES);
+          let left = self.operand(left_ty, depth);
+          let op = *self.rng.pick(&["and", "or"]);
+          let right_ty = *self.rng.pick(&TYPES);
+          Expr::Logical(Box::new(left), op, Box::new(self.operand(right_ty, depth)))
+        }
+      },
+      Type::Nil => return None,
+    };
+    Some(expr)
+  }
+
+  fn leaf(&mut self, ty: Type) -> Expr {
+    if self.rng.chance(50) {
+      let candidates: Vec<(usize, usize)> = self.visible(|kind| match kind {
+        Kind::Var(var_ty) => *var_ty == ty,
+        Kind::Counter => ty == Type::Number,
+        Kind::Fun { .. } => false,
+      });
+      if !candidates.is_empty() {
+        let (scope, index) = *self.rng.pick(&candidates);
+        let binding = &mut self.scopes[scope][index];
+        binding.read = true;
+        return Expr::Variable(binding.name.clone());
+      }
+    }
+    let literal = match ty {
+      Type::Number => match self.rng.chance(20) {
+        true => format!("{}.5", self.rng.below(4)),
+        false => self.rng.below(10).to_string(),
+      },
+      Type::Str => format!("\"{}\"", self.rng.pick(&["", "a", "b", "ab"])),
+      Type::Bool => self.rng.pick(&["true", "false"]).to_string(),
+      Type::Nil => "nil".to_string(),
+    };
+    Expr::Literal(literal)
+  }
+
+  fn assign(&mut self, ty: Type, depth: usize) -> Option<Expr> {
+    let candidates = self.visible(|kind| matches!(kind, Kind::Var(var_ty) if *var_ty == ty));
+    if candidates.is_empty() {
+      return None;
+    }
+    let (scope, index) = *self.rng.pick(&candidates);
+    let name = self.scopes[scope][index].name.clone();
+    Some(Expr::Assign(name, Box::new(self.expr(ty, depth + 1))))
+  }
+
+  fn call(&mut self, ty: Type, depth: usize) -> Option<Expr> {
+    let candidates =
+      self.visible(|kind| matches!(kind, Kind::Fun { returns, .. } if *returns == ty));
+    if candidates.is_empty() {
+      return None;
+    }
+    let (scope, index) = *self.rng.pick(&candidates);
+    let binding = &mut self.scopes[scope][index];
+    binding.read = true;
+    let name = binding.name.clone();
+    let Kind::Fun { params, .. } = &binding.kind else {
+      unreachable!();
+    };
+    let mut params = params.clone();
+    // Now and then, the wrong number of arguments.
+    if self.rng.chance(2) {
+      params.push(Type::Nil);
+    }
+    let args = params
+      .into_iter()
+      .map(|ty| self.expr(ty, depth + 1))
+      .collect();
+    Some(Expr::Call(name, args))
+  }
+
+  /// The positions of the bindings in scope whose kind matches.
+  fn visible(&self, matches: impl Fn(&Kind) -> bool) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    for (scope, bindings) in self.scopes.iter().enumerate() {
+      for (index, binding) in bindings.iter().enumerate() {
+        if matches(&binding.kind) {
+          found.push((scope, index));
+        }
+      }
+    }
+    found
+  }
+}
@@ -0,0 +1,150 @@
+//! Runs generated programs and checks invariants that must hold for
+//! any of them, shrinking those that break one.
+
+pub mod generator;
+pub mod program;
+pub mod rewrite;
+pub mod shrink;
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+use rlox::{Backend, Completion, Limits, RunOptions};
+
+use crate::common::run_with_options;
+
+pub use generator::Generator;
+pub use program::Program;
+pub use rewrite::REWRITES;
+pub use shrink::shrink;
+
+/// Enough fuel for any generated program, so that one looping forever
+/// fails instead of hanging the test.
+const FUEL: u64 = 1_000_000;
+
+/// What running a program did: what it printed, and how it stopped or
+/// the codes of its errors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+  pub output: String,
+  pub result: Result<Completion, Vec<&'static str>>,
+}
+
+impl Outcome {
+  fn ran_out_of_fuel(&self) -> bool {
+    self.result == Err(vec!["E0408"])
+  }
+}
+
+/// Runs the program with fuel, turning a panic into its message.
+pub fn run(source_code: &str, options: RunOptions) -> Result<Outcome, String> {
+  let result = panic::catch_unwind(AssertUnwindSafe(|| {
+    run_with_options(source_code, Limits::default().with_fuel(FUEL), options)
+  }));
+  let (output, result) = result.map_err(|payload| {
+    let message = payload
+      .downcast_ref::<String>()
+      .map(String::as_str)
+      .or_else(|| payload.downcast_ref::<&str>().copied())
+      .unwrap_or("a panic");
+    format!("panicked: {message}")
+  })?;
+  Ok(Outcome {
+    output,
+    result: result.map_err(|errs| errs.iter().map(|err| err.code()).collect()),
+  })
+}
+
+/// An invariant a program broke, and how.
+#[derive(Debug)]
+pub struct Failure {
+  pub invariant: &'static str,
+  pub details: String,
+}
+
+impl Failure {
+  fn new(invariant: &'static str, details: impl Into<String>) -> Failure {
+    Failure {
+      invariant,
+      details: details.into(),
+    }
+  }
+}
+
+impl fmt::Display for Failure {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}: {}", self.invariant, self.details)
+  }
+}
+
+fn run_checked(source_code: &str, options: RunOptions) -> Result<Outcome, Failure> {
+  run(source_code, options).map_err(|message| Failure::new("it panics", message))
+}
+
+/// Checks that the program is well-formed, terminates and runs without
+/// panicking, and that it prints the same every time, on both backends,
+/// optimised and after every rewrite.
+///
+/// # Errors
+///
+/// Returns the invariant the program breaks.
+pub fn check(program: &Program) -> Result<(), Failure> {
+  let source_code = program.to_string();
+  let expected = run_checked(&source_code, RunOptions::default())?;
+  if let Err(codes) = &expected.result
+    && codes
+      .iter()
+      .any(|code| code.starts_with("E02") || code.starts_with("E03"))
+  {
+    return Err(Failure::new("it is not well-formed", format!("{codes:?}")));
+  }
+  if expected.ran_out_of_fuel() {
+    return Err(Failure::new(
+      "it does not terminate",
+      format!("{:?}", expected.output),
+    ));
+  }
+  let again = run_checked(&source_code, RunOptions::default())?;
+  if again != expected {
+    let details = format!("{again:?} after {expected:?}");
+    return Err(Failure::new("its output is not deterministic", details));
+  }
+  let vm = run_checked(
+    &source_code,
+    RunOptions::default().with_backend(Backend::Vm),
+  )?;
+  if vm != expected {
+    let details = format!("{vm:?} instead of {expected:?}");
+    return Err(Failure::new("the VM differs", details));
+  }
+  let optimized = run_checked(&source_code, RunOptions::default().with_opt_level(1))?;
+  if optimized != expected {
+    let details = format!("{optimized:?} instead of {expected:?}");
+    return Err(Failure::new("optimising changes its output", details));
+  }
+  for rewrite in &REWRITES {
+    let rewritten = (rewrite.apply)(program).to_string();
+    let actual = run_checked(&rewritten, RunOptions::default())?;
+    if actual != expected {
+      let details = format!("{actual:?} instead of {expected:?}");
+      return Err(Failure::new(rewrite.name, details));
+    }
+  }
+  Ok(())
+}
+
+/// Checks the program generated from `seed`, panicking with the smallest
+/// program that still breaks the same invariant when it breaks one.
+pub fn check_seed(seed: u64) {
+  let program = Generator::new(seed).program();
+  let Err(failure) = check(&program) else {
+    return;
+  };
+  let breaks_it = |program: &Program| {
+    rlox::check_source_code("main.lox", &program.to_string()).is_ok()
+      && check(program).is_err_and(|found| found.invariant == failure.invariant)
+  };
+  let reduced = shrink(program, breaks_it);
+  let failure = check(&reduced).err().unwrap_or(failure);
+  panic!("program of seed {seed} fails, {failure}:\n{reduced}");
+}
@@ -0,0 +1,148 @@
+use std::fmt;
+
+/// A generated program, in the shape of the `Stmt`/`Expr` grammar of
+/// the parser, printed as Lox source code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+  pub stmts: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+  Print(Expr),
+  Expr(Expr),
+  Var(String, Expr),
+  Block(Vec<Stmt>),
+  If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+  While(Expr, Box<Stmt>),
+  For {
+    init: Box<Stmt>,
+    condition: Expr,
+    increment: Expr,
+    body: Box<Stmt>,
+  },
+  Fun(String, Vec<String>, Vec<Stmt>),
+  Return(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+  /// A number, string, boolean or `nil`, as written in the source.
+  Literal(String),
+  Variable(String),
+  Assign(String, Box<Expr>),
+  Unary(&'static str, Box<Expr>),
+  Binary(Box<Expr>, &'static str, Box<Expr>),
+  Logical(Box<Expr>, &'static str, Box<Expr>),
+  Grouping(Box<Expr>),
+  Call(String, Vec<Expr>),
+}
+
+impl Stmt {
+  /// Whether the statement declares a name in its block.
+  pub fn is_declaration(&self) -> bool {
+    matches!(self, Stmt::Var(..) | Stmt::Fun(..))
+  }
+}
+
+impl fmt::Display for Program {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for stmt in &self.stmts {
+      write_stmt(f, stmt, 0)?;
+    }
+    Ok(())
+  }
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, stmts: &[Stmt], indent: usize) -> fmt::Result {
+  writeln!(f, "{{")?;
+  for stmt in stmts {
+    write_stmt(f, stmt, indent + 1)?;
+  }
+  write!(f, "{:width$}}}", "", width = indent * 2)
+}
+
+/// Writes a statement that follows a header such as `if (...)`, on the
+/// same line when it is a block.
+fn write_body(f: &mut fmt::Formatter<'_>, stmt: &Stmt, indent: usize) -> fmt::Result {
+  match stmt {
+    Stmt::Block(stmts) => {
+      write!(f, " ")?;
+      write_block(f, stmts, indent)?;
+      writeln!(f)
+    }
+    stmt => {
+      writeln!(f)?;
+      write_stmt(f, stmt, indent + 1)
+    }
+  }
+}
+
+fn write_stmt(f: &mut fmt::Formatter<'_>, stmt: &Stmt, indent: usize) -> fmt::Result {
+  write!(f, "{:width$}", "", width = indent * 2)?;
+  match stmt {
+    Stmt::Print(expr) => writeln!(f, "print {expr};"),
+    Stmt::Expr(expr) => writeln!(f, "{expr};"),
+    Stmt::Var(name, expr) => writeln!(f, "var {name} = {expr};"),
+    Stmt::Block(stmts) => {
+      write_block(f, stmts, indent)?;
+      writeln!(f)
+    }
+    Stmt::If(condition, then_stmt, else_stmt) => {
+      write!(f, "if ({condition})")?;
+      write_body(f, then_stmt, indent)?;
+      if let Some(else_stmt) = else_stmt {
+        write!(f, "{:width$}else", "", width = indent * 2)?;
+        write_body(f, else_stmt, indent)?;
+      }
+      Ok(())
+    }
+    Stmt::While(condition, body) => {
+      write!(f, "while ({condition})")?;
+      write_body(f, body, indent)
+    }
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body,
+    } => {
+      let Stmt::Var(name, expr) = init.as_ref() else {
+        panic!("expected a for loop to declare its counter");
+      };
+      write!(f, "for (var {name} = {expr}; {condition}; {increment})")?;
+      write_body(f, body, indent)
+    }
+    Stmt::Fun(name, params, body) => {
+      write!(f, "fun {name}({}) ", params.join(", "))?;
+      write_block(f, body, indent)?;
+      writeln!(f)
+    }
+    Stmt::Return(expr) => writeln!(f, "return {expr};"),
+  }
+}
+
+impl fmt::Display for Expr {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Expr::Literal(literal) => write!(f, "{literal}"),
+      Expr::Variable(name) => write!(f, "{name}"),
+      Expr::Assign(name, expr) => write!(f, "{name} = {expr}"),
+      Expr::Unary(op, right) => write!(f, "{op}{right}"),
+      Expr::Binary(left, op, right) | Expr::Logical(left, op, right) => {
+        write!(f, "{left} {op} {right}")
+      }
+      Expr::Grouping(expr) => write!(f, "({expr})"),
+      Expr::Call(name, args) => {
+        write!(f, "{name}(")?;
+        for (i, arg) in args.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write!(f, "{arg}")?;
+        }
+        write!(f, ")")
+      }
+    }
+  }
+}
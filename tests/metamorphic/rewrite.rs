@@ -0,0 +1,158 @@
+use super::program::{Expr, Program, Stmt};
+
+/// A rewrite of a program that must not change what it prints.
+pub struct Rewrite {
+  /// The invariant the rewrite checks.
+  pub name: &'static str,
+  pub apply: fn(&Program) -> Program,
+}
+
+pub const REWRITES: [Rewrite; 3] = [
+  Rewrite {
+    name: "wrapping statements in blocks changes its output",
+    apply: wrap_in_blocks,
+  },
+  Rewrite {
+    name: "converting for loops to while loops changes its output",
+    apply: for_to_while,
+  },
+  Rewrite {
+    name: "renaming locals changes its output",
+    apply: rename_locals,
+  },
+];
+
+/// Applies `f` to every statement of the program, innermost first,
+/// each returning the statements that replace it.
+fn map_stmts(stmts: &[Stmt], f: &impl Fn(Stmt) -> Vec<Stmt>) -> Vec<Stmt> {
+  stmts.iter().flat_map(|stmt| f(map_stmt(stmt, f))).collect()
+}
+
+fn map_body(stmt: &Stmt, f: &impl Fn(Stmt) -> Vec<Stmt>) -> Box<Stmt> {
+  let mut stmts = f(map_stmt(stmt, f));
+  match stmts.len() {
+    1 => Box::new(stmts.pop().unwrap()),
+    _ => Box::new(Stmt::Block(stmts)),
+  }
+}
+
+fn map_stmt(stmt: &Stmt, f: &impl Fn(Stmt) -> Vec<Stmt>) -> Stmt {
+  match stmt {
+    Stmt::Block(stmts) => Stmt::Block(map_stmts(stmts, f)),
+    Stmt::If(condition, then_stmt, else_stmt) => Stmt::If(
+      condition.clone(),
+      map_body(then_stmt, f),
+      else_stmt.as_ref().map(|stmt| map_body(stmt, f)),
+    ),
+    Stmt::While(condition, body) => Stmt::While(condition.clone(), map_body(body, f)),
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body,
+    } => Stmt::For {
+      init: init.clone(),
+      condition: condition.clone(),
+      increment: increment.clone(),
+      body: map_body(body, f),
+    },
+    Stmt::Fun(name, params, body) => Stmt::Fun(name.clone(), params.clone(), map_stmts(body, f)),
+    stmt => stmt.clone(),
+  }
+}
+
+/// Wraps every statement that declares nothing in a block of its own.
+pub fn wrap_in_blocks(program: &Program) -> Program {
+  let wrap = |stmt: Stmt| match stmt.is_declaration() {
+    true => vec![stmt],
+    false => vec![Stmt::Block(vec![stmt])],
+  };
+  Program {
+    stmts: map_stmts(&program.stmts, &wrap),
+  }
+}
+
+/// Replaces every `for` loop with the `while` loop the parser desugars
+/// it to.
+pub fn for_to_while(program: &Program) -> Program {
+  let desugar = |stmt: Stmt| match stmt {
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body,
+    } => {
+      let body = Stmt::Block(vec![*body, Stmt::Expr(increment)]);
+      vec![Stmt::Block(vec![
+        *init,
+        Stmt::While(condition, Box::new(body)),
+      ])]
+    }
+    stmt => vec![stmt],
+  };
+  Program {
+    stmts: map_stmts(&program.stmts, &desugar),
+  }
+}
+
+/// Renames every variable and parameter. Functions keep their names,
+/// which show when they are printed.
+pub fn rename_locals(program: &Program) -> Program {
+  Program {
+    stmts: program.stmts.iter().map(rename_stmt).collect(),
+  }
+}
+
+fn rename(name: &str) -> String {
+  match name.starts_with('f') {
+    true => name.to_string(),
+    false => format!("renamed_{name}"),
+  }
+}
+
+fn rename_stmt(stmt: &Stmt) -> Stmt {
+  let body = |stmt: &Stmt| Box::new(rename_stmt(stmt));
+  match stmt {
+    Stmt::Print(expr) => Stmt::Print(rename_expr(expr)),
+    Stmt::Expr(expr) => Stmt::Expr(rename_expr(expr)),
+    Stmt::Var(name, expr) => Stmt::Var(rename(name), rename_expr(expr)),
+    Stmt::Block(stmts) => Stmt::Block(stmts.iter().map(rename_stmt).collect()),
+    Stmt::If(condition, then_stmt, else_stmt) => Stmt::If(
+      rename_expr(condition),
+      body(then_stmt),
+      else_stmt.as_deref().map(body),
+    ),
+    Stmt::While(condition, stmt) => Stmt::While(rename_expr(condition), body(stmt)),
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body: stmt,
+    } => Stmt::For {
+      init: body(init),
+      condition: rename_expr(condition),
+      increment: rename_expr(increment),
+      body: body(stmt),
+    },
+    Stmt::Fun(name, params, stmts) => Stmt::Fun(
+      name.clone(),
+      params.iter().map(|param| rename(param)).collect(),
+      stmts.iter().map(rename_stmt).collect(),
+    ),
+    Stmt::Return(expr) => Stmt::Return(rename_expr(expr)),
+  }
+}
+
+fn rename_expr(expr: &Expr) -> Expr {
+  let sub = |expr: &Expr| Box::new(rename_expr(expr));
+  match expr {
+    Expr::Literal(literal) => Expr::Literal(literal.clone()),
+    Expr::Variable(name) => Expr::Variable(rename(name)),
+    Expr::Assign(name, expr) => Expr::Assign(rename(name), sub(expr)),
+    Expr::Unary(op, right) => Expr::Unary(op, sub(right)),
+    Expr::Binary(left, op, right) => Expr::Binary(sub(left), op, sub(right)),
+    Expr::Logical(left, op, right) => Expr::Logical(sub(left), op, sub(right)),
+    Expr::Grouping(expr) => Expr::Grouping(sub(expr)),
+    Expr::Call(name, args) => Expr::Call(name.clone(), args.iter().map(rename_expr).collect()),
+  }
+}
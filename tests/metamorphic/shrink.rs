@@ -0,0 +1,248 @@
+use super::program::{Expr, Program, Stmt};
+
+/// Shrinks a program for which `fails` holds to a smaller one for which
+/// it still does, one removal or simplification at a time, until none
+/// of them keeps it failing.
+pub fn shrink(mut program: Program, fails: impl Fn(&Program) -> bool) -> Program {
+  'shrinking: loop {
+    for stmts in stmts_candidates(&program.stmts) {
+      let candidate = Program { stmts };
+      if fails(&candidate) {
+        program = candidate;
+        continue 'shrinking;
+      }
+    }
+    return program;
+  }
+}
+
+/// The lists of statements one step simpler than `stmts`.
+fn stmts_candidates(stmts: &[Stmt]) -> Vec<Vec<Stmt>> {
+  let mut candidates = Vec::new();
+  for i in 0..stmts.len() {
+    if is_increment(&stmts[i]) {
+      continue;
+    }
+    let mut removed = stmts.to_vec();
+    removed.remove(i);
+    candidates.push(removed);
+  }
+  // A declaration goes along with the statements that use it, which
+  // could not stay without it.
+  for (i, stmt) in stmts.iter().enumerate() {
+    let (Stmt::Var(name, _) | Stmt::Fun(name, ..)) = stmt else {
+      continue;
+    };
+    let rest = stmts[i + 1..]
+      .iter()
+      .filter(|stmt| !stmt_mentions(stmt, name));
+    candidates.push(stmts[..i].iter().chain(rest).cloned().collect());
+  }
+  for (i, stmt) in stmts.iter().enumerate() {
+    for replacement in stmt_candidates(stmt) {
+      let mut replaced = stmts[..i].to_vec();
+      replaced.extend(replacement);
+      replaced.extend_from_slice(&stmts[i + 1..]);
+      candidates.push(replaced);
+    }
+  }
+  candidates
+}
+
+/// Whether the statement counts a `while` loop up, which stays so that
+/// the loop keeps terminating. The generator names counters `i…`.
+fn is_increment(stmt: &Stmt) -> bool {
+  matches!(stmt, Stmt::Expr(Expr::Assign(name, _)) if name.starts_with('i'))
+}
+
+/// The statements that could replace `stmt`, each one step simpler.
+fn stmt_candidates(stmt: &Stmt) -> Vec<Vec<Stmt>> {
+  let single = |stmt| vec![stmt];
+  match stmt {
+    Stmt::Print(expr) => expr_candidates(expr)
+      .into_iter()
+      .map(|expr| single(Stmt::Print(expr)))
+      .collect(),
+    stmt if is_increment(stmt) => Vec::new(),
+    Stmt::Expr(expr) => expr_candidates(expr)
+      .into_iter()
+      .map(|expr| single(Stmt::Expr(expr)))
+      .collect(),
+    Stmt::Var(name, expr) => expr_candidates(expr)
+      .into_iter()
+      .map(|expr| single(Stmt::Var(name.clone(), expr)))
+      .collect(),
+    Stmt::Return(expr) => expr_candidates(expr)
+      .into_iter()
+      .map(|expr| single(Stmt::Return(expr)))
+      .collect(),
+    Stmt::Block(stmts) => {
+      let mut candidates = vec![stmts.clone()];
+      candidates.extend(
+        stmts_candidates(stmts)
+          .into_iter()
+          .map(|stmts| single(Stmt::Block(stmts))),
+      );
+      candidates
+    }
+    Stmt::If(condition, then_stmt, else_stmt) => {
+      let mut candidates = vec![single(*then_stmt.clone())];
+      if let Some(else_stmt) = else_stmt {
+        candidates.push(single(*else_stmt.clone()));
+        candidates.push(single(Stmt::If(condition.clone(), then_stmt.clone(), None)));
+        for stmt in body_candidates(else_stmt) {
+          let if_stmt = Stmt::If(condition.clone(), then_stmt.clone(), Some(Box::new(stmt)));
+          candidates.push(single(if_stmt));
+        }
+      }
+      for stmt in body_candidates(then_stmt) {
+        let if_stmt = Stmt::If(condition.clone(), Box::new(stmt), else_stmt.clone());
+        candidates.push(single(if_stmt));
+      }
+      for condition in expr_candidates(condition) {
+        let if_stmt = Stmt::If(condition, then_stmt.clone(), else_stmt.clone());
+        candidates.push(single(if_stmt));
+      }
+      candidates
+    }
+    Stmt::While(condition, body) => {
+      let mut candidates = vec![single(*body.clone())];
+      for body in body_candidates(body) {
+        candidates.push(single(Stmt::While(condition.clone(), Box::new(body))));
+      }
+      candidates
+    }
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body,
+    } => {
+      let mut candidates = vec![vec![*init.clone(), *body.clone()]];
+      for body in body_candidates(body) {
+        candidates.push(single(Stmt::For {
+          init: init.clone(),
+          condition: condition.clone(),
+          increment: increment.clone(),
+          body: Box::new(body),
+        }));
+      }
+      candidates
+    }
+    Stmt::Fun(name, params, body) => stmts_candidates(body)
+      .into_iter()
+      .map(|body| single(Stmt::Fun(name.clone(), params.clone(), body)))
+      .collect(),
+  }
+}
+
+/// The single statements one step simpler than the body of a compound
+/// statement.
+fn body_candidates(body: &Stmt) -> Vec<Stmt> {
+  stmt_candidates(body)
+    .into_iter()
+    .filter_map(|mut stmts| match stmts.len() {
+      1 => stmts.pop(),
+      _ => None,
+    })
+    .collect()
+}
+
+/// The expressions one step simpler than `expr`: a literal, one of its
+/// operands, or itself with a simpler operand.
+fn expr_candidates(expr: &Expr) -> Vec<Expr> {
+  let mut candidates = Vec::new();
+  if !matches!(expr, Expr::Literal(_)) {
+    for literal in ["nil", "0", "\"\"", "true"] {
+      candidates.push(Expr::Literal(literal.to_string()));
+    }
+  }
+  let sub = |expr: Expr| Box::new(expr);
+  match expr {
+    Expr::Literal(_) | Expr::Variable(_) => {}
+    Expr::Assign(name, expr) => {
+      candidates.push(*expr.clone());
+      for expr in expr_candidates(expr) {
+        candidates.push(Expr::Assign(name.clone(), sub(expr)));
+      }
+    }
+    Expr::Unary(op, right) => {
+      candidates.push(*right.clone());
+      for right in expr_candidates(right) {
+        candidates.push(Expr::Unary(op, sub(right)));
+      }
+    }
+    Expr::Binary(left, op, right) | Expr::Logical(left, op, right) => {
+      let rebuild = |left: Expr, right: Expr| match expr {
+        Expr::Binary(..) => Expr::Binary(sub(left), op, sub(right)),
+        _ => Expr::Logical(sub(left), op, sub(right)),
+      };
+      candidates.push(*left.clone());
+      candidates.push(*right.clone());
+      for left in expr_candidates(left) {
+        candidates.push(rebuild(left, *right.clone()));
+      }
+      for right in expr_candidates(right) {
+        candidates.push(rebuild(*left.clone(), right));
+      }
+    }
+    Expr::Grouping(expr) => {
+      candidates.push(*expr.clone());
+      for expr in expr_candidates(expr) {
+        candidates.push(Expr::Grouping(sub(expr)));
+      }
+    }
+    Expr::Call(name, args) => {
+      candidates.extend(args.iter().cloned());
+      for (i, arg) in args.iter().enumerate() {
+        for arg in expr_candidates(arg) {
+          let mut args = args.clone();
+          args[i] = arg;
+          candidates.push(Expr::Call(name.clone(), args));
+        }
+      }
+    }
+  }
+  candidates
+}
+
+fn stmt_mentions(stmt: &Stmt, name: &str) -> bool {
+  let any = |stmts: &[Stmt]| stmts.iter().any(|stmt| stmt_mentions(stmt, name));
+  match stmt {
+    Stmt::Print(expr) | Stmt::Expr(expr) | Stmt::Return(expr) => expr_mentions(expr, name),
+    Stmt::Var(_, expr) => expr_mentions(expr, name),
+    Stmt::Block(stmts) | Stmt::Fun(_, _, stmts) => any(stmts),
+    Stmt::If(condition, then_stmt, else_stmt) => {
+      expr_mentions(condition, name)
+        || stmt_mentions(then_stmt, name)
+        || else_stmt
+          .as_ref()
+          .is_some_and(|stmt| stmt_mentions(stmt, name))
+    }
+    Stmt::While(condition, body) => expr_mentions(condition, name) || stmt_mentions(body, name),
+    Stmt::For {
+      init,
+      condition,
+      increment,
+      body,
+    } => {
+      stmt_mentions(init, name)
+        || expr_mentions(condition, name)
+        || expr_mentions(increment, name)
+        || stmt_mentions(body, name)
+    }
+  }
+}
+
+fn expr_mentions(expr: &Expr, name: &str) -> bool {
+  match expr {
+    Expr::Literal(_) => false,
+    Expr::Variable(variable) => variable == name,
+    Expr::Assign(variable, expr) => variable == name || expr_mentions(expr, name),
+    Expr::Unary(_, expr) | Expr::Grouping(expr) => expr_mentions(expr, name),
+    Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+      expr_mentions(left, name) || expr_mentions(right, name)
+    }
+    Expr::Call(callee, args) => callee == name || args.iter().any(|arg| expr_mentions(arg, name)),
+  }
+}
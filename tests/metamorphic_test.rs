@@ -0,0 +1,47 @@
+mod common;
+mod metamorphic;
+
+use metamorphic::{Generator, Program, check_seed, run, shrink};
+use rlox::RunOptions;
+
+/// How many programs to check, more with `RLOX_METAMORPHIC_SEEDS`.
+fn seeds() -> u64 {
+  std::env::var("RLOX_METAMORPHIC_SEEDS")
+    .ok()
+    .and_then(|seeds| seeds.parse().ok())
+    .unwrap_or(100)
+}
+
+#[test]
+fn generated_programs_keep_their_invariants() {
+  for seed in 0..seeds() {
+    check_seed(seed);
+  }
+}
+
+#[test]
+fn generated_programs_are_well_formed() {
+  for seed in 0..50 {
+    let source_code = Generator::new(seed).program().to_string();
+    if let Err(errs) = rlox::check_source_code("main.lox", &source_code) {
+      let codes: Vec<&str> = errs.iter().map(|err| err.code()).collect();
+      panic!("program of seed {seed} fails with {codes:?}:\n{source_code}");
+    }
+  }
+}
+
+fn prints_ab(program: &Program) -> bool {
+  run(&program.to_string(), RunOptions::default())
+    .is_ok_and(|outcome| outcome.result.is_ok() && outcome.output.lines().any(|line| line == "ab"))
+}
+
+#[test]
+fn shrinks_programs_to_small_reproducers() {
+  let program = (0..)
+    .map(|seed| Generator::new(seed).program())
+    .find(|program| program.stmts.len() > 3 && prints_ab(program))
+    .unwrap();
+  let reduced = shrink(program, prints_ab);
+  assert!(prints_ab(&reduced));
+  assert_eq!(reduced.stmts.len(), 1, "{reduced}");
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::run_with_args;
+use rlox::{Completion, Limits, RuntimeError, RuntimeErrorKind};
+
+fn run(source_code: &str, script_args: &[&str]) -> (String, Completion) {
+  let script_args = script_args.iter().map(|a| a.to_string()).collect();
+  let (out, result) = run_with_args(source_code, script_args, Limits::default());
+  (out.trim().to_string(), result.unwrap())
+}
+
+#[test]
+fn args_returns_script_arguments() {
+  let (out, _) = run("print args();", &["a", "b c"]);
+  assert_eq!(out, "[a, b c]");
+}
+
+#[test]
+fn env_reads_environment_variables() {
+  let source_code = r#"
+    print env("CARGO_PKG_NAME");
+    print env("RLOX_SURELY_NOT_SET");
+  "#;
+  let (out, _) = run(source_code, &[]);
+  assert_eq!(out, "rlox\nnil");
+}
+
+#[test]
+fn exit_unwinds_from_nested_calls() {
+  let source_code = r#"
+    fun stop() {
+      {
+        exit(3);
+      }
+      print "unreachable";
+    }
+    print "before";
+    stop();
+    print "after";
+  "#;
+  let (out, completion) = run(source_code, &[]);
+  assert_eq!(out, "before");
+  assert_eq!(completion, Completion::Exit(3));
+}
+
+#[test]
+fn exit_is_not_mistaken_for_an_interrupt() {
+  let exit = RuntimeError::from(RuntimeErrorKind::Exit(3));
+  assert_eq!(exit.exit_code(), Some(3));
+  assert_eq!(exit.code(), "E0412");
+  let interrupt = RuntimeError::from(RuntimeErrorKind::Interrupted);
+  assert_eq!(interrupt.exit_code(), None);
+}
+
+#[test]
+fn finishing_normally_completes() {
+  let (_, completion) = run("print 1;", &[]);
+  assert_eq!(completion, Completion::Finished);
+}
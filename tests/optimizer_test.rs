@@ -0,0 +1,46 @@
+mod common;
+
+use common::run_with_options as run;
+use rlox::{Backend, Limits, RunOptions};
+
+#[test]
+fn optimising_keeps_output_and_errors() {
+  let source_code = r#"
+    var day = 60 * 60 * 24;
+    if (day > 1000 and !false) print "long " + "day"; else print "short";
+    while (nil or 0) print "never";
+    fun f(n) { if ("" + "") return -1; return n / (1 + 1); }
+    print f(day) == 43200;
+    print (1 + 2) * "a";
+  "#;
+  for backend in [Backend::TreeWalker, Backend::Vm] {
+    let options = RunOptions::default().with_backend(backend);
+    let (plain_out, plain) = run(source_code, Limits::default(), options.with_opt_level(0));
+    let (out, result) = run(source_code, Limits::default(), options.with_opt_level(1));
+    assert_eq!(out, "long day\ntrue\n");
+    assert_eq!(out, plain_out);
+    let err = &result.unwrap_err()[0];
+    let plain_err = &plain.unwrap_err()[0];
+    assert_eq!(err.code(), "E0401");
+    assert_eq!(err.render(source_code), plain_err.render(source_code));
+  }
+}
+
+#[test]
+fn optimising_saves_fuel() {
+  let source_code = "
+    var total = 0;
+    for (var i = 0; i < 10; i = i + 1) {
+      if (false) print i;
+      total = total + 60 * 60 * 24;
+    }
+    print total;
+  ";
+  let limits = Limits::default().with_fuel(200);
+  let options = RunOptions::default().with_opt_level(1);
+  let (out, result) = run(source_code, limits.clone(), options);
+  assert!(result.is_ok());
+  assert_eq!(out, "864000\n");
+  let (_, result) = run(source_code, limits, RunOptions::default());
+  assert_eq!(result.unwrap_err()[0].code(), "E0408");
+}
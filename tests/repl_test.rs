@@ -0,0 +1,74 @@
+use rlox::Repl;
+
+fn run_repl(input: &str) -> (String, String) {
+  let mut out_buf = Vec::new();
+  let mut err_buf = Vec::new();
+  {
+    let mut repl = Repl::new(Box::new(&mut out_buf), Box::new(&mut err_buf));
+    repl.run(input.as_bytes()).unwrap();
+  }
+  (
+    String::from_utf8(out_buf).unwrap(),
+    String::from_utf8(err_buf).unwrap(),
+  )
+}
+
+#[test]
+fn prints_bare_expression_values() {
+  let (out, err) = run_repl("var a = 20;\na + 1;\n");
+  assert_eq!(out, "> > 21\n> \n");
+  assert_eq!(err, "");
+}
+
+#[test]
+fn reads_multi_line_input() {
+  let (out, _) = run_repl("fun add(a, b) {\n  return a + b;\n}\nadd(1, 2);\n");
+  assert_eq!(out, "> ... ... > 3\n> \n");
+}
+
+#[test]
+fn keeps_state_after_errors() {
+  let (out, err) = run_repl("var a = 1;\nprint b;\n-\"x\";\na;\n");
+  assert!(out.ends_with("> 1\n> \n"));
+  assert_eq!(err.matches("error[").count(), 2);
+}
+
+#[test]
+fn points_errors_at_the_snippet_they_come_from() {
+  let (_, err) = run_repl("fun f(x) {\n  return x + nil;\n}\n\nf(1);\n");
+  assert!(err.contains("--> <repl:1>:2:12\n"));
+  assert!(err.contains("return x + nil;"));
+}
+
+#[test]
+fn allows_redeclaring_globals() {
+  let (out, err) = run_repl("var a = 1;\nvar a = 2;\na;\n");
+  assert!(out.contains("2\n"));
+  assert_eq!(err, "");
+}
+
+#[test]
+fn lists_and_resets_globals() {
+  let (out, _) = run_repl("var b = \"x\";\nvar a = 1;\n:env\n:reset\n:env\n");
+  assert!(out.contains("a = 1\nb = x\n"));
+  assert!(out.ends_with("Session reset.\n> > \n"));
+}
+
+#[test]
+fn reports_unknown_commands() {
+  let (_, err) = run_repl(":nope\n");
+  assert!(err.contains("unknown command ':nope'"));
+}
+
+#[test]
+fn stops_on_exit() {
+  let mut out_buf = Vec::new();
+  let mut err_buf = Vec::new();
+  let mut repl = Repl::new(Box::new(&mut out_buf), Box::new(&mut err_buf));
+  repl
+    .run("print 1;\nexit(2);\nprint 3;\n".as_bytes())
+    .unwrap();
+  assert_eq!(repl.exit_code(), Some(2));
+  drop(repl);
+  assert_eq!(String::from_utf8(out_buf).unwrap(), "> 1\n> ");
+}
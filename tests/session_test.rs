@@ -0,0 +1,120 @@
+use rlox::{Session, Value};
+
+fn run_snippets(snippets: &[&str]) -> (String, Vec<bool>) {
+  let mut out_buf = Vec::new();
+  let mut results = Vec::new();
+  {
+    let mut session = Session::new();
+    session.set_out_writer(Box::new(&mut out_buf));
+    for snippet in snippets {
+      results.push(session.run(snippet).is_ok());
+    }
+  }
+  (
+    String::from_utf8(out_buf).unwrap().trim().to_string(),
+    results,
+  )
+}
+
+#[test]
+fn globals_persist_between_runs() {
+  let (out, results) = run_snippets(&["var a = 1;", "a = a + 1;", "print a;"]);
+  assert_eq!(results, vec![true, true, true]);
+  assert_eq!(out, "2");
+}
+
+#[test]
+fn functions_persist_between_runs() {
+  let (out, results) = run_snippets(&[
+    "var greeting = \"Hello\";",
+    "fun greet(name) { return greeting + \", \" + name; }",
+    "print greet(\"World\");",
+  ]);
+  assert_eq!(results, vec![true, true, true]);
+  assert_eq!(out, "Hello, World");
+}
+
+#[test]
+fn recovers_from_static_errors() {
+  let (out, results) = run_snippets(&["var a = 1;", "var b = 2; print c;", "print b;", "print a;"]);
+  assert_eq!(results, vec![true, false, false, true]);
+  assert_eq!(out, "1");
+}
+
+#[test]
+fn recovers_from_runtime_errors() {
+  let (out, results) = run_snippets(&[
+    "fun fail() { { var x = -\"a\"; print x; } }",
+    "var a = fail();",
+    "print a;",
+  ]);
+  assert_eq!(results, vec![true, false, true]);
+  assert_eq!(out, "nil");
+}
+
+#[test]
+fn snippets_keep_their_own_names_and_sources() {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  session.run_named("setup.lox", "var a = 1;").unwrap();
+  let errs = session.run_named("<repl>", "\nprint a + nil;").unwrap_err();
+  let rendered = session.sources().render(&errs[0]);
+  assert!(rendered.contains("--> <repl>:2:9\n"));
+  assert!(rendered.contains("2 | print a + nil;\n"));
+}
+
+#[test]
+fn runs_again_after_an_error_inside_a_block() {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  session
+    .run("var depth = 0; { var inner = 1; { print inner - nil; } }")
+    .unwrap_err();
+  session.run("var after = depth + 1;").unwrap();
+  let names: Vec<String> = session
+    .globals()
+    .into_iter()
+    .map(|(name, _)| name)
+    .collect();
+  assert_eq!(names, vec!["after", "depth"]);
+}
+
+#[test]
+fn runs_again_after_an_error_inside_a_function() {
+  let (out, results) = run_snippets(&[
+    "fun f(n) { var local = n; if (n > 2) return local - nil; return f(n + 1); }",
+    "f(0);",
+    "var local = \"global\";",
+    "print local;",
+    "print f(2) == nil;",
+    "print local;",
+  ]);
+  assert_eq!(results, vec![true, false, true, true, false, true]);
+  assert_eq!(out, "global\nglobal");
+}
+
+#[test]
+fn redeclared_globals_are_seen_by_functions() {
+  let (out, results) = run_snippets(&[
+    "var a = 1; var b = 2;",
+    "fun show() { print a + b; }",
+    "var a = 10; show();",
+    "var c = -nil; var d = 3;",
+    "print d; show();",
+  ]);
+  assert_eq!(results, vec![true, true, true, false, true]);
+  assert_eq!(out, "12\nnil\n12");
+}
+
+#[test]
+fn returns_the_value_of_a_trailing_expression() {
+  let mut session = Session::new();
+  session.set_out_writer(Box::new(std::io::sink()));
+  assert!(matches!(
+    session.run("var a = 2; a * 3;"),
+    Ok(Some(Value::Number(6.0)))
+  ));
+  assert!(matches!(session.run("a * 3; print a;"), Ok(None)));
+  assert!(matches!(session.run("exit(4);"), Ok(None)));
+  assert_eq!(session.exit_code(), Some(4));
+}
@@ -70,3 +70,11 @@ fn errors_on_unassigned_variable() {
   let source_code = "var a; print a;";
   run_and_capture_output(source_code);
 }
+
+#[test]
+fn errors_inside_blocks_do_not_leak_scopes() {
+  let source_code = "{ { print missing; } } var a = 1; print a;";
+  let errs = rlox::check_source_code("main.lox", source_code).unwrap_err();
+  assert_eq!(errs.len(), 1);
+  assert_eq!(errs[0].code(), "E0303");
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use common::{capture, render, run_with_options};
+use rlox::{Backend, Completion, Limits, LoxError, RunOptions};
+
+/// Runs the source code on a backend, returning what it printed and how
+/// it stopped, with errors rendered as they are for humans.
+fn run(
+  backend: Backend,
+  source_code: &str,
+  limits: Limits,
+) -> (String, Result<Completion, String>) {
+  let options = RunOptions::default().with_backend(backend);
+  let (out, result) = run_with_options(source_code, limits, options);
+  (out, render(source_code, result))
+}
+
+fn assert_same_on_both_backends(
+  source_code: &str,
+  limits: Limits,
+) -> (String, Result<Completion, String>) {
+  let tree = run(Backend::TreeWalker, source_code, limits.clone());
+  let vm = run(Backend::Vm, source_code, limits);
+  assert_eq!(tree, vm);
+  vm
+}
+
+#[test]
+fn closures_capture_variables() {
+  let source_code = "
+    var shared = 0;
+    fun make(i) {
+      var own = i;
+      fun get() { shared = shared + 1; return own + shared * 10; }
+      return get;
+    }
+    var first = make(1);
+    var second = make(2);
+    print first();
+    print second();
+    print first();
+  ";
+  let (out, result) = run(Backend::Vm, source_code, Limits::default());
+  assert_eq!(out, "11\n22\n31\n");
+  assert_eq!(result, Ok(Completion::Finished));
+}
+
+#[test]
+fn loop_bodies_get_fresh_variables_on_every_iteration() {
+  let source_code = "
+    var a = nil;
+    var b = nil;
+    for (var i = 0; i < 2; i = i + 1) {
+      var j = i;
+      fun get() { return j; }
+      if (i == 0) a = get; else b = get;
+    }
+    print a();
+    print b();
+  ";
+  let (out, _) = assert_same_on_both_backends(source_code, Limits::default());
+  assert_eq!(out, "0\n1\n");
+}
+
+#[test]
+fn runtime_errors_have_the_same_backtrace() {
+  let source_code = "
+    fun inner(x) { return x + nil; }
+    fun outer() { print \"before\"; print inner(1); }
+    outer();
+  ";
+  let (out, result) = assert_same_on_both_backends(source_code, Limits::default());
+  assert_eq!(out, "before\n");
+  let rendered = result.unwrap_err();
+  assert!(rendered.contains("in inner(), called at main.lox:3:"));
+  assert!(rendered.contains("in outer(), called at main.lox:4:"));
+}
+
+#[test]
+fn tail_calls_do_not_nest() {
+  let source_code = "
+    fun count(n, acc) { if (n == 0) return acc; return count(n - 1, acc + 1); }
+    print count(1000000, 0);
+
+    var is_odd;
+    fun is_even(n) { if (n == 0) return true; return is_odd(n - 1); }
+    fun odd(n) { if (n == 0) return false; return is_even(n - 1); }
+    is_odd = odd;
+    print is_even(100001);
+
+    fun zero() { return 0; }
+    fun sum(n, f) { fun g() { return f() + n; } if (n == 0) return f(); return sum(n - 1, g); }
+    print sum(100, zero);
+
+    fun now() { return clock(); }
+    print now() > 0;
+  ";
+  let (out, result) = assert_same_on_both_backends(source_code, Limits::default());
+  assert_eq!(out, "1000000\nfalse\n5050\ntrue\n");
+  assert_eq!(result, Ok(Completion::Finished));
+}
+
+#[test]
+fn tail_calls_take_the_frame_of_their_caller() {
+  let source_code = "
+    fun inner(x) { return x + nil; }
+    fun outer() { return inner(1); }
+    outer();
+  ";
+  let (_, result) = assert_same_on_both_backends(source_code, Limits::default());
+  let rendered = result.unwrap_err();
+  assert!(rendered.contains("in inner(), called at main.lox:4:"));
+  assert!(!rendered.contains("in outer()"));
+}
+
+#[test]
+fn limits_stop_programs_at_the_same_point() {
+  let source_code = "var i = 0; while (true) { print i; i = i + 1; }";
+  let (out, result) = assert_same_on_both_backends(source_code, Limits::default().with_fuel(100));
+  assert_eq!(out.lines().count(), 11);
+  assert!(result.unwrap_err().starts_with("error[E0408]"));
+
+  let source_code = "fun f(n) { print n; return 1 + f(n + 1); } f(0);";
+  let (_, result) =
+    assert_same_on_both_backends(source_code, Limits::default().with_max_call_depth(50));
+  assert!(result.unwrap_err().contains("... and 34 more calls"));
+
+  let source_code = "fun f(n) { print n; return f(n + 1); } f(0);";
+  let (out, result) = assert_same_on_both_backends(source_code, Limits::default().with_fuel(100));
+  assert_eq!(out.lines().count(), 11);
+  assert!(result.unwrap_err().starts_with("error[E0408]"));
+}
+
+#[test]
+fn exit_stops_the_program() {
+  let source_code = "fun f() { exit(7); print \"unreachable\"; } print 1; f();";
+  let (out, result) = assert_same_on_both_backends(source_code, Limits::default());
+  assert_eq!(out, "1\n");
+  assert_eq!(result, Ok(Completion::Exit(7)));
+}
+
+#[test]
+fn frees_cycles_between_closures_and_upvalues() {
+  let source_code = "
+    fun make() {
+      var s = \"ab\" + \"cd\";
+      fun inner() { return s + inner(); }
+      return inner;
+    }
+    for (var i = 0; i < 100; i = i + 1) { make(); }
+    print gc();
+    print gc();
+  ";
+  // The closure `inner` and the upvalues of `s` and `inner`.
+  let (out, _) = run(Backend::Vm, source_code, Limits::default());
+  assert_eq!(out, "300\n0\n");
+}
+
+#[test]
+fn runs_instructions_with_wide_operands() {
+  let params: Vec<String> = (0..300).map(|i| format!("p{i}")).collect();
+  let args: Vec<String> = (0..300).map(|i| format!("{i}.5")).collect();
+  let source_code = format!(
+    "fun f({}) {{ fun get() {{ return p299; }} return get() + {}; }} print f({});",
+    params.join(", "),
+    params.join(" + "),
+    args.join(", "),
+  );
+  let (out, result) = assert_same_on_both_backends(&source_code, Limits::default());
+  assert_eq!(out, "45299.5\n");
+  assert_eq!(result, Ok(Completion::Finished));
+}
+
+#[test]
+fn rejects_returns_outside_of_functions() {
+  let (out, result) = assert_same_on_both_backends("return 1; print 2;", Limits::default());
+  assert_eq!(out, "");
+  assert!(
+    result
+      .unwrap_err()
+      .contains("error[E0306]: cannot return outside of a function")
+  );
+}
+
+#[test]
+fn traces_to_the_given_writer() {
+  let mut trace = Vec::new();
+  let options = RunOptions::default()
+    .with_backend(Backend::Vm)
+    .with_trace(true);
+  let (out, result) = capture(|out| {
+    rlox::run_source_code_with_options(
+      "main.lox",
+      "print 1;",
+      Vec::new(),
+      Limits::default(),
+      options,
+      Some(out),
+      Some(Box::new(&mut trace)),
+    )
+  });
+  assert_eq!(out, "1\n");
+  assert!(matches!(result, Ok(Completion::Finished)));
+  let trace = String::from_utf8(trace).unwrap();
+  assert!(trace.contains("[ 1 ]\n0002    | OP_PRINT\n"));
+}
+
+/// A writer that always fails, like a closed pipe.
+struct Closed;
+
+impl std::io::Write for Closed {
+  fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+    Err(std::io::ErrorKind::BrokenPipe.into())
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn fails_when_the_bytecode_cannot_be_written() {
+  let options = RunOptions::default().with_dump_bytecode(true);
+  let (out, result) = capture(|out| {
+    rlox::run_source_code_with_options(
+      "main.lox",
+      "print 1;",
+      Vec::new(),
+      Limits::default(),
+      options,
+      Some(out),
+      Some(Box::new(Closed)),
+    )
+  });
+  assert_eq!(out, "");
+  assert!(matches!(result.unwrap_err()[0], LoxError::IoError(_)));
+}